@@ -0,0 +1,51 @@
+// Times running a script of many short lines, which mostly measures reading, parsing and
+// dispatching commands rather than the commands themselves. Run it with `cargo bench`
+use std::{
+    env, fs,
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+const LINES: usize = 20_000;
+const RUNS: usize = 30;
+
+fn main() {
+    let path = env::temp_dir().join(format!("shell-bench-{}.sh", std::process::id()));
+
+    let script: String = (0..LINES)
+        .map(|i| match i % 4 {
+            0 => format!("echo line {} of the script\n", i),
+            1 => "true && echo \"$HOME\" two three\n".to_owned(),
+            2 => ": a b c d e f g h\n".to_owned(),
+            _ => format!("x={}; echo $x\n", i),
+        })
+        .collect();
+
+    fs::write(&path, script).unwrap();
+
+    let mut times: Vec<Duration> = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+
+            let status = Command::new(env!("CARGO_BIN_EXE_shell"))
+                .arg(&path)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .unwrap();
+
+            assert!(status.success());
+            start.elapsed()
+        })
+        .collect();
+
+    fs::remove_file(&path).unwrap();
+    times.sort();
+
+    println!(
+        "{} lines: best {:.1?}, median {:.1?}",
+        LINES,
+        times[0],
+        times[RUNS / 2]
+    );
+}
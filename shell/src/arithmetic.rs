@@ -0,0 +1,162 @@
+use std::{
+    io::{BufRead, Write},
+    iter::Peekable,
+    str::Chars,
+};
+
+use super::{error::ShellError, Result, Shell};
+
+const PAREN_OPEN: char = '(';
+const PAREN_CLOSE: char = ')';
+const PROMPT: char = '$';
+
+impl<R: BufRead, W: Write, E: Write> Shell<R, W, E> {
+    // Evaluate the integer expression of a `$((expr))`, supporting `+ - * / %` with the usual
+    // precedence, parentheses and variables like `x` or `$x`. Unset or empty variables are 0
+    pub(super) fn evaluate_arithmetic(&self, expr: &str) -> Result<i64> {
+        let mut chars = expr.chars().peekable();
+        let value = self.parse_sum(expr, &mut chars)?;
+
+        Self::skip_whitespace(&mut chars);
+
+        // Anything left like in `1 2` isn't part of the expression
+        match chars.next() {
+            Some(_) => Err(ShellError::InvalidArithmetic(expr.trim().to_owned())),
+            None => Ok(value),
+        }
+    }
+
+    fn parse_sum(&self, expr: &str, chars: &mut Peekable<Chars>) -> Result<i64> {
+        let mut value = self.parse_product(expr, chars)?;
+
+        loop {
+            Self::skip_whitespace(chars);
+
+            match chars.next_if(|&c| c == '+' || c == '-') {
+                Some('+') => value = value.wrapping_add(self.parse_product(expr, chars)?),
+                Some(_) => value = value.wrapping_sub(self.parse_product(expr, chars)?),
+                None => return Ok(value),
+            }
+        }
+    }
+
+    fn parse_product(&self, expr: &str, chars: &mut Peekable<Chars>) -> Result<i64> {
+        let mut value = self.parse_unary(expr, chars)?;
+
+        loop {
+            Self::skip_whitespace(chars);
+
+            let Some(operator) = chars.next_if(|&c| c == '*' || c == '/' || c == '%') else {
+                return Ok(value);
+            };
+
+            let operand = self.parse_unary(expr, chars)?;
+
+            value = match operator {
+                '*' => value.wrapping_mul(operand),
+                _ if operand == 0 => {
+                    return Err(ShellError::DivisionByZero(expr.trim().to_owned()))
+                }
+                '/' => value.wrapping_div(operand),
+                _ => value.wrapping_rem(operand),
+            };
+        }
+    }
+
+    fn parse_unary(&self, expr: &str, chars: &mut Peekable<Chars>) -> Result<i64> {
+        Self::skip_whitespace(chars);
+
+        match chars.peek() {
+            Some('+') => {
+                chars.next();
+                self.parse_unary(expr, chars)
+            }
+            Some('-') => {
+                chars.next();
+                self.parse_unary(expr, chars).map(i64::wrapping_neg)
+            }
+            Some(&PAREN_OPEN) => {
+                chars.next();
+                let value = self.parse_sum(expr, chars)?;
+
+                Self::skip_whitespace(chars);
+
+                match chars.next() {
+                    Some(PAREN_CLOSE) => Ok(value),
+                    _ => Err(ShellError::InvalidArithmetic(expr.trim().to_owned())),
+                }
+            }
+            _ => self.parse_operand(expr, chars),
+        }
+    }
+
+    fn parse_operand(&self, expr: &str, chars: &mut Peekable<Chars>) -> Result<i64> {
+        // Variables may be written with or without `$`
+        let is_var = chars.next_if_eq(&PROMPT).is_some();
+
+        let mut operand = String::new();
+
+        while let Some(c) = chars.next_if(|&c| c.is_ascii_alphanumeric() || c == '_') {
+            operand.push(c);
+        }
+
+        let value = if operand.is_empty() {
+            // An operator or the end of the expression where an operand belongs like in `1 +`
+            None
+        } else if is_var || Self::is_name(&operand) {
+            // Unset or empty variables are 0
+            match self.variables.get(&operand).unwrap_or_default().trim() {
+                "" => Some(0),
+                value => value.parse().ok(),
+            }
+        } else {
+            operand.parse().ok()
+        };
+
+        value.ok_or_else(|| ShellError::InvalidArithmetic(expr.trim().to_owned()))
+    }
+
+    fn skip_whitespace(chars: &mut Peekable<Chars>) {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluates_with_precedence() {
+        let mut shell = Shell::for_test();
+
+        assert_eq!(shell.output("echo $((2 + 3 * 4))"), (0, "14\n".to_owned()));
+        assert_eq!(shell.evaluate_arithmetic("(2 + 3) * 4").unwrap(), 20);
+        assert_eq!(shell.evaluate_arithmetic("17 % 5 - 10 / 3").unwrap(), -1);
+        assert_eq!(shell.evaluate_arithmetic("-(2 - 5) * -2").unwrap(), -6);
+    }
+
+    #[test]
+    fn evaluates_variables() {
+        let mut shell = Shell::for_test();
+        shell.variables.set("x", "6");
+
+        assert_eq!(shell.evaluate_arithmetic("x * 2").unwrap(), 12);
+        assert_eq!(shell.evaluate_arithmetic("$x + 1").unwrap(), 7);
+        // Unset variables are 0
+        assert_eq!(shell.evaluate_arithmetic("unset + 1").unwrap(), 1);
+    }
+
+    #[test]
+    fn fails_on_division_by_zero() {
+        let mut shell = Shell::for_test();
+
+        assert!(matches!(
+            shell.evaluate_arithmetic("1 / 0"),
+            Err(ShellError::DivisionByZero(expr)) if expr == "1 / 0"
+        ));
+        assert!(shell.evaluate_arithmetic("1 % (2 - 2)").is_err());
+
+        let (status, _) = shell.output("echo $((1 / 0))");
+        assert_ne!(status, 0);
+    }
+}
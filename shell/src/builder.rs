@@ -0,0 +1,167 @@
+use std::{
+    io::{self, BufRead, IsTerminal, Stderr, StdinLock, Stdout, Write},
+    path::PathBuf,
+};
+
+use super::{
+    history::History,
+    sys::{STDERR_FD, STDOUT_FD},
+    Shell,
+};
+
+// Sets up a shell before it runs, e.g. for embedding it with commands read from a string and
+// its output written into a `Vec<u8>` instead of the streams of the process:
+//
+//     let mut shell = Shell::builder()
+//         .stdin("echo hi\n".as_bytes())
+//         .stdout(Vec::new())
+//         .disable_builtin("cd")
+//         .alias("ll", "ls -l")
+//         .disable_history()
+//         .build();
+//
+// Only builtins and the messages of the shell itself go to the writers given here. External
+// commands keep the streams of the process, so e.g. the output of `ls` isn't in the `Vec<u8>`
+pub struct ShellBuilder<R: BufRead = StdinLock<'static>, W: Write = Stdout, E: Write = Stderr> {
+    stdin: R,
+    tty: bool,
+    stdout: W,
+    stderr: E,
+    // Whether the shell writes to the streams of the process, see `Output`
+    process_stdout: bool,
+    process_stderr: bool,
+    disabled_builtins: Vec<String>,
+    variables: Vec<(String, String)>,
+    aliases: Vec<(String, String)>,
+    history_file: Option<PathBuf>,
+}
+
+impl ShellBuilder {
+    pub(super) fn new() -> Self {
+        let stdin = io::stdin();
+
+        Self {
+            tty: stdin.is_terminal(),
+            stdin: stdin.lock(),
+            stdout: io::stdout(),
+            stderr: io::stderr(),
+            process_stdout: true,
+            process_stderr: true,
+            disabled_builtins: Vec::new(),
+            variables: Vec::new(),
+            aliases: Vec::new(),
+            history_file: History::default_path(),
+        }
+    }
+}
+
+impl<R: BufRead, W: Write, E: Write> ShellBuilder<R, W, E> {
+    // Where `run()` reads commands from, without the line editor and job control of a terminal
+    pub fn stdin<T: BufRead>(self, stdin: T) -> ShellBuilder<T, W, E> {
+        ShellBuilder {
+            stdin,
+            tty: false,
+            stdout: self.stdout,
+            stderr: self.stderr,
+            process_stdout: self.process_stdout,
+            process_stderr: self.process_stderr,
+            disabled_builtins: self.disabled_builtins,
+            variables: self.variables,
+            aliases: self.aliases,
+            history_file: self.history_file,
+        }
+    }
+
+    // Where builtins write their output to, external commands keep writing to the stdout of
+    // the process
+    pub fn stdout<T: Write>(self, stdout: T) -> ShellBuilder<R, T, E> {
+        ShellBuilder {
+            stdin: self.stdin,
+            tty: self.tty,
+            stdout,
+            stderr: self.stderr,
+            process_stdout: false,
+            process_stderr: self.process_stderr,
+            disabled_builtins: self.disabled_builtins,
+            variables: self.variables,
+            aliases: self.aliases,
+            history_file: self.history_file,
+        }
+    }
+
+    // Where builtins and the shell itself write errors to
+    pub fn stderr<T: Write>(self, stderr: T) -> ShellBuilder<R, W, T> {
+        ShellBuilder {
+            stdin: self.stdin,
+            tty: self.tty,
+            stdout: self.stdout,
+            stderr,
+            process_stdout: self.process_stdout,
+            process_stderr: false,
+            disabled_builtins: self.disabled_builtins,
+            variables: self.variables,
+            aliases: self.aliases,
+            history_file: self.history_file,
+        }
+    }
+
+    // Leave out the builtin `name`, a command of that name is then looked up in `$PATH`
+    pub fn disable_builtin(mut self, name: &str) -> Self {
+        self.disabled_builtins.push(name.to_owned());
+        self
+    }
+
+    // Set the variable `name` to `value` before the shell runs anything. It only belongs to the
+    // shell and the commands it runs, the environment of the process stays as it is
+    pub fn env(mut self, name: &str, value: &str) -> Self {
+        self.variables.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    // Define an alias like `alias name=value` does
+    pub fn alias(mut self, name: &str, value: &str) -> Self {
+        self.aliases.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    // Load the history from and save it to `path` instead of `~/.shell_history`
+    pub fn history_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.history_file = Some(path.into());
+        self
+    }
+
+    // Keep the history only in memory, so nothing is read from or written to a file
+    pub fn disable_history(mut self) -> Self {
+        self.history_file = None;
+        self
+    }
+
+    pub fn build(self) -> Shell<R, W, E> {
+        let mut shell = Shell::with_streams(
+            self.stdin,
+            self.tty,
+            self.stdout,
+            self.stderr,
+            self.history_file,
+        );
+
+        if self.process_stdout {
+            shell.stdout.get_mut().fd = Some(STDOUT_FD);
+        }
+
+        if self.process_stderr {
+            shell.stderr.get_mut().fd = Some(STDERR_FD);
+        }
+
+        for name in &self.disabled_builtins {
+            shell.builtins.remove(name.as_str());
+        }
+
+        for (name, value) in self.variables {
+            shell.variables.set(name, value);
+        }
+
+        shell.aliases.extend(self.aliases);
+        shell
+    }
+}
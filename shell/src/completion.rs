@@ -0,0 +1,302 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, fs,
+    io::{BufRead, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    thread,
+};
+
+use super::{lexer::Token, Shell};
+
+const SPACE: char = ' ';
+const SLASH: char = '/';
+const DOT: char = '.';
+const BACKSLASH: char = '\\';
+
+// Characters which are escaped in candidates as they would otherwise split or expand the word
+const SPECIAL_CHARS: &str = " \t\n'\"\\$`|&;<>()*?[]#~!";
+
+// The executables found in `$PATH` by name, along with the value of `$PATH` they were collected
+// from so a changed `$PATH` causes a rescan. Only the first executable of a name is kept, as
+// that's the one which runs
+pub struct ExecutableCache {
+    path: String,
+    executables: BTreeMap<String, PathBuf>,
+}
+
+// Shared between the shell and the thread scanning `$PATH` in the background, so the first
+// prompt doesn't have to wait for the scan
+#[derive(Clone, Default)]
+pub struct PathIndex(Arc<Mutex<Option<ExecutableCache>>>);
+
+impl PathIndex {
+    // Drop the current index and scan `path`, the `$PATH` of a shell, again in the background
+    pub fn refresh(&self, path: &str) {
+        let index = self.clone();
+        let path = path.to_owned();
+
+        *self.lock() = None;
+
+        // The lock is only taken once the scan is done, so it's never held for long
+        thread::spawn(move || {
+            let cache = <Shell>::scan_path(path);
+            *index.lock() = Some(cache);
+        });
+    }
+
+    // Where the index found `name`, without ever waiting for it. Gives `None` while the index
+    // is being built or if it was built from another `path`
+    pub fn find(&self, name: &str, path: &str) -> Option<PathBuf> {
+        let index = self.0.try_lock().ok()?;
+
+        index
+            .as_ref()
+            .filter(|cache| cache.path == path)?
+            .executables
+            .get(name)
+            .cloned()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<ExecutableCache>> {
+        // A panic while holding the lock can't leave the index half-updated
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<R: BufRead, W: Write, E: Write> Shell<R, W, E> {
+    // Complete the last word of `line`, returning where that word starts along with the
+    // candidates replacing it. Each candidate already ends with the character which should
+    // follow it
+    pub(super) fn complete(&mut self, line: &str) -> (usize, Vec<String>) {
+        // Words end where the lexer ends them, so `cat my\ fi` and `cat "my fi` complete
+        // `my fi` and `ls|gr` completes `gr` as a command
+        let Ok(mut tokens) = Self::tokenize(line) else {
+            return (line.len(), Vec::new());
+        };
+
+        let word = match tokens.last() {
+            Some(Token::Word(word)) if line.ends_with(word.as_str()) => word.clone(),
+            _ => String::new(),
+        };
+
+        if !word.is_empty() {
+            tokens.pop();
+        }
+
+        // Commands are expected at the start of the line, after operators and after keywords
+        // like `then`
+        let mut is_cmd_position = true;
+
+        for token in &tokens {
+            is_cmd_position = match token {
+                Token::Word(word) => is_cmd_position && Self::is_opening_word(word),
+                token => matches!(
+                    token,
+                    Token::Pipe
+                        | Token::And
+                        | Token::Or
+                        | Token::Semicolon
+                        | Token::Newline
+                        | Token::Background
+                ),
+            };
+        }
+
+        let start = line.len() - word.len();
+        let word = Self::unquote_delimiter(&word);
+
+        // Commands given as a path like `./build.sh` are completed like arguments
+        let candidates = if is_cmd_position && !word.contains(SLASH) {
+            self.complete_cmd(&word)
+        } else {
+            Self::complete_path(&word)
+        };
+
+        (start, candidates)
+    }
+
+    fn complete_cmd(&mut self, prefix: &str) -> Vec<String> {
+        let builtins: Vec<_> = self.builtins.keys().copied().collect();
+        let executables = self.executables();
+
+        // Builtins and executables may share a name, so collect them into a sorted set first
+        builtins
+            .into_iter()
+            .chain(executables.iter().map(String::as_str))
+            .filter(|name| name.starts_with(prefix))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|name| format!("{}{}", Self::escape(name), SPACE))
+            .collect()
+    }
+
+    fn complete_path(word: &str) -> Vec<String> {
+        // Split `src/ma` into the directory `src/` to search and the prefix `ma` of its entries
+        let (dir, prefix) = word.rsplit_once(SLASH).map_or(("", word), |(dir, prefix)| {
+            (&word[..dir.len() + SLASH.len_utf8()], prefix)
+        });
+
+        let Ok(entries) = fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+            return Vec::new();
+        };
+
+        let mut candidates: Vec<_> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+
+                // Hidden entries are only offered when explicitly asked for
+                if !name.starts_with(prefix) || (name.starts_with(DOT) && !prefix.starts_with(DOT))
+                {
+                    return None;
+                }
+
+                // Directories can be completed further, while files end the argument
+                let suffix = if entry.path().is_dir() { SLASH } else { SPACE };
+
+                Some(format!(
+                    "{}{}",
+                    Self::escape(&format!("{}{}", dir, name)),
+                    suffix
+                ))
+            })
+            .collect();
+
+        candidates.sort();
+        candidates
+    }
+
+    // Escape the characters of a candidate which the shell would treat specially, so completing
+    // `my file` inserts `my\ file`
+    fn escape(candidate: &str) -> String {
+        let mut escaped = String::with_capacity(candidate.len());
+
+        for c in candidate.chars() {
+            if SPECIAL_CHARS.contains(c) {
+                escaped.push(BACKSLASH);
+            }
+
+            escaped.push(c);
+        }
+
+        escaped
+    }
+
+    fn executables(&self) -> Vec<String> {
+        let path = self.variables.get("PATH").unwrap_or_default();
+        let mut index = self.path_index.lock();
+
+        // Only scan the filesystem here if the background scan isn't done yet or `$PATH`
+        // changed since
+        let cache = index
+            .take()
+            .filter(|cache| cache.path == path)
+            .unwrap_or_else(|| Self::scan_path(path.to_owned()));
+
+        index.insert(cache).executables.keys().cloned().collect()
+    }
+
+    fn scan_path(path: String) -> ExecutableCache {
+        let mut executables = BTreeMap::new();
+
+        for entry in env::split_paths(&path)
+            .filter_map(|dir| fs::read_dir(dir).ok())
+            .flatten()
+            .flatten()
+        {
+            let path = entry.path();
+
+            if let Ok(name) = entry.file_name().into_string() {
+                if !executables.contains_key(&name) && Self::is_executable(&path) {
+                    executables.insert(name, path);
+                }
+            }
+        }
+
+        ExecutableCache { path, executables }
+    }
+
+    pub(super) fn display_name(candidate: &str) -> &str {
+        // Only show the last path component when listing candidates, keeping the `/` of a
+        // directory but not the space after a file
+        let candidate = candidate.trim_end_matches(SPACE);
+
+        candidate
+            .trim_end_matches(SLASH)
+            .rfind(SLASH)
+            .map_or(candidate, |i| &candidate[i + SLASH.len_utf8()..])
+    }
+
+    pub(super) fn common_prefix(candidates: &[String]) -> &str {
+        let Some((first, rest)) = candidates.split_first() else {
+            return "";
+        };
+
+        rest.iter().fold(first.as_str(), |prefix, candidate| {
+            // Byte index of the first differing character, or the end of the shorter string
+            let len = prefix
+                .char_indices()
+                .zip(candidate.chars())
+                .find(|((_, a), b)| a != b)
+                .map_or_else(|| prefix.len().min(candidate.len()), |((i, _), _)| i);
+
+            &prefix[..len]
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn completes_words_ended_by_the_lexer() {
+        let dir = env::temp_dir().join(format!("shell-completion-test-{}", process::id()));
+        fs::create_dir_all(dir.join("my dir")).unwrap();
+        fs::write(dir.join("my file"), "").unwrap();
+        fs::write(dir.join("a&b"), "").unwrap();
+
+        let dir = dir.to_str().unwrap();
+        let mut shell = Shell::for_test();
+
+        let line = format!("cat {}/my\\ f", dir);
+        let file = format!("{}/my\\ file ", dir);
+        assert_eq!(shell.complete(&line), (4, vec![file.clone()]));
+
+        let line = format!("cat \"{}/my f", dir);
+        assert_eq!(shell.complete(&line), (4, vec![file]));
+
+        let line = format!("ls {}/my", dir);
+        let candidates = vec![format!("{}/my\\ dir/", dir), format!("{}/my\\ file ", dir)];
+        assert_eq!(shell.complete(&line), (3, candidates));
+
+        let line = format!("ls {}/a", dir);
+        assert_eq!(shell.complete(&line), (3, vec![format!("{}/a\\&b ", dir)]));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn completes_commands_after_operators() {
+        let mut shell = Shell::for_test();
+
+        for line in ["ech", "ls|ech", "true && ech", "if true; then ech"] {
+            let (start, candidates) = shell.complete(line);
+
+            assert_eq!(start, line.len() - 3);
+            assert!(candidates.contains(&"echo ".to_owned()));
+        }
+
+        // After a redirection or a quoted operator the word is an argument again
+        let (_, candidates) = shell.complete("ls > ech");
+        assert!(!candidates.contains(&"echo ".to_owned()));
+
+        let (_, candidates) = shell.complete("echo '|' ech");
+        assert!(!candidates.contains(&"echo ".to_owned()));
+
+        assert_eq!(shell.complete("ls  ").0, 4);
+    }
+}
@@ -0,0 +1,816 @@
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, Write},
+    mem, process,
+    rc::Rc,
+};
+
+use super::{
+    error::ShellError,
+    lexer::{Token, PIPE},
+    Result, Shell,
+};
+
+const IF: &str = "if";
+const THEN: &str = "then";
+const ELIF: &str = "elif";
+const ELSE: &str = "else";
+const FI: &str = "fi";
+const FOR: &str = "for";
+const IN: &str = "in";
+const DO: &str = "do";
+const DONE: &str = "done";
+const WHILE: &str = "while";
+const UNTIL: &str = "until";
+const BRACE_OPEN: &str = "{";
+const BRACE_CLOSE: &str = "}";
+
+// Follows the name of a function being defined, like in `greet() { ... }`
+const FUNCTION_PARENS: &str = "()";
+
+// Status of a command killed by Ctrl-C, which also stops the loops it runs in
+const INTERRUPTED: i32 = 130;
+
+// Words which end a part of a compound command, anywhere else they are a syntax error
+const CLOSING_WORDS: [&str; 7] = [THEN, ELIF, ELSE, FI, DO, DONE, BRACE_CLOSE];
+
+// Words after which another command starts, like `then` in `if a; then b; fi`
+const OPENING_WORDS: [&str; 8] = [IF, THEN, ELIF, ELSE, DO, WHILE, UNTIL, BRACE_OPEN];
+
+// Words starting a compound command which may also follow a `|`, like in `ls | while ...`
+const PIPED_WORDS: [&str; 4] = [IF, FOR, WHILE, UNTIL];
+
+// The pipelines of a list along with the operator following each of them
+type Commands = VecDeque<(Vec<Token>, Option<Token>)>;
+
+// A command of a list along with the operator following it, which decides whether the next
+// command runs
+pub struct Item {
+    cmd: Compound,
+    operator: Option<Token>,
+}
+
+pub enum Compound {
+    // A pipeline like `ls | wc -l`, which is only expanded right before it runs
+    Pipeline(Vec<Token>),
+    // `if cond; then ...; elif cond; then ...; else ...; fi` runs the body of the first
+    // condition which succeeds, or the one of `else` if none does
+    If {
+        branches: Vec<(Vec<Item>, Vec<Item>)>,
+        otherwise: Vec<Item>,
+    },
+    // `for name in words; do ...; done` runs the body with `name` set to each of the expanded
+    // words, or to each positional parameter without `in words`
+    For {
+        name: String,
+        words: Option<Vec<Token>>,
+        body: Vec<Item>,
+    },
+    // `while cond; do ...; done` runs the body as long as the condition succeeds, with `until`
+    // as long as it fails
+    While {
+        until: bool,
+        condition: Vec<Item>,
+        body: Vec<Item>,
+    },
+    // `name() { ...; }` defines a function, whose body runs whenever `name` is run as command
+    Function {
+        name: String,
+        body: Rc<Vec<Item>>,
+    },
+    // A compound command followed by redirections like `while ...; done < file`, which apply
+    // to all of its commands
+    Redirected {
+        cmd: Box<Compound>,
+        redirects: Vec<Token>,
+    },
+    // A pipeline with compound commands in it like `if ...; fi | wc -l`, each of its commands
+    // runs in a forked copy of the shell
+    Piped(Vec<Compound>),
+}
+
+// Set by `break` and `continue` to leave the body of the given number of loops, and by
+// `return` to leave the body of a function with the status
+pub enum Control {
+    Break(usize),
+    Continue(usize),
+    Return(Option<i32>),
+}
+
+impl<R: BufRead, W: Write, E: Write> Shell<R, W, E> {
+    // Group the pipelines of a list into compound commands like `if`
+    pub(super) fn parse_compound(list: Vec<(Vec<Token>, Option<Token>)>) -> Result<Vec<Item>> {
+        let mut commands = Commands::from(list);
+        let (items, _) = Self::parse_items(&mut commands, &[])?;
+
+        Ok(items)
+    }
+
+    // Parse commands up to the first one starting with any of `ends`, which is removed and
+    // returned along with them. Without `ends` the commands reach up to the end of the input
+    fn parse_items(
+        commands: &mut Commands,
+        ends: &[&'static str],
+    ) -> Result<(Vec<Item>, Option<&'static str>)> {
+        let mut items = Vec::new();
+
+        while let Some((mut tokens, operator)) = commands.pop_front() {
+            let word = match tokens.first() {
+                Some(Token::Word(word)) => word.as_str(),
+                // Blank lines and empty commands around `;` are left out
+                None => continue,
+                Some(_) => "",
+            };
+
+            if let Some(&end) = ends.iter().find(|&&end| end == word) {
+                // The word may be followed by the first command of the next part
+                tokens.remove(0);
+                commands.push_front((tokens, operator));
+
+                return Ok((items, Some(end)));
+            }
+
+            if CLOSING_WORDS.contains(&word) {
+                return Err(ShellError::SyntaxError(word.to_owned()));
+            }
+
+            let (cmd, operator) = Self::parse_command(tokens, operator, commands)?;
+
+            // Compound commands can't run in the background, as that needs a copy of the shell
+            if !matches!(cmd, Compound::Pipeline(_)) && operator == Some(Token::Background) {
+                return Err(ShellError::SyntaxError(Token::Background.to_string()));
+            }
+
+            items.push(Item { cmd, operator });
+        }
+
+        match ends {
+            [] => Ok((items, None)),
+            _ => Err(ShellError::SyntaxError("end of file".to_owned())),
+        }
+    }
+
+    // Parse the command starting with `tokens`, which for a compound command reaches over the
+    // commands following it
+    fn parse_command(
+        mut tokens: Vec<Token>,
+        operator: Option<Token>,
+        commands: &mut Commands,
+    ) -> Result<(Compound, Option<Token>)> {
+        let word = match tokens.first() {
+            Some(Token::Word(word)) => word.as_str(),
+            _ => "",
+        };
+
+        let cmd = match word {
+            IF => {
+                tokens.remove(0);
+                commands.push_front((tokens, operator));
+
+                Self::parse_if(commands)?
+            }
+            FOR => {
+                tokens.remove(0);
+                Self::parse_for(tokens, commands)?
+            }
+            WHILE | UNTIL => {
+                let until = word == UNTIL;
+                tokens.remove(0);
+                commands.push_front((tokens, operator));
+
+                Self::parse_while(until, commands)?
+            }
+            _ => match Self::function_name(&tokens) {
+                Some((name, len)) => {
+                    tokens.drain(..len);
+                    commands.push_front((tokens, operator));
+
+                    return Self::parse_function(name, commands);
+                }
+                None => return Self::parse_pipeline_of(tokens, operator, commands),
+            },
+        };
+
+        Self::parse_tail(cmd, commands)
+    }
+
+    // A pipeline of simple commands, unless a compound command follows one of its `|` like in
+    // `ls | while read name; do ...; done`
+    fn parse_pipeline_of(
+        mut tokens: Vec<Token>,
+        operator: Option<Token>,
+        commands: &mut Commands,
+    ) -> Result<(Compound, Option<Token>)> {
+        let pipe = tokens.windows(2).position(|pair| match pair {
+            [Token::Pipe, Token::Word(word)] => PIPED_WORDS.contains(&word.as_str()),
+            _ => false,
+        });
+
+        let Some(i) = pipe else {
+            return Ok((Compound::Pipeline(tokens), operator));
+        };
+
+        if i == 0 {
+            return Err(ShellError::SyntaxError(PIPE.to_owned()));
+        }
+
+        let rest = tokens.split_off(i + 1);
+        tokens.pop();
+
+        let (next, operator) = Self::parse_command(rest, operator, commands)?;
+
+        Ok((Self::pipe(Compound::Pipeline(tokens), next), operator))
+    }
+
+    // The redirections and the rest of the pipeline following the closing word of a compound
+    // command like in `done < file | sort`, along with the operator after them
+    fn parse_tail(cmd: Compound, commands: &mut Commands) -> Result<(Compound, Option<Token>)> {
+        let (mut tokens, operator) = commands.pop_front().unwrap_or_default();
+
+        let rest = tokens
+            .iter()
+            .position(|token| *token == Token::Pipe)
+            .map(|i| {
+                let rest = tokens.split_off(i + 1);
+                tokens.pop();
+                rest
+            });
+
+        Self::check_redirects(&tokens)?;
+
+        let cmd = match tokens.is_empty() {
+            true => cmd,
+            false => Compound::Redirected {
+                cmd: Box::new(cmd),
+                redirects: tokens,
+            },
+        };
+
+        match rest {
+            // Every command of a pipeline needs a name, like after `fi |`
+            Some(rest) if rest.is_empty() => Err(ShellError::SyntaxError(PIPE.to_owned())),
+            Some(rest) => {
+                let (next, operator) = Self::parse_command(rest, operator, commands)?;
+                Ok((Self::pipe(cmd, next), operator))
+            }
+            None => Ok((cmd, operator)),
+        }
+    }
+
+    // Only redirections with their targets may follow the closing word of a compound command
+    fn check_redirects(tokens: &[Token]) -> Result<()> {
+        let mut tokens = tokens.iter();
+
+        while let Some(token) = tokens.next() {
+            match token {
+                Token::RedirectIn(_)
+                | Token::RedirectOut(_)
+                | Token::RedirectAppend(_)
+                | Token::RedirectForce(_)
+                | Token::HereString(_) => match tokens.next() {
+                    Some(Token::Word(_)) => {}
+                    Some(token) => return Err(ShellError::SyntaxError(token.to_string())),
+                    None => return Err(ShellError::SyntaxError(Token::Newline.to_string())),
+                },
+                Token::Duplicate(..) | Token::Close(_) | Token::HereDoc { .. } => {}
+                token => return Err(ShellError::SyntaxError(token.to_string())),
+            }
+        }
+
+        Ok(())
+    }
+
+    // Join two commands of a pipeline, `rest` may already be a pipeline itself
+    fn pipe(first: Compound, rest: Compound) -> Compound {
+        let mut stages = vec![first];
+
+        match rest {
+            Compound::Piped(rest) => stages.extend(rest),
+            rest => stages.push(rest),
+        }
+
+        Compound::Piped(stages)
+    }
+
+    // Parse an `if` whose keyword was already consumed, up to and including its `fi`
+    fn parse_if(commands: &mut Commands) -> Result<Compound> {
+        let mut branches = Vec::new();
+        let mut otherwise = Vec::new();
+
+        loop {
+            let (condition, _) = Self::parse_part(commands, &[THEN])?;
+            let (body, end) = Self::parse_part(commands, &[ELIF, ELSE, FI])?;
+            branches.push((condition, body));
+
+            match end {
+                ELIF => continue,
+                ELSE => otherwise = Self::parse_part(commands, &[FI])?.0,
+                _ => {}
+            }
+
+            break;
+        }
+
+        Ok(Compound::If {
+            branches,
+            otherwise,
+        })
+    }
+
+    // Parse a `for` from the words following its keyword on the same line up to its `done`
+    fn parse_for(header: Vec<Token>, commands: &mut Commands) -> Result<Compound> {
+        let mut header = header.into_iter();
+
+        let name = match header.next() {
+            Some(Token::Word(name)) if Self::is_name(&name) => name,
+            Some(Token::Word(name)) => {
+                return Err(ShellError::InvalidIdentifier(FOR.to_owned(), name));
+            }
+            token => {
+                let token = token.map_or_else(|| Token::Newline.to_string(), |t| t.to_string());
+                return Err(ShellError::SyntaxError(token));
+            }
+        };
+
+        let words = match header.next() {
+            Some(Token::Word(word)) if word == IN => Some(header.collect::<Vec<_>>()),
+            Some(token) => return Err(ShellError::SyntaxError(token.to_string())),
+            None => None,
+        };
+
+        if let Some(token) = words
+            .iter()
+            .flatten()
+            .find(|t| !matches!(t, Token::Word(_)))
+        {
+            return Err(ShellError::SyntaxError(token.to_string()));
+        }
+
+        // `do` follows on the next line or after `;`
+        match Self::parse_items(commands, &[DO])? {
+            (items, _) if items.is_empty() => {}
+            _ => return Err(ShellError::SyntaxError(DO.to_owned())),
+        }
+
+        let (body, _) = Self::parse_part(commands, &[DONE])?;
+
+        Ok(Compound::For { name, words, body })
+    }
+
+    // Parse a `while` or `until` whose keyword was already consumed, up to and including its
+    // `done`
+    fn parse_while(until: bool, commands: &mut Commands) -> Result<Compound> {
+        let (condition, _) = Self::parse_part(commands, &[DO])?;
+        let (body, _) = Self::parse_part(commands, &[DONE])?;
+
+        Ok(Compound::While {
+            until,
+            condition,
+            body,
+        })
+    }
+
+    // The name of the function a command defines like `greet() { ... }` or `greet () { ... }`,
+    // along with the number of tokens it takes up
+    fn function_name(tokens: &[Token]) -> Option<(String, usize)> {
+        let (name, len) = match tokens {
+            [Token::Word(name), Token::Word(parens), ..] if parens == FUNCTION_PARENS => {
+                (name.as_str(), 2)
+            }
+            [Token::Word(word), ..] => (word.strip_suffix(FUNCTION_PARENS)?, 1),
+            _ => return None,
+        };
+
+        Self::is_name(name).then(|| (name.to_owned(), len))
+    }
+
+    // Parse the body of a function whose name was already consumed, from its `{` which may
+    // follow on the next line up to and including its `}`
+    fn parse_function(name: String, commands: &mut Commands) -> Result<(Compound, Option<Token>)> {
+        match Self::parse_items(commands, &[BRACE_OPEN])? {
+            (items, _) if items.is_empty() => {}
+            _ => return Err(ShellError::SyntaxError(BRACE_OPEN.to_owned())),
+        }
+
+        let (body, _) = Self::parse_part(commands, &[BRACE_CLOSE])?;
+        let operator = Self::parse_end(commands)?;
+
+        Ok((
+            Compound::Function {
+                name,
+                body: Rc::new(body),
+            },
+            operator,
+        ))
+    }
+
+    // Parse a part of a compound command like the condition of `if`, which needs at least one
+    // command and has to end with any of `ends`
+    fn parse_part(
+        commands: &mut Commands,
+        ends: &[&'static str],
+    ) -> Result<(Vec<Item>, &'static str)> {
+        match Self::parse_items(commands, ends)? {
+            (items, Some(end)) if !items.is_empty() => Ok((items, end)),
+            (_, end) => Err(ShellError::SyntaxError(end.unwrap_or_default().to_owned())),
+        }
+    }
+
+    // The operator following the `}` of a function, which may not be followed by anything else
+    fn parse_end(commands: &mut Commands) -> Result<Option<Token>> {
+        let (tokens, operator) = commands.pop_front().unwrap_or_default();
+
+        match tokens.first() {
+            Some(token) => Err(ShellError::SyntaxError(token.to_string())),
+            None => Ok(operator),
+        }
+    }
+
+    // Whether another command follows the word at the start of a command, like after `then` or
+    // the name of a function being defined
+    pub(super) fn is_opening_word(word: &str) -> bool {
+        OPENING_WORDS.contains(&word) || word.ends_with(FUNCTION_PARENS)
+    }
+
+    // Whether `tokens` end inside a compound command like `if`, which continues on the next line
+    pub(super) fn has_open_compound(tokens: &[Token]) -> bool {
+        let mut depth = 0;
+        let mut is_cmd_start = true;
+        // Whether the `{` of a function already counts, as it may follow on the next line
+        let mut in_function_header = false;
+
+        for token in tokens {
+            match token {
+                // The body of a function follows its name like a command
+                Token::Word(word) if word.ends_with(FUNCTION_PARENS) => {
+                    depth += 1;
+                    is_cmd_start = true;
+                    in_function_header = true;
+                }
+                Token::Word(word) if is_cmd_start => {
+                    match word.as_str() {
+                        BRACE_OPEN if mem::take(&mut in_function_header) => {}
+                        IF | FOR | WHILE | UNTIL | BRACE_OPEN => depth += 1,
+                        FI | DONE | BRACE_CLOSE => depth -= 1,
+                        _ => {}
+                    }
+
+                    is_cmd_start = OPENING_WORDS.contains(&word.as_str());
+                }
+                Token::Semicolon
+                | Token::Newline
+                | Token::Background
+                | Token::And
+                | Token::Or
+                | Token::Pipe => is_cmd_start = true,
+                _ => is_cmd_start = false,
+            }
+        }
+
+        depth > 0
+    }
+
+    // Run the commands of a list, skipping those after `&&` or `||` depending on the status.
+    // Failing commands in conditions don't exit the shell with `set -e`
+    pub(super) fn run_items(&mut self, items: &[Item], is_condition: bool) -> io::Result<()> {
+        let mut run_next = true;
+
+        for item in items {
+            // `break` and `continue` skip the rest of the loop's body
+            if self.control.is_some() {
+                break;
+            }
+
+            if run_next {
+                let status = self.last_status;
+
+                // With `set -e` a failing command exits the shell, unless its status is checked
+                // by `&&`, `||` or a condition
+                let is_checked =
+                    is_condition || matches!(item.operator, Some(Token::And | Token::Or));
+
+                self.run_compound(&item.cmd, item.operator.as_ref(), is_condition)?;
+
+                // `return` without a status returns the one of the command before it
+                if let Some(Control::Return(returned @ None)) = &mut self.control {
+                    *returned = Some(status);
+                }
+
+                if self.flags.errexit && self.last_status != 0 && !is_checked {
+                    self.run_exit_trap()?;
+                    self.save_history();
+                    self.flush()?;
+
+                    process::exit(self.last_status);
+                }
+            }
+
+            // Signals caught while the command ran are handled once it finished
+            self.run_traps()?;
+
+            // Skipped commands keep the status, so `false && a || b` still runs `b`, while the
+            // command after `;` or `&` always runs
+            run_next = match item.operator {
+                Some(Token::And) => self.last_status == 0,
+                Some(Token::Or) => self.last_status != 0,
+                _ => true,
+            };
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn run_compound(
+        &mut self,
+        cmd: &Compound,
+        operator: Option<&Token>,
+        is_condition: bool,
+    ) -> io::Result<()> {
+        match cmd {
+            Compound::Pipeline(tokens) => self.run_pipeline(tokens.clone(), operator),
+            Compound::If {
+                branches,
+                otherwise,
+            } => self.run_if(branches, otherwise, is_condition),
+            Compound::For { name, words, body } => {
+                self.run_for(name, words.as_deref(), body, is_condition)
+            }
+            Compound::While {
+                until,
+                condition,
+                body,
+            } => self.run_while(*until, condition, body, is_condition),
+            Compound::Function { name, body } => {
+                self.functions.insert(name.clone(), Rc::clone(body));
+                self.last_status = 0;
+                Ok(())
+            }
+            Compound::Redirected { cmd, redirects } => {
+                let result = self.run_redirected(cmd, redirects, is_condition);
+                self.report(result)
+            }
+            Compound::Piped(stages) => {
+                let result = self.run_piped(stages, is_condition);
+                self.report(result)
+            }
+        }
+    }
+
+    // Run a compound command with its redirections applied to the shell like for a builtin, so
+    // variables set by it are kept
+    fn run_redirected(
+        &mut self,
+        cmd: &Compound,
+        redirects: &[Token],
+        is_condition: bool,
+    ) -> Result<()> {
+        let segment = self.parse_pipeline(redirects.to_vec())?.pop();
+        self.redirects = segment.map(|segment| segment.redirects).unwrap_or_default();
+
+        self.run_in_shell(|shell| Ok(shell.run_compound(cmd, None, is_condition)?))
+    }
+
+    // Commands which fail before they run, like when a redirection can't be opened, fail with
+    // the status of the error
+    fn report(&mut self, result: Result<()>) -> io::Result<()> {
+        if let Err(error) = result {
+            self.last_status = error.status();
+            writeln!(self.stderr, "{}", error)?;
+        }
+
+        Ok(())
+    }
+
+    // The command line a pipeline is listed by as a job, with keywords standing in for compound
+    // commands
+    pub(super) fn describe(cmd: &Compound) -> String {
+        match cmd {
+            Compound::Pipeline(tokens) => {
+                let words: Vec<_> = tokens.iter().map(Token::to_string).collect();
+                words.join(" ")
+            }
+            Compound::If { .. } => IF.to_owned(),
+            Compound::For { .. } => FOR.to_owned(),
+            Compound::While { until: false, .. } => WHILE.to_owned(),
+            Compound::While { until: true, .. } => UNTIL.to_owned(),
+            Compound::Function { name, .. } => name.clone(),
+            Compound::Redirected { cmd, .. } => Self::describe(cmd),
+            Compound::Piped(stages) => {
+                let stages: Vec<_> = stages.iter().map(Self::describe).collect();
+                stages.join(" | ")
+            }
+        }
+    }
+
+    fn run_if(
+        &mut self,
+        branches: &[(Vec<Item>, Vec<Item>)],
+        otherwise: &[Item],
+        is_condition: bool,
+    ) -> io::Result<()> {
+        for (condition, body) in branches {
+            self.run_items(condition, true)?;
+
+            if self.control.is_some() {
+                return Ok(());
+            }
+
+            if self.last_status == 0 {
+                return self.run_items(body, is_condition);
+            }
+        }
+
+        // Without an `else` the status is 0, not the one of the failed condition
+        self.last_status = 0;
+        self.run_items(otherwise, is_condition)
+    }
+
+    fn run_for(
+        &mut self,
+        name: &str,
+        words: Option<&[Token]>,
+        body: &[Item],
+        is_condition: bool,
+    ) -> io::Result<()> {
+        let values = match words {
+            Some(words) => match self.expand_words(words) {
+                Ok(values) => values,
+                Err(error) => {
+                    self.last_status = error.status();
+                    return writeln!(self.stderr, "{}", error);
+                }
+            },
+            None => self.positional.clone(),
+        };
+
+        // Without any words the body never runs and the loop succeeds
+        self.last_status = 0;
+        self.loop_depth += 1;
+
+        for value in values {
+            self.variables.set(name, value);
+            self.run_items(body, is_condition)?;
+
+            if self.end_iteration() {
+                break;
+            }
+        }
+
+        self.loop_depth -= 1;
+
+        Ok(())
+    }
+
+    fn run_while(
+        &mut self,
+        until: bool,
+        condition: &[Item],
+        body: &[Item],
+        is_condition: bool,
+    ) -> io::Result<()> {
+        // The status of the loop is the one of the last body which ran, or 0 if none did
+        let mut status = 0;
+        self.loop_depth += 1;
+
+        loop {
+            self.run_items(condition, true)?;
+
+            if self.end_iteration() {
+                break;
+            }
+
+            if (self.last_status == 0) == until {
+                self.last_status = status;
+                break;
+            }
+
+            self.run_items(body, is_condition)?;
+            status = self.last_status;
+
+            if self.end_iteration() {
+                break;
+            }
+        }
+
+        self.loop_depth -= 1;
+
+        Ok(())
+    }
+
+    fn expand_words(&mut self, words: &[Token]) -> Result<Vec<String>> {
+        let mut values = Vec::new();
+
+        for word in words {
+            if let Token::Word(word) = word {
+                values.extend(self.expand_word(word)?);
+            }
+        }
+
+        Ok(values)
+    }
+
+    // Handle a `break` or `continue` in the body of a loop, returning whether the loop ends
+    fn end_iteration(&mut self) -> bool {
+        // Ctrl-C only reaches the command running in the foreground, so the loops are left
+        // from here instead of starting the next command
+        if self.last_status == INTERRUPTED && self.control.is_none() {
+            self.control = Some(Control::Break(self.loop_depth));
+        }
+
+        match self.control.take() {
+            Some(Control::Break(loops)) => {
+                self.control = (loops > 1).then(|| Control::Break(loops - 1));
+                true
+            }
+            Some(Control::Continue(loops)) if loops > 1 => {
+                self.control = Some(Control::Continue(loops - 1));
+                true
+            }
+            // `return` leaves every loop of the function
+            Some(control @ Control::Return(_)) => {
+                self.control = Some(control);
+                true
+            }
+            Some(Control::Continue(_)) | None => false,
+        }
+    }
+
+    // Run the body of a function with the arguments as positional parameters, the loops
+    // around the call can't be left from inside of it
+    pub(super) fn call_function(&mut self, body: &[Item]) -> Result<()> {
+        let args = self.args.take().unwrap_or_default();
+        let positional = mem::replace(&mut self.positional, args);
+        let loop_depth = mem::take(&mut self.loop_depth);
+        self.function_depth += 1;
+
+        let result = self.run_items(body, false);
+
+        if let Some(Control::Return(status)) = self.control.take() {
+            self.last_status = status.unwrap_or(self.last_status);
+        }
+
+        self.function_depth -= 1;
+        self.loop_depth = loop_depth;
+        self.positional = positional;
+
+        Ok(result?)
+    }
+
+    pub(super) fn return_(&mut self) -> Result<()> {
+        if self.function_depth == 0 {
+            return Err(ShellError::InvalidExpression(
+                self.cmd.clone(),
+                "can only `return' from a function".to_owned(),
+            ));
+        }
+
+        let status = match self.args.as_ref().and_then(|args| args.first()) {
+            Some(status) => Some(status.parse::<i32>().map_err(|_| {
+                ShellError::InvalidExpression(
+                    self.cmd.clone(),
+                    format!("{}: numeric argument required", status),
+                )
+            })?),
+            None => None,
+        };
+
+        self.control = Some(Control::Return(status));
+        Ok(())
+    }
+
+    pub(super) fn break_(&mut self) -> Result<()> {
+        self.control = Some(Control::Break(self.loop_count()?));
+        Ok(())
+    }
+
+    pub(super) fn continue_(&mut self) -> Result<()> {
+        self.control = Some(Control::Continue(self.loop_count()?));
+        Ok(())
+    }
+
+    // The number of loops `break n` and `continue n` refer to, 1 by default. More loops than
+    // there are refer to all of them
+    fn loop_count(&self) -> Result<usize> {
+        if self.loop_depth == 0 {
+            return Err(ShellError::InvalidExpression(
+                self.cmd.clone(),
+                "only meaningful in a loop".to_owned(),
+            ));
+        }
+
+        let count = match self.args.as_ref().and_then(|args| args.first()) {
+            Some(count) => match count.parse::<usize>() {
+                Ok(count) if count > 0 => count,
+                _ => {
+                    return Err(ShellError::InvalidExpression(
+                        self.cmd.clone(),
+                        format!("{}: loop count out of range", count),
+                    ))
+                }
+            },
+            None => 1,
+        };
+
+        Ok(count.min(self.loop_depth))
+    }
+}
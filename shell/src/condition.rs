@@ -0,0 +1,105 @@
+use std::{
+    fs,
+    io::{BufRead, Write},
+    path::Path,
+};
+
+use super::{error::ShellError, sys, Result, Shell};
+
+const NOT: &str = "!";
+
+impl<R: BufRead, W: Write, E: Write> Shell<R, W, E> {
+    // Evaluate the expression of `test` or `[`. Like in POSIX the meaning of the arguments
+    // depends on how many there are, so e.g. `[ -n ]` tests whether `-n` is a non-empty string
+    pub(super) fn evaluate(cmd: &str, args: &[String]) -> Result<bool> {
+        match args {
+            // No expression is false, a single argument is true if it isn't empty
+            [] => Ok(false),
+            [arg] => Ok(!arg.is_empty()),
+            [not, rest @ ..] if not == NOT && rest.len() < 3 => {
+                Self::evaluate(cmd, rest).map(|result| !result)
+            }
+            [operator, operand] => Self::evaluate_unary(cmd, operator, operand),
+            [left, operator, right] if Self::is_binary(operator) => {
+                Self::evaluate_binary(cmd, left, operator, right)
+            }
+            [not, rest @ ..] if not == NOT && rest.len() == 3 => {
+                Self::evaluate(cmd, rest).map(|result| !result)
+            }
+            [_, operator, _] => Err(ShellError::InvalidCondition(
+                cmd.to_owned(),
+                format!("{}: binary operator expected", operator),
+            )),
+            _ => Err(ShellError::InvalidCondition(
+                cmd.to_owned(),
+                "too many arguments".to_owned(),
+            )),
+        }
+    }
+
+    fn evaluate_unary(cmd: &str, operator: &str, operand: &str) -> Result<bool> {
+        let path = Path::new(operand);
+
+        let result = match operator {
+            "-z" => operand.is_empty(),
+            "-n" => !operand.is_empty(),
+            // Symlinks are followed, so a dangling one doesn't exist
+            "-e" => path.exists(),
+            "-f" => path.is_file(),
+            "-d" => path.is_dir(),
+            "-s" => fs::metadata(path).is_ok_and(|metadata| metadata.len() > 0),
+            "-r" => sys::access(path, sys::R_OK),
+            "-w" => sys::access(path, sys::W_OK),
+            "-x" => sys::access(path, sys::X_OK),
+            _ => {
+                return Err(ShellError::InvalidCondition(
+                    cmd.to_owned(),
+                    format!("{}: unary operator expected", operator),
+                ))
+            }
+        };
+
+        Ok(result)
+    }
+
+    fn evaluate_binary(cmd: &str, left: &str, operator: &str, right: &str) -> Result<bool> {
+        let result = match operator {
+            "=" | "==" => left == right,
+            "!=" => left != right,
+            _ => {
+                let (left, right) = (
+                    Self::parse_integer(cmd, left)?,
+                    Self::parse_integer(cmd, right)?,
+                );
+
+                match operator {
+                    "-eq" => left == right,
+                    "-ne" => left != right,
+                    "-lt" => left < right,
+                    "-le" => left <= right,
+                    "-gt" => left > right,
+                    _ => left >= right,
+                }
+            }
+        };
+
+        Ok(result)
+    }
+
+    fn is_binary(operator: &str) -> bool {
+        matches!(
+            operator,
+            "=" | "==" | "!=" | "-eq" | "-ne" | "-lt" | "-le" | "-gt" | "-ge"
+        )
+    }
+
+    fn parse_integer(cmd: &str, arg: &str) -> Result<i64> {
+        // Surrounding whitespace like in `" 3"` is allowed
+        arg.trim().parse().map_err(|_| {
+            ShellError::InvalidCondition(
+                cmd.to_owned(),
+                format!("{}: integer expression expected", arg),
+            )
+        })
+    }
+}
@@ -0,0 +1,513 @@
+use std::{
+    io::{self, BufRead, Write},
+    mem,
+};
+
+use super::{sys, Shell};
+
+const CTRL_A: u8 = 0x01;
+const CTRL_C: u8 = 0x03;
+const CTRL_D: u8 = 0x04;
+const CTRL_E: u8 = 0x05;
+const CTRL_G: u8 = 0x07;
+const CTRL_H: u8 = 0x08;
+const CTRL_L: u8 = 0x0c;
+const CTRL_R: u8 = 0x12;
+const TAB: u8 = b'\t';
+const ESCAPE: u8 = 0x1b;
+const DELETE: u8 = 0x7f;
+const CARRIAGE_RETURN: u8 = b'\r';
+const LINE_FEED: u8 = b'\n';
+const CSI: u8 = b'[';
+
+// Erase from the cursor to the end of the line
+const CLEAR_LINE: &str = "\x1b[K";
+// Erase the whole screen and move the cursor to its top left corner
+pub(super) const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+const BELL: char = '\x07';
+
+// Shown while reading further lines of an incomplete command
+const CONTINUATION_PROMPT: &str = "> ";
+
+// Shown instead of the prompt while searching the history with Ctrl-R
+const SEARCH_PROMPT: &str = "(reverse-i-search)";
+const FAILED_SEARCH_PROMPT: &str = "(failed reverse-i-search)";
+
+enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Tab,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    Delete,
+    ClearScreen,
+    Search,
+    Cancel,
+    Interrupt,
+    Eof,
+    Unknown,
+}
+
+impl<R: BufRead, W: Write, E: Write> Shell<R, W, E> {
+    // Read a command, which continues on further lines as long as quotes are left open or lines
+    // end with `\`. Fails with `io::ErrorKind::Interrupted` if the input is cancelled by Ctrl-C
+    pub(super) fn read_cmd(&mut self, input: &mut String) -> io::Result<usize> {
+        let mut len = self.read_input(&self.prompt(), input)?;
+
+        while len > 0 && Self::is_incomplete(input) {
+            match self.read_input(CONTINUATION_PROMPT, input)? {
+                // Run whatever was read so far if the input ends before the command does
+                0 => break,
+                read => len += read,
+            }
+        }
+
+        Ok(len)
+    }
+
+    fn read_input(&mut self, prompt: &str, input: &mut String) -> io::Result<usize> {
+        self.print_prompt(prompt)?;
+
+        let original = sys::enable_raw_mode()?;
+        let result = self.edit_line(prompt, input);
+
+        // Always restore the terminal, commands expect it in its normal mode
+        sys::set_terminal_mode(&original)?;
+        result
+    }
+
+    fn edit_line(&mut self, prompt: &str, input: &mut String) -> io::Result<usize> {
+        let mut line = String::new();
+        // Byte offset of the cursor into the line, always at the start of a character
+        let mut cursor = 0;
+        // Line typed before navigating the history, restored when moving past the newest entry
+        let mut draft = String::new();
+        // Index of the history entry currently shown, `None` while editing a new line
+        let mut position: Option<usize> = None;
+        // Whether the previous key was Tab as well, the second Tab lists all candidates
+        let mut tabbed = false;
+
+        loop {
+            let key = Self::read_key(&mut self.stdin)?;
+            let is_tab = matches!(key, Key::Tab);
+
+            match key {
+                Key::Char(c) => {
+                    line.insert(cursor, c);
+                    cursor += c.len_utf8();
+
+                    // Only characters inserted in front of others need the rest redrawn
+                    if cursor == line.len() {
+                        write!(self.stdout, "{}", c)?;
+                    } else {
+                        self.redraw(prompt, &line, cursor)?;
+                    }
+                }
+                Key::Backspace => {
+                    if let Some(c) = line[..cursor].chars().next_back() {
+                        cursor -= c.len_utf8();
+                        line.remove(cursor);
+                        self.redraw(prompt, &line, cursor)?;
+                    }
+                }
+                Key::Delete => {
+                    if cursor < line.len() {
+                        line.remove(cursor);
+                        self.redraw(prompt, &line, cursor)?;
+                    }
+                }
+                Key::Left => {
+                    if let Some(c) = line[..cursor].chars().next_back() {
+                        cursor -= c.len_utf8();
+                        self.redraw(prompt, &line, cursor)?;
+                    }
+                }
+                Key::Right => {
+                    if let Some(c) = line[cursor..].chars().next() {
+                        cursor += c.len_utf8();
+                        self.redraw(prompt, &line, cursor)?;
+                    }
+                }
+                Key::Home => {
+                    cursor = 0;
+                    self.redraw(prompt, &line, cursor)?;
+                }
+                Key::End => {
+                    cursor = line.len();
+                    self.redraw(prompt, &line, cursor)?;
+                }
+                // Completion works on the whole line, so the cursor moves to its end
+                Key::Tab => {
+                    self.complete_line(prompt, &mut line, tabbed)?;
+                    cursor = line.len();
+                }
+                Key::Up => {
+                    let entries = self.history.entries();
+                    let previous = position.unwrap_or(entries.len()).checked_sub(1);
+
+                    if let Some(previous) = previous {
+                        if position.is_none() {
+                            draft = mem::take(&mut line);
+                        }
+
+                        line = entries[previous].clone();
+                        cursor = line.len();
+                        position = Some(previous);
+                        self.redraw(prompt, &line, cursor)?;
+                    }
+                }
+                Key::Down => {
+                    if let Some(current) = position {
+                        let entries = self.history.entries();
+
+                        if current + 1 < entries.len() {
+                            line = entries[current + 1].clone();
+                            position = Some(current + 1);
+                        } else {
+                            line = mem::take(&mut draft);
+                            position = None;
+                        }
+
+                        cursor = line.len();
+                        self.redraw(prompt, &line, cursor)?;
+                    }
+                }
+                // Ctrl-R searches the history, with the line kept as-is if it is cancelled
+                Key::Search => {
+                    if let Some(found) = self.search_history()? {
+                        line = found;
+                        cursor = line.len();
+                        position = None;
+                    }
+
+                    self.redraw(prompt, &line, cursor)?;
+                }
+                // Ctrl-L clears the screen, then shows the line being edited at the top
+                Key::ClearScreen => {
+                    write!(self.stdout, "{}", CLEAR_SCREEN)?;
+                    self.redraw(prompt, &line, cursor)?;
+                }
+                Key::Enter => {
+                    writeln!(self.stdout)?;
+                    self.stdout.flush()?;
+
+                    input.push_str(&line);
+                    input.push('\n');
+
+                    return Ok(line.len() + 1);
+                }
+                // Ctrl-D only signals the end of input on an empty line
+                Key::Eof if line.is_empty() => return Ok(0),
+                // Ctrl-C discards the line, along with any previous lines of the command
+                Key::Interrupt => {
+                    writeln!(self.stdout, "^C")?;
+                    self.stdout.flush()?;
+
+                    return Err(io::ErrorKind::Interrupted.into());
+                }
+                Key::Eof | Key::Cancel | Key::Unknown => {}
+            }
+
+            tabbed = is_tab;
+            self.stdout.flush()?;
+        }
+    }
+
+    // Search the history for entries containing what is typed, from the newest to the oldest.
+    // Ctrl-R again steps to the next older match, Enter takes the match onto the line, and
+    // Ctrl-C or Ctrl-G cancel the search, which returns `None` then
+    fn search_history(&mut self) -> io::Result<Option<String>> {
+        let mut query = String::new();
+        // Index of the history entry which matched last
+        let mut found: Option<usize> = None;
+        let mut failed = false;
+
+        loop {
+            let matched = found.map_or("", |index| self.history.entries()[index].as_str());
+            let prompt = if failed {
+                FAILED_SEARCH_PROMPT
+            } else {
+                SEARCH_PROMPT
+            };
+
+            write!(
+                self.stdout,
+                "\r{}`{}': {}{}",
+                prompt, query, matched, CLEAR_LINE
+            )?;
+            self.stdout.flush()?;
+
+            // Without a new match the previous one stays selected
+            let start = match Self::read_key(&mut self.stdin)? {
+                Key::Char(c) => {
+                    query.push(c);
+                    found.map_or(self.history.entries().len(), |index| index + 1)
+                }
+                Key::Backspace => {
+                    query.pop();
+                    self.history.entries().len()
+                }
+                Key::Search => found.unwrap_or(self.history.entries().len()),
+                Key::Interrupt | Key::Cancel => return Ok(None),
+                Key::Eof | Key::Unknown => continue,
+                _ => return Ok(found.is_some().then(|| matched.to_owned())),
+            };
+
+            let next = self.history.entries()[..start]
+                .iter()
+                .rposition(|entry| !query.is_empty() && entry.contains(&query));
+
+            failed = next.is_none() && !query.is_empty();
+            found = next.or(found.filter(|_| !query.is_empty()));
+        }
+    }
+
+    fn complete_line(&mut self, prompt: &str, line: &mut String, tabbed: bool) -> io::Result<()> {
+        let (start, candidates) = self.complete(line);
+        let prefix = Self::common_prefix(&candidates);
+
+        // The word may have been typed with quotes, which the candidates replace by escapes
+        if !prefix.is_empty() && prefix != &line[start..] {
+            // Complete up to the longest prefix shared by all candidates
+            line.truncate(start);
+            line.push_str(prefix);
+            self.redraw(prompt, line, line.len())
+        } else if tabbed && candidates.len() > 1 {
+            // List the candidates below the line, then show the line again
+            let names: Vec<_> = candidates
+                .iter()
+                .map(|candidate| Self::display_name(candidate))
+                .collect();
+
+            write!(self.stdout, "\n{}\n", names.join("  "))?;
+            self.redraw(prompt, line, line.len())
+        } else {
+            write!(self.stdout, "{}", BELL)
+        }
+    }
+
+    fn redraw(&mut self, prompt: &str, line: &str, cursor: usize) -> io::Result<()> {
+        // Return to the start of the line, then print prompt and line and clear any leftovers
+        // of a previously longer line
+        write!(self.stdout, "\r{}{}{}", prompt, line, CLEAR_LINE)?;
+
+        // Then move back over the columns the characters after the cursor take up
+        match Self::text_width(&line[cursor..]) {
+            0 => Ok(()),
+            width => write!(self.stdout, "\x1b[{}D", width),
+        }
+    }
+
+    // Number of terminal columns `text` takes up
+    fn text_width(text: &str) -> usize {
+        text.chars().map(Self::char_width).sum()
+    }
+
+    // Wide characters like CJK take up two columns, combining ones like accents none, as they
+    // are drawn on top of the character before. This covers the common ranges, not all of them
+    fn char_width(c: char) -> usize {
+        match u32::from(c) {
+            0x0300..=0x036f | 0x200b..=0x200f | 0xfe00..=0xfe0f => 0,
+            0x1100..=0x115f
+            | 0x2e80..=0x303e
+            | 0x3041..=0x33ff
+            | 0x3400..=0x4dbf
+            | 0x4e00..=0x9fff
+            | 0xa000..=0xa4cf
+            | 0xac00..=0xd7a3
+            | 0xf900..=0xfaff
+            | 0xfe30..=0xfe4f
+            | 0xff00..=0xff60
+            | 0xffe0..=0xffe6
+            | 0x1f300..=0x1f64f
+            | 0x1f900..=0x1f9ff
+            | 0x20000..=0x3fffd => 2,
+            _ => 1,
+        }
+    }
+
+    fn read_key(stdin: &mut R) -> io::Result<Key> {
+        let key = match Self::read_byte(stdin)? {
+            Some(CARRIAGE_RETURN | LINE_FEED) => Key::Enter,
+            Some(DELETE | CTRL_H) => Key::Backspace,
+            Some(TAB) => Key::Tab,
+            Some(CTRL_C) => Key::Interrupt,
+            Some(CTRL_L) => Key::ClearScreen,
+            Some(CTRL_A) => Key::Home,
+            Some(CTRL_E) => Key::End,
+            Some(CTRL_R) => Key::Search,
+            Some(CTRL_G) => Key::Cancel,
+            Some(CTRL_D) | None => Key::Eof,
+            Some(ESCAPE) => Self::read_escape_sequence(stdin)?,
+            Some(byte) if byte.is_ascii_control() => Key::Unknown,
+            Some(byte) => Self::read_char(stdin, byte)?,
+        };
+
+        Ok(key)
+    }
+
+    fn read_escape_sequence(stdin: &mut R) -> io::Result<Key> {
+        if Self::read_byte(stdin)? != Some(CSI) {
+            return Ok(Key::Unknown);
+        }
+
+        // Skip parameter bytes up to the final byte which identifies the sequence. Keys like
+        // Delete send a number before `~` like `\x1b[3~`
+        let mut param = None;
+
+        loop {
+            match Self::read_byte(stdin)? {
+                Some(b'A') => return Ok(Key::Up),
+                Some(b'B') => return Ok(Key::Down),
+                Some(b'C') => return Ok(Key::Right),
+                Some(b'D') => return Ok(Key::Left),
+                Some(b'H') => return Ok(Key::Home),
+                Some(b'F') => return Ok(Key::End),
+                Some(b'~') => {
+                    let key = match param {
+                        Some(b'1' | b'7') => Key::Home,
+                        Some(b'4' | b'8') => Key::End,
+                        Some(b'3') => Key::Delete,
+                        _ => Key::Unknown,
+                    };
+
+                    return Ok(key);
+                }
+                Some(0x40..=0x7e) | None => return Ok(Key::Unknown),
+                Some(byte) => param = Some(byte),
+            }
+        }
+    }
+
+    fn read_char(stdin: &mut R, first: u8) -> io::Result<Key> {
+        // The leading byte determines how many bytes the UTF-8 encoded character has
+        let len = match first.leading_ones() {
+            0 => 1,
+            n @ 2..=4 => n as usize,
+            _ => return Ok(Key::Unknown),
+        };
+
+        let mut bytes = [first, 0, 0, 0];
+        stdin.read_exact(&mut bytes[1..len])?;
+
+        let key = std::str::from_utf8(&bytes[..len])
+            .ok()
+            .and_then(|s| s.chars().next())
+            .map_or(Key::Unknown, Key::Char);
+
+        Ok(key)
+    }
+
+    fn read_byte(stdin: &mut R) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+
+        match stdin.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    const LEFT: &str = "\x1b[D";
+    const RIGHT: &str = "\x1b[C";
+    const HOME: &str = "\x1b[H";
+    const BACKSPACE: &str = "\x7f";
+    const DEL: &str = "\x1b[3~";
+
+    // Edit a line by typing `keys`, returning the line once Enter is pressed along with what
+    // was drawn for it
+    fn edit(keys: &str) -> (String, String) {
+        let mut shell = Shell::builder()
+            .stdin(Cursor::new(format!("{}\r", keys).into_bytes()))
+            .stdout(Vec::new())
+            .stderr(Vec::new())
+            .disable_history()
+            .build();
+
+        let mut input = String::new();
+        let len = shell.edit_line("$ ", &mut input).unwrap();
+        shell.flush().unwrap();
+
+        assert_eq!(len, input.len());
+        let drawn = String::from_utf8(mem::take(shell.stdout())).unwrap();
+
+        (input, drawn)
+    }
+
+    #[test]
+    fn inserts_and_deletes_multibyte_characters() {
+        assert_eq!(edit("日本語").0, "日本語\n");
+        assert_eq!(edit(&format!("日本語{}", BACKSPACE)).0, "日本\n");
+        assert_eq!(edit(&format!("aé{}{}", BACKSPACE, BACKSPACE)).0, "\n");
+        assert_eq!(edit(&format!("😀{}x", BACKSPACE)).0, "x\n");
+        assert_eq!(edit(&format!("a😀b{}{}{}", HOME, RIGHT, DEL)).0, "ab\n");
+    }
+
+    #[test]
+    fn moves_over_whole_characters() {
+        assert_eq!(edit(&format!("aé日{}{}x", LEFT, LEFT)).0, "axé日\n");
+        assert_eq!(
+            edit(&format!("日本{}{}{}x", LEFT, LEFT, RIGHT)).0,
+            "日x本\n"
+        );
+        assert_eq!(edit(&format!("😀😀{}{}x", HOME, RIGHT)).0, "😀x😀\n");
+        // Moving past either end of the line stays there
+        assert_eq!(edit(&format!("é{}{}{}x", LEFT, LEFT, BACKSPACE)).0, "xé\n");
+    }
+
+    #[test]
+    fn deletes_combining_marks_on_their_own() {
+        // The cursor moves by character, so a combining accent is removed before its letter
+        assert_eq!(edit(&format!("e\u{301}{}", BACKSPACE)).0, "e\n");
+        assert_eq!(edit(&format!("e\u{301}{}{}", BACKSPACE, BACKSPACE)).0, "\n");
+        assert_eq!(edit(&format!("e\u{301}{}x", LEFT)).0, "ex\u{301}\n");
+    }
+
+    #[test]
+    fn measures_columns_of_characters() {
+        type TestShell = Shell<Cursor<Vec<u8>>, Vec<u8>, Vec<u8>>;
+
+        assert_eq!(TestShell::text_width("abc"), 3);
+        assert_eq!(TestShell::text_width("日本語"), 6);
+        assert_eq!(TestShell::text_width("한글"), 4);
+        assert_eq!(TestShell::text_width("😀🤖"), 4);
+        assert_eq!(TestShell::text_width("e\u{301}"), 1);
+        assert_eq!(TestShell::text_width("ｗ"), 2);
+    }
+
+    #[test]
+    fn moves_back_over_the_columns_after_the_cursor() {
+        // Inserting in front of wide characters redraws the line, then steps back two columns
+        // for each of them
+        let (line, drawn) = edit(&format!("日本{}x", LEFT));
+
+        assert_eq!(line, "日x本\n");
+        assert!(drawn.ends_with("\r$ 日x本\x1b[K\x1b[2D\n"), "{:?}", drawn);
+
+        let (_, drawn) = edit(&format!("e\u{301}😀{}{}{}", LEFT, LEFT, LEFT));
+        assert!(
+            drawn.ends_with("\r$ e\u{301}😀\x1b[K\x1b[3D\n"),
+            "{:?}",
+            drawn
+        );
+
+        // A combining mark takes up no column of its own
+        let (_, drawn) = edit(&format!("ae\u{301}{}", HOME));
+        assert!(
+            drawn.ends_with("\r$ ae\u{301}\x1b[K\x1b[2D\n"),
+            "{:?}",
+            drawn
+        );
+    }
+}
@@ -1,33 +1,84 @@
-use std::{
-    error, fmt, io, num,
-    path::{self, PathBuf},
-};
+use std::{error, fmt, io, num, os::fd::RawFd, path::PathBuf};
 
 #[derive(Debug)]
 pub enum ShellError {
+    BadFileDescriptor(RawFd),
+    BadSubstitution(String),
+    CannotExecute(String),
     CommandNotFound(String),
+    DirStackEmpty(String),
+    DivisionByZero(String),
     EnvVarNotFound(String),
-    FileOrDirNotFound(PathBuf),
-    HomeDirPathError(path::StripPrefixError),
+    EventNotFound(String),
+    FileOrDirNotFound(String, PathBuf),
     ExitCodeParseError(num::ParseIntError),
     IoError(io::Error),
+    InvalidArithmetic(String),
+    InvalidCondition(String, String),
+    InvalidExpression(String, String),
+    InvalidIdentifier(String, String),
+    InvalidOption(String, String),
+    InvalidProcessId(String, String),
+    InvalidSignal(String),
+    NotAChild(String, String),
+    NotFound(String, String),
     NoArguments,
+    NoSuchJob(String, String),
+    ParameterNotSet(String, String),
+    PermissionDenied(String),
+    RedirectionError(PathBuf, io::ErrorKind),
+    ScriptError(PathBuf, io::ErrorKind),
+    SyntaxError(String),
+    UnboundVariable(String),
+    WriteError(String, io::Error),
+}
+
+impl ShellError {
+    // The exit status of a command which failed with this error, like other shells 127 when
+    // the command or script doesn't exist and 126 when it can't be executed or read
+    pub fn status(&self) -> i32 {
+        match self {
+            Self::CommandNotFound(_) => 127,
+            Self::NotAChild(..) => 127,
+            Self::ScriptError(_, io::ErrorKind::NotFound) => 127,
+            Self::ScriptError(..) => 126,
+            // A broken expression of `test` fails with 2, so it can be told apart from a false one
+            Self::InvalidCondition(..) => 2,
+            Self::CannotExecute(_) | Self::PermissionDenied(_) => 126,
+            _ => 1,
+        }
+    }
 }
 
 impl fmt::Display for ShellError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            Self::BadFileDescriptor(fd) => {
+                write!(f, "{fd}: Bad file descriptor")
+            }
+            Self::BadSubstitution(expr) => {
+                write!(f, "${{{expr}}}: bad substitution")
+            }
+            Self::CannotExecute(cmd) => {
+                write!(f, "{cmd}: cannot execute binary file")
+            }
             Self::CommandNotFound(cmd) => {
                 write!(f, "{cmd}: not found")
             }
+            Self::DirStackEmpty(cmd) => {
+                write!(f, "{cmd}: directory stack empty")
+            }
+            Self::DivisionByZero(expr) => {
+                write!(f, "{expr}: division by 0")
+            }
             Self::EnvVarNotFound(var) => {
                 write!(f, "${} not found", var.to_uppercase())
             }
-            Self::FileOrDirNotFound(path) => {
-                write!(f, "cd: {}: No such file or directory", path.display())
+            Self::EventNotFound(reference) => {
+                write!(f, "{reference}: event not found")
             }
-            Self::HomeDirPathError(error) => {
-                write!(f, "could not strip '~' prefix from path: {error}")
+            Self::FileOrDirNotFound(cmd, path) => {
+                write!(f, "{cmd}: {}: No such file or directory", path.display())
             }
             Self::ExitCodeParseError(error) => {
                 write!(f, "invalid exit code: {error}")
@@ -35,13 +86,88 @@ impl fmt::Display for ShellError {
             Self::IoError(error) => {
                 write!(f, "{error}")
             }
+            Self::InvalidArithmetic(expr) => {
+                write!(f, "{expr}: syntax error in expression")
+            }
+            Self::InvalidCondition(cmd, reason) => {
+                write!(f, "{cmd}: {reason}")
+            }
+            Self::InvalidExpression(cmd, reason) => {
+                write!(f, "{cmd}: {reason}")
+            }
+            Self::InvalidIdentifier(cmd, arg) => {
+                write!(f, "{cmd}: `{arg}': not a valid identifier")
+            }
+            Self::InvalidOption(cmd, option) => {
+                write!(f, "{cmd}: {option}: invalid option")
+            }
+            Self::InvalidProcessId(cmd, arg) => {
+                write!(f, "{cmd}: {arg}: arguments must be process or job IDs")
+            }
+            Self::InvalidSignal(signal) => {
+                write!(f, "kill: {signal}: invalid signal specification")
+            }
+            Self::NotAChild(cmd, pid) => {
+                write!(f, "{cmd}: pid {pid} is not a child of this shell")
+            }
+            Self::NotFound(cmd, name) => {
+                write!(f, "{cmd}: {name}: not found")
+            }
             Self::NoArguments => {
                 write!(f, "arguments are required")
             }
+            Self::NoSuchJob(cmd, spec) => {
+                write!(f, "{cmd}: {spec}: no such job")
+            }
+            Self::ParameterNotSet(name, message) if message.is_empty() => {
+                write!(f, "{name}: parameter null or not set")
+            }
+            Self::ParameterNotSet(name, message) => {
+                write!(f, "{name}: {message}")
+            }
+            Self::PermissionDenied(cmd) => {
+                write!(f, "{cmd}: Permission denied")
+            }
+            Self::RedirectionError(path, kind) => {
+                write!(f, "{}: {}", path.display(), describe_error_kind(kind))
+            }
+            Self::ScriptError(path, kind) => {
+                write!(f, "{}: {}", path.display(), describe_error_kind(kind))
+            }
+            Self::SyntaxError(token) => {
+                write!(f, "syntax error near unexpected token `{token}`")
+            }
+            Self::UnboundVariable(name) => {
+                write!(f, "{name}: unbound variable")
+            }
+            Self::WriteError(cmd, error) => {
+                write!(f, "{cmd}: write error: {}", describe_error(error))
+            }
         }
     }
 }
 
+// Describe an IO error like the shell does, without the OS error code `io::Error` appends
+fn describe_error_kind(kind: &io::ErrorKind) -> String {
+    match kind {
+        io::ErrorKind::NotFound => "No such file or directory".to_owned(),
+        io::ErrorKind::PermissionDenied => "Permission denied".to_owned(),
+        io::ErrorKind::IsADirectory => "Is a directory".to_owned(),
+        io::ErrorKind::AlreadyExists => "cannot overwrite existing file".to_owned(),
+        kind => kind.to_string(),
+    }
+}
+
+// The message of an IO error without the OS error code, like `Bad file descriptor`
+fn describe_error(error: &io::Error) -> String {
+    let message = error.to_string();
+
+    match message.find(" (os error") {
+        Some(end) => message[..end].to_owned(),
+        None => message,
+    }
+}
+
 impl From<io::Error> for ShellError {
     fn from(error: io::Error) -> Self {
         Self::IoError(error)
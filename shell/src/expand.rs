@@ -0,0 +1,775 @@
+use std::{
+    fs,
+    io::{BufRead, Write},
+    iter::Peekable,
+};
+
+use super::{error::ShellError, Result, Shell};
+
+const BRACE_OPEN: char = '{';
+const BRACE_CLOSE: char = '}';
+const UNDERSCORE: char = '_';
+const QUESTION_MARK: char = '?';
+const COLON: char = ':';
+const HASH: char = '#';
+const DASH: char = '-';
+const EQUALS: char = '=';
+const PLUS: char = '+';
+const SLASH: char = '/';
+const PERCENT: char = '%';
+const PAREN_OPEN: char = '(';
+const PAREN_CLOSE: char = ')';
+const SINGLE_QUOTES: char = '\'';
+const DOUBLE_QUOTES: char = '"';
+const BACKSLASH: char = '\\';
+const PROMPT: char = '$';
+const NEWLINE: char = '\n';
+const AT: char = '@';
+const STAR: char = '*';
+
+// Parameters named by a single special character: `$?`, the exit status of the last command,
+// `$#`, the number of positional parameters, and `$@` and `$*`, all of them
+const SPECIAL_PARAMS: [char; 4] = [QUESTION_MARK, HASH, AT, STAR];
+
+pub(super) const BACKTICK: char = '`';
+
+const PASSWD: &str = "/etc/passwd";
+
+impl<R: BufRead, W: Write, E: Write> Shell<R, W, E> {
+    // Expand the variable whose name follows a `$` which was already consumed from `chars`.
+    // Returns `None` if no name follows, in which case the `$` is meant literally
+    pub(super) fn expand_var<I: Iterator<Item = char>>(
+        &mut self,
+        chars: &mut Peekable<I>,
+    ) -> Result<Option<String>> {
+        let name = match chars.peek() {
+            Some(c) if SPECIAL_PARAMS.contains(c) => {
+                let name = chars.next().map(String::from).unwrap_or_default();
+                return Ok(self.param_value(&name));
+            }
+            // `${NAME}` or `${NAME:-word}`, reaching up to the matching closing brace
+            Some(&BRACE_OPEN) => {
+                chars.next();
+                let expr = Self::read_brace_expr(chars);
+                return self.expand_brace_expr(&expr).map(Some);
+            }
+            // Positional parameters like `$1` only consist of a single digit, so in `$1abc` the
+            // name stops after `1`
+            Some(c) if c.is_ascii_digit() => {
+                let name = chars.next().map(String::from).unwrap_or_default();
+                return self.require_param(&name, self.param_value(&name)).map(Some);
+            }
+            // `$NAME`, the name reaches up to the first non-identifier character
+            Some(&c) if Self::is_name_start(c) => {
+                let mut name = String::new();
+
+                while let Some(c) = chars.next_if(|&c| Self::is_name_char(c)) {
+                    name.push(c);
+                }
+                name
+            }
+            // `$` at the end of input or followed by any other character
+            _ => return Ok(None),
+        };
+
+        // Unknown variables expand to an empty string
+        let value = self.variables.get(&name).map(str::to_owned);
+        self.require_param(&name, value).map(Some)
+    }
+
+    // Expand the body of a here-document like text inside `"..."`, except that quotes are
+    // literal. `\` only escapes `$`, `` ` ``, `\` and a newline, which is removed along with it
+    pub(super) fn expand_here_doc(&mut self, body: &str) -> Result<String> {
+        const ESCAPABLE: [char; 4] = [PROMPT, BACKTICK, BACKSLASH, NEWLINE];
+
+        let mut chars = body.chars().peekable();
+        let mut text = String::new();
+
+        while let Some(c) = chars.next() {
+            match c {
+                BACKSLASH => match chars.next_if(|c| ESCAPABLE.contains(c)) {
+                    Some(NEWLINE) => {}
+                    Some(escaped) => text.push(escaped),
+                    None => text.push(c),
+                },
+                PROMPT if chars.next_if_eq(&PAREN_OPEN).is_some() => {
+                    if chars.next_if_eq(&PAREN_OPEN).is_some() {
+                        // Reading up to the matching `)` leaves the second `)` of `))`
+                        let expr = Self::read_paren_cmd(&mut chars);
+                        chars.next_if_eq(&PAREN_CLOSE);
+
+                        text.push_str(&self.expand_arithmetic(&expr)?.to_string());
+                    } else {
+                        text.push_str(&self.substitute(&Self::read_paren_cmd(&mut chars)));
+                    }
+                }
+                BACKTICK => {
+                    text.push_str(&self.substitute(&Self::read_backtick_cmd(&mut chars)));
+                }
+                PROMPT => match self.expand_var(&mut chars)? {
+                    Some(value) => text.push_str(&value),
+                    None => text.push(c),
+                },
+                _ => text.push(c),
+            }
+        }
+
+        Ok(text)
+    }
+
+    // Evaluate the `expr` of a `$((expr))` once the variables and command substitutions in it
+    // are expanded, so `$(( ${#x} * 2 ))` or `$(( $(wc -l < file) + 1 ))` work
+    pub(super) fn expand_arithmetic(&mut self, expr: &str) -> Result<i64> {
+        let expr = self.expand_here_doc(expr)?;
+
+        self.evaluate_arithmetic(&expr)
+    }
+
+    // Expand the contents of a `${...}`, which is a name optionally followed by a modifier:
+    //
+    // - `${NAME:-word}` is `word` if `NAME` is unset or empty
+    // - `${NAME:=word}` is the same, but also assigns `word` to `NAME`
+    // - `${NAME:+word}` is `word` if `NAME` is set and not empty, otherwise empty
+    // - `${NAME:?message}` fails the command with `message` if `NAME` is unset or empty
+    //
+    // Without `:` like in `${NAME-word}` only unset variables count, empty ones don't. `word`
+    // is only expanded when it is used. Besides these, `${#NAME}` is the length of the value
+    // in characters, `${NAME:offset:length}` a part of it, `${NAME/pattern/string}` the
+    // value with `pattern` replaced by `string` and `${NAME#pattern}` and `${NAME%pattern}`
+    // the value without `pattern` at its start or end. The patterns may contain `*`, `?` and
+    // `[...]` like paths
+    fn expand_brace_expr(&mut self, expr: &str) -> Result<String> {
+        if let Some(name) = expr.strip_prefix(HASH).filter(|name| !name.is_empty()) {
+            if !Self::is_special(name) && !Self::is_name(name) && !Self::is_number(name) {
+                return Err(ShellError::BadSubstitution(expr.to_owned()));
+            }
+
+            let value = self.require_param(name, self.param_value(name))?;
+            return Ok(value.chars().count().to_string());
+        }
+
+        let name_len = if expr.starts_with(SPECIAL_PARAMS) {
+            1
+        } else {
+            expr.find(|c| !Self::is_name_char(c)).unwrap_or(expr.len())
+        };
+
+        let (name, modifier) = expr.split_at(name_len);
+
+        if name.is_empty() {
+            return Err(ShellError::BadSubstitution(expr.to_owned()));
+        }
+
+        let value = self.param_value(name);
+
+        let (allow_empty, modifier) = match modifier.strip_prefix(COLON) {
+            Some(modifier) => (false, modifier),
+            None => (true, modifier),
+        };
+
+        let range = modifier;
+        let mut modifier = modifier.chars();
+        let operator = modifier.next();
+        let word = modifier.as_str();
+
+        let is_set = value
+            .as_ref()
+            .is_some_and(|value| allow_empty || !value.is_empty());
+
+        match (operator, value) {
+            (None, value) if allow_empty => self.require_param(name, value),
+            (Some(DASH | EQUALS | QUESTION_MARK), Some(value)) if is_set => Ok(value),
+            (Some(DASH), _) => self.expand_word(word).map(|words| words.join(" ")),
+            (Some(EQUALS), _) => {
+                let word = self.expand_word(word)?.join(" ");
+
+                // Special parameters like `$?` can't be assigned
+                if !Self::is_name(name) {
+                    return Err(ShellError::BadSubstitution(expr.to_owned()));
+                }
+
+                self.variables.set(name, &word);
+                Ok(word)
+            }
+            (Some(PLUS), _) if is_set => self.expand_word(word).map(|words| words.join(" ")),
+            (Some(PLUS), _) => Ok(String::new()),
+            (Some(QUESTION_MARK), _) => {
+                let message = self.expand_word(word)?.join(" ");
+                Err(ShellError::ParameterNotSet(name.to_owned(), message))
+            }
+            (Some(SLASH), value) if allow_empty => {
+                let value = self.require_param(name, value)?;
+                self.replace_pattern(&value, word)
+            }
+            (Some(side @ (HASH | PERCENT)), value) if allow_empty => {
+                let value = self.require_param(name, value)?;
+                self.remove_pattern(&value, word, side == HASH)
+            }
+            // Any other character after `:` starts the offset, which is why a negative one like
+            // in `${NAME: -3}` needs a space to not be read as `:-`
+            (Some(_), value) if !allow_empty => {
+                self.substring(&self.require_param(name, value)?, range)
+            }
+            _ => Err(ShellError::BadSubstitution(expr.to_owned())),
+        }
+    }
+
+    // Replace the first match of the pattern in `value` for `${NAME/pattern/string}`, all of
+    // them for `${NAME//pattern/string}`, and only one at the start or end of `value` for
+    // `${NAME/#pattern/string}` and `${NAME/%pattern/string}`. Without `/string` the matches
+    // are removed. Each match is the longest one possible
+    fn replace_pattern(&mut self, value: &str, expr: &str) -> Result<String> {
+        let (anchor, expr) = match expr.chars().next() {
+            Some(c @ (SLASH | HASH | PERCENT)) => (Some(c), &expr[c.len_utf8()..]),
+            _ => (None, expr),
+        };
+
+        let (pattern, string) = Self::split_pattern(expr);
+        let pattern = self.expand_unsplit(pattern)?.pattern();
+        let string = self.expand_unsplit(string)?;
+        let string = string.text();
+
+        let chars: Vec<_> = value.chars().collect();
+        let len = chars.len();
+
+        let replaced = match anchor {
+            Some(HASH) => (0..=len)
+                .rev()
+                .find(|&end| pattern.matches(&chars[..end]))
+                .map(|end| format!("{}{}", string, Self::collect(&chars[end..]))),
+            Some(PERCENT) => (0..=len)
+                .find(|&start| pattern.matches(&chars[start..]))
+                .map(|start| format!("{}{}", Self::collect(&chars[..start]), string)),
+            _ => {
+                let mut replaced = String::new();
+                let mut start = 0;
+                let mut is_done = false;
+
+                // Empty matches are skipped, as they would match between every character
+                while start < len {
+                    let end = (start + 1..=len)
+                        .rev()
+                        .find(|&end| !is_done && pattern.matches(&chars[start..end]));
+
+                    match end {
+                        Some(end) => {
+                            replaced.push_str(string);
+                            start = end;
+                            is_done = anchor != Some(SLASH);
+                        }
+                        None => {
+                            replaced.push(chars[start]);
+                            start += 1;
+                        }
+                    }
+                }
+
+                Some(replaced)
+            }
+        };
+
+        Ok(replaced.unwrap_or_else(|| value.to_owned()))
+    }
+
+    // Remove the shortest match of the pattern at the start of `value` for `${NAME#pattern}`,
+    // or the longest one for `${NAME##pattern}`. `%` instead of `#` removes it at the end, so
+    // e.g. `${path##*/}` is the last component of a path and `${path%/*}` the rest
+    fn remove_pattern(&mut self, value: &str, expr: &str, at_start: bool) -> Result<String> {
+        let side = if at_start { HASH } else { PERCENT };
+
+        let (longest, expr) = match expr.strip_prefix(side) {
+            Some(expr) => (true, expr),
+            None => (false, expr),
+        };
+
+        let pattern = self.expand_unsplit(expr)?.pattern();
+        let chars: Vec<_> = value.chars().collect();
+        let len = chars.len();
+
+        // Whether the pattern matches the first or last `n` characters
+        let matches = |&n: &usize| match at_start {
+            true => pattern.matches(&chars[..n]),
+            false => pattern.matches(&chars[len - n..]),
+        };
+
+        let removed = if longest {
+            (0..=len).rev().find(matches)
+        } else {
+            (0..=len).find(matches)
+        };
+
+        Ok(match removed {
+            Some(n) if at_start => Self::collect(&chars[n..]),
+            Some(n) => Self::collect(&chars[..len - n]),
+            None => value.to_owned(),
+        })
+    }
+
+    fn collect(chars: &[char]) -> String {
+        chars.iter().collect()
+    }
+
+    // Split `pattern/string` at the first `/` which isn't escaped by `\`, the string is empty
+    // without one
+    fn split_pattern(expr: &str) -> (&str, &str) {
+        let mut escaped = false;
+
+        for (i, c) in expr.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                BACKSLASH => escaped = true,
+                SLASH => return (&expr[..i], &expr[i + 1..]),
+                _ => {}
+            }
+        }
+
+        (expr, "")
+    }
+
+    // With `set -u` using a variable which isn't set is an error, instead of it expanding to an
+    // empty string. Modifiers like `${NAME:-word}` still handle unset variables
+    fn require_param(&self, name: &str, value: Option<String>) -> Result<String> {
+        match value {
+            Some(value) => Ok(value),
+            None if self.flags.nounset => Err(ShellError::UnboundVariable(name.to_owned())),
+            None => Ok(String::new()),
+        }
+    }
+
+    // The value of a variable, or of a special parameter like `$?` or `$1`
+    fn param_value(&self, name: &str) -> Option<String> {
+        match name {
+            "?" => Some(self.last_status.to_string()),
+            "#" => Some(self.positional.len().to_string()),
+            "@" | "*" => Some(self.positional.join(" ")),
+            "0" => Some(self.script_name.clone()),
+            // `${10}` is the tenth positional parameter, `$10` is `$1` followed by `0`
+            _ if Self::is_number(name) => name
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| n.checked_sub(1))
+                .and_then(|n| self.positional.get(n).cloned()),
+            _ => self.variables.get(name).map(str::to_owned),
+        }
+    }
+
+    fn is_special(name: &str) -> bool {
+        let mut chars = name.chars();
+        chars.next().is_some_and(|c| SPECIAL_PARAMS.contains(&c)) && chars.next().is_none()
+    }
+
+    fn is_number(name: &str) -> bool {
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_digit())
+    }
+
+    // Take the characters of `value` described by `range`, which is `offset` or `offset:length`
+    // with both evaluated like in `$((...))`. A negative offset counts from the end, as does a
+    // negative length for where the part ends. Either is clamped to the bounds of `value`
+    fn substring(&self, value: &str, range: &str) -> Result<String> {
+        let chars: Vec<_> = value.chars().collect();
+        let len = chars.len() as i64;
+
+        let (offset, length) = match range.split_once(COLON) {
+            Some((offset, length)) => (offset, Some(length)),
+            None => (range, None),
+        };
+
+        let offset = self.evaluate_arithmetic(offset)?;
+        let start = if offset < 0 { len + offset } else { offset }.clamp(0, len);
+
+        let end = match length {
+            Some(length) => match self.evaluate_arithmetic(length)? {
+                length if length < 0 => len + length,
+                length => start.saturating_add(length),
+            },
+            None => len,
+        };
+        let end = end.clamp(start, len);
+
+        Ok(chars[start as usize..end as usize].iter().collect())
+    }
+
+    // Collect the command of a `$(...)` whose `(` was already consumed from `chars`, up to the
+    // matching `)`. Parentheses of nested substitutions are balanced, quoted ones are ignored
+    pub(super) fn read_paren_cmd<I: Iterator<Item = char>>(chars: &mut I) -> String {
+        Self::read_balanced(chars, PAREN_OPEN, PAREN_CLOSE)
+    }
+
+    // Collect the contents of a `${...}` whose `{` was already consumed from `chars`, up to the
+    // matching `}`, so a nested `${A:-${B}}` is read as a whole
+    pub(super) fn read_brace_expr<I: Iterator<Item = char>>(chars: &mut I) -> String {
+        Self::read_balanced(chars, BRACE_OPEN, BRACE_CLOSE)
+    }
+
+    fn read_balanced<I: Iterator<Item = char>>(chars: &mut I, open: char, close: char) -> String {
+        let mut contents = String::new();
+        let mut depth = 0;
+
+        let mut in_single_quotes = false;
+        let mut in_double_quotes = false;
+
+        while let Some(c) = chars.next() {
+            let is_quoted = in_single_quotes || in_double_quotes;
+
+            match c {
+                SINGLE_QUOTES if !in_double_quotes => in_single_quotes = !in_single_quotes,
+                DOUBLE_QUOTES if !in_single_quotes => in_double_quotes = !in_double_quotes,
+                // Keep escapes for the inner expansion, but don't let them close anything
+                BACKSLASH if !in_single_quotes => {
+                    contents.push(c);
+                    contents.extend(chars.next());
+                    continue;
+                }
+                c if c == open && !is_quoted => depth += 1,
+                c if c == close && !is_quoted => {
+                    if depth == 0 {
+                        break;
+                    }
+                    depth -= 1;
+                }
+                _ => {}
+            }
+
+            contents.push(c);
+        }
+
+        contents
+    }
+
+    // Collect the command of a `` `...` `` whose opening backtick was already consumed from
+    // `chars`, up to the closing one. Inside, `\` only escapes `` ` ``, `$` and `\` itself, so
+    // nested substitutions are written like `` `echo \`pwd\`` ``
+    pub(super) fn read_backtick_cmd<I: Iterator<Item = char>>(chars: &mut I) -> String {
+        let mut cmd = String::new();
+
+        while let Some(c) = chars.next() {
+            match c {
+                BACKTICK => break,
+                BACKSLASH => match chars.next() {
+                    Some(c @ (BACKTICK | PROMPT | BACKSLASH)) => cmd.push(c),
+                    Some(c) => {
+                        cmd.push(BACKSLASH);
+                        cmd.push(c);
+                    }
+                    None => cmd.push(BACKSLASH),
+                },
+                _ => cmd.push(c),
+            }
+        }
+
+        cmd
+    }
+
+    // Decode the contents of a `$'...'` whose opening quote was already consumed from `chars`,
+    // up to the closing quote. Supports the escapes `\t`, `\n`, `\r`, `\0`, `\\`, `\'` and
+    // `\xHH`, any other escape is kept including its backslash
+    pub(super) fn read_ansi_c_quoted<I: Iterator<Item = char>>(chars: &mut Peekable<I>) -> String {
+        let mut decoded = String::new();
+
+        while let Some(c) = chars.next() {
+            if c == SINGLE_QUOTES {
+                break;
+            }
+
+            if c != BACKSLASH {
+                decoded.push(c);
+                continue;
+            }
+
+            match chars.next() {
+                Some('t') => decoded.push('\t'),
+                Some('n') => decoded.push('\n'),
+                Some('r') => decoded.push('\r'),
+                Some('0') => decoded.push('\0'),
+                Some(c @ (BACKSLASH | SINGLE_QUOTES)) => decoded.push(c),
+                // Up to two hex digits like in `\x41`, `\x` without any is kept as-is
+                Some('x') => {
+                    let mut code = 0;
+                    let mut digits = 0;
+
+                    while let Some(digit) = chars
+                        .next_if(|c| digits < 2 && c.is_ascii_hexdigit())
+                        .and_then(|c| c.to_digit(16))
+                    {
+                        code = code * 16 + digit;
+                        digits += 1;
+                    }
+
+                    match char::from_u32(code).filter(|_| digits > 0) {
+                        Some(c) => decoded.push(c),
+                        None => decoded.push_str("\\x"),
+                    }
+                }
+                Some(c) => {
+                    decoded.push(BACKSLASH);
+                    decoded.push(c);
+                }
+                None => decoded.push(BACKSLASH),
+            }
+        }
+
+        decoded
+    }
+
+    // Interpret the escapes `\t`, `\n`, `\r`, `\\`, `\0` and `\a` for `echo -e`, any other
+    // escape is kept including its backslash
+    pub(super) fn interpret_escapes(s: &str) -> String {
+        let mut interpreted = String::with_capacity(s.len());
+        let mut chars = s.chars();
+
+        while let Some(c) = chars.next() {
+            if c != BACKSLASH {
+                interpreted.push(c);
+                continue;
+            }
+
+            match chars.next() {
+                Some('t') => interpreted.push('\t'),
+                Some('n') => interpreted.push('\n'),
+                Some('r') => interpreted.push('\r'),
+                Some('0') => interpreted.push('\0'),
+                Some('a') => interpreted.push('\x07'),
+                Some(BACKSLASH) => interpreted.push(BACKSLASH),
+                Some(c) => {
+                    interpreted.push(BACKSLASH);
+                    interpreted.push(c);
+                }
+                None => interpreted.push(BACKSLASH),
+            }
+        }
+
+        interpreted
+    }
+
+    // Run `cmd` and return its output without trailing newlines, for `$(cmd)` and `` `cmd` ``
+    pub(super) fn substitute(&mut self, cmd: &str) -> String {
+        match self.capture_output(cmd) {
+            Ok(output) => output.trim_end_matches(NEWLINE).to_owned(),
+            Err(error) => {
+                let _ = writeln!(self.stderr, "{}", error);
+                String::new()
+            }
+        }
+    }
+
+    // Expand `~` to `$HOME` or `~user` to the home directory of `user`. Returns `None` if the
+    // user doesn't exist, in which case the tilde is meant literally
+    pub(super) fn expand_tilde(&self, user: &str) -> Option<String> {
+        if user.is_empty() {
+            return self.variables.get("HOME").map(str::to_owned);
+        }
+
+        // Each line of `/etc/passwd` looks like `name:password:uid:gid:gecos:home:shell`
+        fs::read_to_string(PASSWD).ok()?.lines().find_map(|line| {
+            let mut fields = line.split(':');
+
+            (fields.next() == Some(user))
+                .then(|| fields.nth(4).map(str::to_owned))
+                .flatten()
+        })
+    }
+
+    pub(super) fn is_user_char(c: char) -> bool {
+        Self::is_name_char(c) || c == '-' || c == '.'
+    }
+
+    // Whether `name` is a valid variable name like `PATH` or `_private1`
+    pub(super) fn is_name(name: &str) -> bool {
+        let mut chars = name.chars();
+
+        chars.next().is_some_and(Self::is_name_start) && chars.all(Self::is_name_char)
+    }
+
+    fn is_name_start(c: char) -> bool {
+        c.is_ascii_alphabetic() || c == UNDERSCORE
+    }
+
+    fn is_name_char(c: char) -> bool {
+        c.is_ascii_alphanumeric() || c == UNDERSCORE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_arithmetic_before_evaluating() {
+        let mut shell = Shell::for_test();
+        shell.output("x=hello; n=");
+
+        assert_eq!(shell.output("echo $(( $(echo 2) + 1 ))").1, "3\n");
+        assert_eq!(shell.output("echo $(( ${#x} * 2 ))").1, "10\n");
+        assert_eq!(shell.output("echo $(( $n + 1 ))").1, "1\n");
+    }
+
+    #[test]
+    fn expands_default_values() {
+        let mut shell = Shell::for_test();
+        shell.output("unset unset_var; empty=; value=v");
+
+        assert_eq!(shell.output("echo ${unset_var:-word}").1, "word\n");
+        assert_eq!(shell.output("echo ${empty:-word}").1, "word\n");
+        assert_eq!(shell.output("echo ${value:-word}").1, "v\n");
+        // Without the `:` only unset variables count as missing
+        assert_eq!(shell.output("echo \"[${empty-word}]\"").1, "[]\n");
+    }
+
+    #[test]
+    fn assigns_default_values() {
+        let mut shell = Shell::for_test();
+        shell.output("unset assigned");
+
+        assert_eq!(shell.output("echo ${assigned:=first}").1, "first\n");
+        assert_eq!(
+            shell.output("echo ${assigned:=second} $assigned").1,
+            "first first\n"
+        );
+        assert_eq!(shell.variables.get("assigned"), Some("first"));
+    }
+
+    #[test]
+    fn expands_alternative_values() {
+        let mut shell = Shell::for_test();
+        shell.output("unset unset_var; empty=; value=v");
+
+        assert_eq!(shell.output("echo ${value:+alt}").1, "alt\n");
+        assert_eq!(shell.output("echo \"[${empty:+alt}]\"").1, "[]\n");
+        assert_eq!(shell.output("echo \"[${unset_var:+alt}]\"").1, "[]\n");
+    }
+
+    #[test]
+    fn fails_on_missing_values() {
+        let mut shell = Shell::for_test();
+        shell.output("unset unset_var; value=v");
+
+        assert_eq!(shell.output("echo ${value:?missing}").1, "v\n");
+        // The command is aborted, the ones after it still run
+        assert_eq!(
+            shell.output("echo ${unset_var:?is missing} never; echo after"),
+            (0, "after\n".to_owned())
+        );
+        assert_eq!(shell.output("echo ${unset_var:?}; echo $?").1, "1\n");
+
+        let stderr = String::from_utf8(std::mem::take(shell.stderr())).unwrap();
+        assert_eq!(
+            stderr,
+            "unset_var: is missing\nunset_var: parameter null or not set\n"
+        );
+    }
+
+    #[test]
+    fn expands_lengths_of_characters() {
+        let mut shell = Shell::for_test();
+        shell.output("ascii=hello; wide=héllo日本; emoji=😀; empty=");
+
+        assert_eq!(
+            shell.output("echo ${#ascii} ${#wide} ${#emoji}").1,
+            "5 7 1\n"
+        );
+        assert_eq!(shell.output("echo ${#empty} ${#unset_var}").1, "0 0\n");
+    }
+
+    #[test]
+    fn expands_substrings_of_characters() {
+        let mut shell = Shell::for_test();
+        shell.output("x=héllo日本");
+
+        assert_eq!(shell.output("echo ${x:1:3}").1, "éll\n");
+        assert_eq!(shell.output("echo ${x:5}").1, "日本\n");
+        assert_eq!(shell.output("echo ${x: -2} ${x: -3:2}").1, "日本 o日\n");
+        assert_eq!(shell.output("echo ${x:1:-1}").1, "éllo日\n");
+        assert_eq!(shell.output("echo \"[${x:2:0}]\"").1, "[]\n");
+    }
+
+    #[test]
+    fn clamps_substrings_out_of_range() {
+        let mut shell = Shell::for_test();
+        shell.output("x=héllo日本");
+
+        assert_eq!(shell.output("echo \"[${x:10}]\"").1, "[]\n");
+        assert_eq!(shell.output("echo ${x:0:100}").1, "héllo日本\n");
+        assert_eq!(shell.output("echo ${x: -20}").1, "héllo日本\n");
+    }
+
+    #[test]
+    fn replaces_first_and_all_matches() {
+        let mut shell = Shell::for_test();
+        shell.output("p=/usr/local/bin:/usr/bin:/bin");
+
+        assert_eq!(
+            shell.output("echo \"${p/:/ }\"").1,
+            "/usr/local/bin /usr/bin:/bin\n"
+        );
+        assert_eq!(
+            shell.output("echo \"${p//:/ }\"").1,
+            "/usr/local/bin /usr/bin /bin\n"
+        );
+        // Without a replacement the matches are removed
+        assert_eq!(shell.output("echo ${p//bin}").1, "/usr/local/:/usr/:/\n");
+        assert_eq!(
+            shell.output("echo ${p/nomatch/x}").1,
+            "/usr/local/bin:/usr/bin:/bin\n"
+        );
+    }
+
+    #[test]
+    fn replaces_anchored_matches() {
+        let mut shell = Shell::for_test();
+        shell.output("p=bin/usr/bin");
+
+        assert_eq!(shell.output("echo ${p/#bin/X}").1, "X/usr/bin\n");
+        assert_eq!(shell.output("echo ${p/%bin/Y}").1, "bin/usr/Y\n");
+        assert_eq!(shell.output("echo ${p/#usr/Z}").1, "bin/usr/bin\n");
+        // Patterns match like globs, as long as possible
+        assert_eq!(shell.output("echo ${p/b*n/-}").1, "-\n");
+        assert_eq!(shell.output("echo ${p/u?r/-}").1, "bin/-/bin\n");
+    }
+
+    #[test]
+    fn trims_shortest_and_longest_matches() {
+        let mut shell = Shell::for_test();
+        shell.output("f=dir/sub/file.tar.gz");
+
+        assert_eq!(
+            shell.output("echo ${f%.*} ${f%%.*}").1,
+            "dir/sub/file.tar dir/sub/file\n"
+        );
+        assert_eq!(shell.output("echo ${f#*.} ${f##*.}").1, "tar.gz gz\n");
+        assert_eq!(
+            shell.output("echo ${f%.gz} ${f#nomatch}").1,
+            "dir/sub/file.tar dir/sub/file.tar.gz\n"
+        );
+    }
+
+    #[test]
+    fn trims_paths_into_basenames_and_dirnames() {
+        let mut shell = Shell::for_test();
+        shell.output("path=/home/user/notes.txt; name=notes.txt");
+
+        assert_eq!(shell.output("echo ${path##*/}").1, "notes.txt\n");
+        assert_eq!(shell.output("echo ${path%/*}").1, "/home/user\n");
+        assert_eq!(
+            shell.output("echo ${name##*/} ${name%/*}").1,
+            "notes.txt notes.txt\n"
+        );
+        assert_eq!(shell.output("echo ${name%.txt}.md").1, "notes.md\n");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn expands_homes_of_users() {
+        let mut shell = Shell::for_test();
+
+        assert_eq!(
+            shell.output("echo ~root").1,
+            "/root
+"
+        );
+        // An unknown user keeps the word as it is
+        assert_eq!(
+            shell.output("echo ~no-such-user-here/x").1,
+            "~no-such-user-here/x\n"
+        );
+    }
+}
@@ -0,0 +1,58 @@
+// Options of the shell which are toggled with `set`, like `set -e` or `set -o errexit`
+#[derive(Default)]
+pub struct Flags {
+    // Exit as soon as a command fails
+    pub(super) errexit: bool,
+    // Don't let `>` overwrite existing files
+    pub(super) noclobber: bool,
+    // Fail commands which use a variable that isn't set
+    pub(super) nounset: bool,
+    // Print each command before it runs
+    pub(super) xtrace: bool,
+}
+
+// The letter and the name of each option
+const OPTIONS: [(char, &str); 4] = [
+    ('C', "noclobber"),
+    ('e', "errexit"),
+    ('u', "nounset"),
+    ('x', "xtrace"),
+];
+
+impl Flags {
+    // The name of the option with `letter`, like `errexit` for `e`
+    pub(super) fn name_of(letter: char) -> Option<&'static str> {
+        OPTIONS
+            .iter()
+            .find_map(|&(known, name)| (known == letter).then_some(name))
+    }
+
+    // Enable or disable the option `name`, returning whether it exists
+    pub(super) fn set(&mut self, name: &str, enabled: bool) -> bool {
+        match self.option_mut(name) {
+            Some(option) => {
+                *option = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // All options by name along with whether they are enabled
+    pub(super) fn options(&mut self) -> Vec<(&'static str, bool)> {
+        OPTIONS
+            .iter()
+            .map(|&(_, name)| (name, self.option_mut(name).is_some_and(|option| *option)))
+            .collect()
+    }
+
+    fn option_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "errexit" => Some(&mut self.errexit),
+            "noclobber" => Some(&mut self.noclobber),
+            "nounset" => Some(&mut self.nounset),
+            "xtrace" => Some(&mut self.xtrace),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,418 @@
+use std::{
+    fs,
+    io::{BufRead, Write},
+    mem,
+    path::Path,
+};
+
+use super::Shell;
+
+const STAR: char = '*';
+const QUESTION_MARK: char = '?';
+const BACKSLASH: char = '\\';
+const BRACKET_OPEN: char = '[';
+const BRACKET_CLOSE: char = ']';
+const EXCLAMATION_MARK: char = '!';
+const CARET: char = '^';
+const DASH: char = '-';
+const SLASH: char = '/';
+const DOT: char = '.';
+
+// Characters with a special meaning in patterns, which have to be escaped with `\` to match
+// literally
+const GLOB_CHARS: [char; 5] = [STAR, QUESTION_MARK, BACKSLASH, BRACKET_OPEN, BRACKET_CLOSE];
+
+// An argument as it is parsed, along with the pattern it is expanded from. Quoted or escaped
+// characters are escaped in the pattern as well, so only unquoted ones like in `"*".txt` or
+// `\*.txt` are special
+#[derive(Default)]
+pub struct Word {
+    text: String,
+    pattern: String,
+    // Whether the pattern contains any unquoted special characters
+    is_glob: bool,
+}
+
+// A pattern matched against text instead of paths, like in `${NAME%pattern}`
+pub struct Pattern(Vec<Token>);
+
+enum Token {
+    Char(char),
+    // `?` matches any single character
+    Any,
+    // `*` matches any sequence of characters, including an empty one
+    Star,
+    // `[...]` matches a single character out of the ranges, or any other one if negated by
+    // `[!...]` or `[^...]`. Single characters are stored as ranges like `('a', 'a')`
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Word {
+    pub(super) fn push(&mut self, c: char) {
+        if GLOB_CHARS.contains(&c) {
+            self.pattern.push(BACKSLASH);
+        }
+
+        self.text.push(c);
+        self.pattern.push(c);
+    }
+
+    pub(super) fn push_str(&mut self, s: &str) {
+        s.chars().for_each(|c| self.push(c));
+    }
+
+    // Push an unquoted special character like `*`
+    pub(super) fn push_glob(&mut self, c: char) {
+        self.text.push(c);
+        self.pattern.push(c);
+        self.is_glob = true;
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    pub(super) fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub(super) fn pattern(&self) -> Pattern {
+        Pattern(<Shell>::tokenize_pattern(&self.pattern))
+    }
+
+    // Expand the word into the matching paths, or keep it as-is if it is no pattern or nothing
+    // matches it
+    pub(super) fn expand(&mut self) -> Vec<String> {
+        let word = mem::take(self);
+
+        if word.is_glob {
+            let paths = <Shell>::glob(&word.pattern);
+
+            if !paths.is_empty() {
+                return paths;
+            }
+        }
+
+        vec![word.text]
+    }
+}
+
+impl Pattern {
+    // Unlike for paths `*` and `?` also match `/` and a leading `.`
+    pub(super) fn matches(&self, text: &[char]) -> bool {
+        <Shell>::matches(&self.0, text)
+    }
+}
+
+impl<R: BufRead, W: Write, E: Write> Shell<R, W, E> {
+    // Expand `pattern` into the sorted paths matching it. Characters escaped with `\` match
+    // literally, hidden files are skipped unless the pattern starts with `.` as well
+    pub(super) fn glob(pattern: &str) -> Vec<String> {
+        let (mut paths, pattern) = match pattern.strip_prefix(SLASH) {
+            Some(pattern) => (vec![SLASH.to_string()], pattern),
+            None => (vec![String::new()], pattern),
+        };
+
+        // Match each path component against the entries of the directories matched so far
+        for component in pattern.split(SLASH) {
+            paths = if component.is_empty() {
+                // A trailing `/` only matches directories
+                paths
+                    .into_iter()
+                    .filter(|path| Path::new(path).is_dir())
+                    .map(|path| path + "/")
+                    .collect()
+            } else if !Self::is_glob(component) {
+                let name = Self::unescape(component);
+
+                paths
+                    .into_iter()
+                    .map(|path| Self::join(&path, &name))
+                    .collect()
+            } else {
+                let tokens = Self::tokenize_pattern(component);
+
+                paths
+                    .into_iter()
+                    .flat_map(|path| Self::match_dir(&path, component, &tokens))
+                    .collect()
+            };
+        }
+
+        // Literal components after the last pattern aren't checked while matching
+        paths.retain(|path| Path::new(path).symlink_metadata().is_ok());
+        paths.sort();
+        paths
+    }
+
+    fn match_dir(dir: &str, component: &str, tokens: &[Token]) -> Vec<String> {
+        let Ok(entries) = fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+            return Vec::new();
+        };
+
+        entries
+            .flatten()
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|name| !name.starts_with(DOT) || component.starts_with(DOT))
+            .filter(|name| Self::matches(tokens, &name.chars().collect::<Vec<_>>()))
+            .map(|name| Self::join(dir, &name))
+            .collect()
+    }
+
+    fn matches(tokens: &[Token], name: &[char]) -> bool {
+        let (mut t, mut n) = (0, 0);
+        // Positions after the last `*` in the pattern and the name, to retry from when the
+        // rest doesn't match, with the `*` then consuming one character more
+        let mut retry = None;
+
+        while n < name.len() {
+            match tokens.get(t) {
+                Some(Token::Star) => {
+                    t += 1;
+                    retry = Some((t, n));
+                    continue;
+                }
+                Some(Token::Any) => {
+                    t += 1;
+                    n += 1;
+                    continue;
+                }
+                Some(Token::Char(c)) if *c == name[n] => {
+                    t += 1;
+                    n += 1;
+                    continue;
+                }
+                Some(Token::Class { negated, ranges })
+                    if ranges
+                        .iter()
+                        .any(|&(start, end)| (start..=end).contains(&name[n]))
+                        != *negated =>
+                {
+                    t += 1;
+                    n += 1;
+                    continue;
+                }
+                _ => {}
+            }
+
+            match retry {
+                Some((star_t, star_n)) => {
+                    t = star_t;
+                    n = star_n + 1;
+                    retry = Some((star_t, n));
+                }
+                None => return false,
+            }
+        }
+
+        // Only `*` may be left in the pattern, matching the empty rest of the name
+        tokens[t..].iter().all(|token| matches!(token, Token::Star))
+    }
+
+    fn tokenize_pattern(pattern: &str) -> Vec<Token> {
+        let chars: Vec<_> = pattern.chars().collect();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+
+        while i < chars.len() {
+            let token = match chars[i] {
+                STAR => Token::Star,
+                QUESTION_MARK => Token::Any,
+                BRACKET_OPEN => match Self::parse_class(&chars[i + 1..]) {
+                    Some((class, len)) => {
+                        i += len;
+                        class
+                    }
+                    // Without a closing `]` the `[` matches literally
+                    None => Token::Char(BRACKET_OPEN),
+                },
+                // An escaped character matches itself, a trailing `\` matches literally
+                BACKSLASH => {
+                    i += 1;
+                    Token::Char(chars.get(i).copied().unwrap_or(BACKSLASH))
+                }
+                c => Token::Char(c),
+            };
+
+            tokens.push(token);
+            i += 1;
+        }
+
+        tokens
+    }
+
+    // Parse the bracket expression following a `[`, returning it along with the number of
+    // characters it spans including the closing `]`. Returns `None` if it is never closed
+    fn parse_class(chars: &[char]) -> Option<(Token, usize)> {
+        let negated = matches!(chars.first(), Some(&(EXCLAMATION_MARK | CARET)));
+        let start = usize::from(negated);
+
+        let mut ranges = Vec::new();
+        let mut i = start;
+
+        loop {
+            let mut c = *chars.get(i)?;
+
+            // A `]` right at the start like in `[]a]` is part of the class instead of closing it
+            if c == BRACKET_CLOSE && i > start {
+                return Some((Token::Class { negated, ranges }, i + 1));
+            }
+
+            if c == BACKSLASH {
+                i += 1;
+                c = *chars.get(i)?;
+            }
+
+            // `a-z` is a range, while a `-` at the end like in `[a-]` matches literally
+            let end = match (chars.get(i + 1), chars.get(i + 2)) {
+                (Some(&DASH), Some(&end)) if end != BRACKET_CLOSE => {
+                    i += 2;
+
+                    if end == BACKSLASH {
+                        i += 1;
+                        *chars.get(i)?
+                    } else {
+                        end
+                    }
+                }
+                _ => c,
+            };
+
+            ranges.push((c, end));
+            i += 1;
+        }
+    }
+
+    // Whether `pattern` contains any unescaped special characters
+    fn is_glob(pattern: &str) -> bool {
+        let mut chars = pattern.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                BACKSLASH => {
+                    chars.next();
+                }
+                STAR | QUESTION_MARK | BRACKET_OPEN => return true,
+                _ => {}
+            }
+        }
+
+        false
+    }
+
+    fn unescape(pattern: &str) -> String {
+        let mut name = String::new();
+        let mut chars = pattern.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                BACKSLASH => name.extend(chars.next().or(Some(c))),
+                _ => name.push(c),
+            }
+        }
+
+        name
+    }
+
+    fn join(dir: &str, name: &str) -> String {
+        if dir.is_empty() {
+            name.to_owned()
+        } else if dir.ends_with(SLASH) {
+            format!("{}{}", dir, name)
+        } else {
+            format!("{}{}{}", dir, SLASH, name)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn matches(pattern: &str, name: &str) -> bool {
+        Pattern(<Shell>::tokenize_pattern(pattern)).matches(&name.chars().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn matches_ranges_and_sets() {
+        assert!(matches("file[0-9].txt", "file7.txt"));
+        assert!(!matches("file[0-9].txt", "filea.txt"));
+        assert!(matches("[abc]*.md", "balance.md"));
+        assert!(!matches("[abc]*.md", "data.md"));
+        assert!(matches("[a-cx-z]", "y"));
+        assert!(!matches("[a-cx-z]", "m"));
+    }
+
+    #[test]
+    fn matches_negated_classes() {
+        assert!(matches("[!a]", "b"));
+        assert!(!matches("[!a]", "a"));
+        assert!(matches("[^0-9]x", "ax"));
+        assert!(!matches("[^0-9]x", "5x"));
+    }
+
+    #[test]
+    fn matches_literal_dashes_and_brackets() {
+        // A `-` at the end and a `]` at the start are part of the class
+        assert!(matches("[a-]", "-"));
+        assert!(matches("[a-]", "a"));
+        assert!(!matches("[a-]", "b"));
+        assert!(matches("[]a]", "]"));
+        assert!(matches("[]a]", "a"));
+        assert!(!matches("[]a]", "b"));
+        assert!(matches("[!]a]", "b"));
+        assert!(!matches("[!]a]", "]"));
+        assert!(matches("[\\]]", "]"));
+        // Without a closing `]` the `[` is literal
+        assert!(matches("[ab", "[ab"));
+        assert!(!matches("[ab", "a"));
+    }
+
+    #[test]
+    fn combines_classes_with_stars_and_question_marks() {
+        assert!(matches("*[0-9]?.txt", "log12.txt"));
+        assert!(matches("*[0-9]?.txt", "9a.txt"));
+        assert!(!matches("*[0-9]?.txt", "log1.txt"));
+        assert!(matches("?[!.]*", "ab.c"));
+        assert!(!matches("?[!.]*", "a.bc"));
+    }
+
+    #[test]
+    fn expands_classes_into_paths() {
+        let dir = env::temp_dir().join(format!("shell-glob-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        for name in [
+            "file1.txt",
+            "file2.txt",
+            "filea.txt",
+            "a.md",
+            "b.md",
+            "d.md",
+        ] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+
+        let dir_name = dir.display().to_string();
+        let glob = |pattern: &str| {
+            <Shell>::glob(&format!("{}/{}", dir_name, pattern))
+                .into_iter()
+                .map(|path| path[dir_name.len() + 1..].to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(glob("file[0-9].txt"), ["file1.txt", "file2.txt"]);
+        assert_eq!(glob("[abc]*.md"), ["a.md", "b.md"]);
+        assert_eq!(glob("[!a]*.md"), ["b.md", "d.md"]);
+        assert_eq!(glob("file[!0-9].*"), ["filea.txt"]);
+        assert!(glob("[xyz]*").is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
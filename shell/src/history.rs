@@ -0,0 +1,443 @@
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+};
+
+use super::{error::ShellError, variables::Variables, Result};
+
+const HISTORY_FILE: &str = ".shell_history";
+const DEFAULT_HISTSIZE: usize = 500;
+
+// Options of `$HISTCONTROL` separated by `:`, `ignoreboth` is short for both of them
+const IGNORE_DUPS: &str = "ignoredups";
+const IGNORE_SPACE: &str = "ignorespace";
+const IGNORE_BOTH: &str = "ignoreboth";
+
+const BANG: char = '!';
+const SINGLE_QUOTES: char = '\'';
+const BACKSLASH: char = '\\';
+const NEWLINE: char = '\n';
+
+// Characters which end a reference like `!ls` in `!ls;date`
+const REFERENCE_ENDS: &str = ";|&<>()'\"=";
+
+// Characters which keep a `!` right before them literal, like in `[ "$a" != b ]`
+const LITERAL_BANG_ENDS: &str = "=(";
+
+pub struct History {
+    entries: Vec<String>,
+    // Number of the newest entries which were added since loading, only those are saved
+    unsaved: usize,
+    // File the history is loaded from and saved to, `None` to keep it only in memory
+    path: Option<PathBuf>,
+}
+
+impl History {
+    // The file in `$HOME` of the process which shells keep their history in by default, `None`
+    // if `$HOME` is not set
+    pub fn default_path() -> Option<PathBuf> {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE))
+    }
+
+    pub fn load(path: Option<PathBuf>, variables: &Variables) -> Self {
+        let entries = path.as_deref().map(Self::read).unwrap_or_default();
+
+        let mut history = Self {
+            entries,
+            unsaved: 0,
+            path,
+        };
+        history.truncate(variables);
+        history
+    }
+
+    // A missing or unreadable history file just starts an empty history
+    fn read(path: &Path) -> Vec<String> {
+        fs::read_to_string(path)
+            .map(|contents| contents.lines().map(Self::decode).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn push(&mut self, line: &str, variables: &Variables) {
+        // With `ignorespace` a leading space keeps a line out of the history
+        if line.starts_with(' ') && Self::ignores(IGNORE_SPACE, variables) {
+            return;
+        }
+
+        let line = line.trim();
+
+        if line.is_empty() {
+            return;
+        }
+
+        // With `ignoredups` a line repeating the previous one is only recorded once
+        let is_dup = self.entries.last().is_some_and(|last| last == line);
+
+        if is_dup && Self::ignores(IGNORE_DUPS, variables) {
+            return;
+        }
+
+        self.entries.push(line.to_owned());
+        self.unsaved += 1;
+        self.truncate(variables);
+    }
+
+    // Whether `$HISTCONTROL` enables `option`
+    fn ignores(option: &str, variables: &Variables) -> bool {
+        variables.get("HISTCONTROL").is_some_and(|control| {
+            control
+                .split(':')
+                .any(|known| known == option || known == IGNORE_BOTH)
+        })
+    }
+
+    // Append the entries added since loading to the file, so shells running at the same time
+    // don't overwrite each other's history
+    pub fn save(&mut self, variables: &Variables) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let unsaved = &self.entries[self.entries.len() - self.unsaved..];
+
+        // Only the user may read the file, as commands may contain secrets
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .mode(0o600)
+            .open(path)?;
+
+        Self::write(file, unsaved)?;
+        self.unsaved = 0;
+
+        // The file is kept as short as the history, dropping its oldest entries
+        let mut entries = Self::read(path);
+        let size = Self::size(variables);
+
+        if entries.len() > size {
+            entries.drain(..entries.len() - size);
+
+            let file = OpenOptions::new()
+                .write(true)
+                .truncate(true)
+                .create(true)
+                .mode(0o600)
+                .open(path)?;
+
+            Self::write(file, &entries)?;
+        }
+
+        Ok(())
+    }
+
+    fn write(file: File, entries: &[String]) -> io::Result<()> {
+        let mut file = BufWriter::new(file);
+
+        for entry in entries {
+            writeln!(file, "{}", Self::encode(entry))?;
+        }
+
+        file.flush()
+    }
+
+    // Entries are stored one per line, so the newlines of a command spanning several lines are
+    // written as `\n`, and backslashes as `\\` to tell them apart
+    fn encode(entry: &str) -> String {
+        entry.replace(BACKSLASH, "\\\\").replace(NEWLINE, "\\n")
+    }
+
+    fn decode(line: &str) -> String {
+        let mut entry = String::with_capacity(line.len());
+        let mut chars = line.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            let decoded = match c {
+                BACKSLASH => match chars.next_if(|&next| next == 'n' || next == BACKSLASH) {
+                    Some('n') => NEWLINE,
+                    _ => BACKSLASH,
+                },
+                c => c,
+            };
+
+            entry.push(decoded);
+        }
+
+        entry
+    }
+
+    // Replace references to earlier commands in `line`, returning `None` if there are none.
+    // `!!` is the previous command, `!n` the one numbered `n` by `history`, `!-n` the nth
+    // previous one and `!prefix` the most recent one starting with `prefix`. References aren't
+    // expanded inside single quotes or after a `\`
+    pub fn expand(&self, line: &str) -> Result<Option<String>> {
+        let mut expanded = String::new();
+        let mut in_single_quotes = false;
+        let mut changed = false;
+
+        let mut rest = line;
+
+        while let Some(c) = rest.chars().next() {
+            let mut len = c.len_utf8();
+
+            match c {
+                SINGLE_QUOTES => in_single_quotes = !in_single_quotes,
+                // The escaped character is kept along with the backslash
+                BACKSLASH if !in_single_quotes => {
+                    len += rest[1..].chars().next().map_or(0, char::len_utf8);
+                }
+                // A `!` followed by whitespace, `=`, `(` or nothing at all is literal
+                BANG if !in_single_quotes && !Self::is_literal_bang(&rest[1..]) => {
+                    let reference = Self::read_reference(&rest[1..]);
+
+                    if !reference.is_empty() {
+                        let entry = self.find_entry(reference).ok_or_else(|| {
+                            ShellError::EventNotFound(format!("{}{}", BANG, reference))
+                        })?;
+
+                        expanded.push_str(entry);
+                        rest = &rest[1 + reference.len()..];
+                        changed = true;
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+
+            expanded.push_str(&rest[..len]);
+            rest = &rest[len..];
+        }
+
+        Ok(changed.then_some(expanded))
+    }
+
+    fn is_literal_bang(rest: &str) -> bool {
+        rest.chars()
+            .next()
+            .is_none_or(|c| c.is_whitespace() || LITERAL_BANG_ENDS.contains(c))
+    }
+
+    // The reference following a `!`, like `!` of `!!` or `ls` of `!ls`
+    fn read_reference(rest: &str) -> &str {
+        if rest.starts_with(BANG) {
+            return &rest[..1];
+        }
+
+        let len = rest
+            .find(|c: char| c.is_whitespace() || c == BANG || REFERENCE_ENDS.contains(c))
+            .unwrap_or(rest.len());
+
+        &rest[..len]
+    }
+
+    fn find_entry(&self, reference: &str) -> Option<&str> {
+        let index = if reference.starts_with(BANG) {
+            self.entries.len().checked_sub(1)
+        } else {
+            self.find(reference, self.entries.len())
+        };
+
+        index
+            .and_then(|index| self.entries.get(index))
+            .map(String::as_str)
+    }
+
+    // Find the index of the entry `reference` refers to among the first `len` entries. It is
+    // either numbered like by `history`, counted back from the newest entry like `-2`, or a
+    // prefix of the entry
+    pub fn find(&self, reference: &str, len: usize) -> Option<usize> {
+        let entries = &self.entries[..len.min(self.entries.len())];
+
+        let index = if let Some(back) = reference.strip_prefix('-') {
+            back.parse()
+                .ok()
+                .and_then(|back| entries.len().checked_sub(back))
+        } else if let Ok(number) = reference.parse::<usize>() {
+            number.checked_sub(1)
+        } else {
+            entries
+                .iter()
+                .rposition(|entry| entry.starts_with(reference))
+        };
+
+        index.filter(|&index| index < entries.len())
+    }
+
+    // Forget the newest entry, like the `fc` being run which is replaced by what it runs
+    pub fn remove_last(&mut self) {
+        self.entries.pop();
+        self.unsaved = self.unsaved.saturating_sub(1);
+    }
+
+    // Keep the history only in memory from now on, like in forked copies of the shell, which
+    // would otherwise save the entries the shell itself saves
+    pub fn disable_saving(&mut self) {
+        self.path = None;
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    fn truncate(&mut self, variables: &Variables) {
+        // Only keep the most recent `$HISTSIZE` entries
+        let size = Self::size(variables);
+
+        if self.entries.len() > size {
+            self.entries.drain(..self.entries.len() - size);
+        }
+
+        self.unsaved = self.unsaved.min(self.entries.len());
+    }
+
+    fn size(variables: &Variables) -> usize {
+        variables
+            .get("HISTSIZE")
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(DEFAULT_HISTSIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    fn history(entries: &[&str]) -> History {
+        History {
+            entries: entries.iter().map(|entry| entry.to_string()).collect(),
+            unsaved: 0,
+            path: None,
+        }
+    }
+
+    #[test]
+    fn expands_references() {
+        let history = history(&["echo one", "ls -l", "echo two"]);
+
+        let expand = |line| history.expand(line).unwrap();
+
+        assert_eq!(expand("!!"), Some("echo two".to_owned()));
+        assert_eq!(expand("!2 | wc"), Some("ls -l | wc".to_owned()));
+        assert_eq!(expand("!-3"), Some("echo one".to_owned()));
+        assert_eq!(expand("!ls;date"), Some("ls -l;date".to_owned()));
+        assert_eq!(expand("!ec"), Some("echo two".to_owned()));
+    }
+
+    #[test]
+    fn keeps_literal_bangs() {
+        let history = history(&["echo one"]);
+
+        for line in [
+            "[ \"$a\" != b ]",
+            "test x != y",
+            "echo hi!",
+            "echo ! x",
+            "echo !(x)",
+            "echo '!!'",
+            "echo \\!!",
+        ] {
+            assert_eq!(history.expand(line).unwrap(), None, "{}", line);
+        }
+    }
+
+    #[test]
+    fn encodes_newlines_and_backslashes() {
+        for entry in [
+            "echo a\\ b",
+            "for i in 1 2\ndo echo $i\ndone",
+            "echo \\n",
+            "\\",
+        ] {
+            let encoded = History::encode(entry);
+
+            assert!(!encoded.contains(NEWLINE));
+            assert_eq!(History::decode(&encoded), entry);
+        }
+    }
+
+    #[test]
+    fn appends_entries_of_each_shell() {
+        let path = env::temp_dir().join(format!("shell-history-test-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut first = History {
+            path: Some(path.clone()),
+            ..history(&[])
+        };
+        let mut second = History {
+            path: Some(path.clone()),
+            ..history(&[])
+        };
+
+        let variables = Variables::default();
+
+        first.push("echo first", &variables);
+        second.push("if true\nthen echo second\nfi", &variables);
+        first.save(&variables).unwrap();
+        second.save(&variables).unwrap();
+
+        // Saving again doesn't write the same entries twice
+        first.save(&variables).unwrap();
+
+        let entries = History::read(&path);
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(entries, ["echo first", "if true\nthen echo second\nfi"]);
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn ignores_lines_by_histcontrol() {
+        let lines = ["echo a", "echo a", " echo b", "echo c"];
+
+        let pushed = |control: &str| {
+            let mut variables = Variables::default();
+            variables.set("HISTCONTROL", control);
+
+            let mut history = history(&[]);
+
+            for line in lines {
+                history.push(line, &variables);
+            }
+
+            history.entries
+        };
+
+        assert_eq!(pushed(""), ["echo a", "echo a", "echo b", "echo c"]);
+        assert_eq!(pushed(IGNORE_DUPS), ["echo a", "echo b", "echo c"]);
+        assert_eq!(pushed(IGNORE_SPACE), ["echo a", "echo a", "echo c"]);
+        assert_eq!(pushed(IGNORE_BOTH), ["echo a", "echo c"]);
+        assert_eq!(pushed("ignorespace:ignoredups"), ["echo a", "echo c"]);
+    }
+
+    #[test]
+    fn keeps_histsize_entries() {
+        let mut variables = Variables::default();
+        variables.set("HISTSIZE", "2");
+
+        let mut history = history(&[]);
+
+        for line in ["echo one", "echo two", "echo three"] {
+            history.push(line, &variables);
+        }
+
+        assert_eq!(history.entries, ["echo two", "echo three"]);
+        assert_eq!(history.unsaved, 2);
+    }
+
+    #[test]
+    fn fails_on_missing_events() {
+        let history = history(&["echo one"]);
+
+        assert!(matches!(
+            history.expand("!nope"),
+            Err(ShellError::EventNotFound(reference)) if reference == "!nope"
+        ));
+        assert!(history.expand("!5").is_err());
+    }
+}
@@ -0,0 +1,392 @@
+use std::{
+    io::{self, BufRead, Write},
+    mem,
+    os::{raw::c_int, unix::process::ExitStatusExt},
+};
+
+use super::{
+    error::ShellError,
+    pipeline::Stage,
+    sys::{self, Pid},
+    Result, Shell,
+};
+
+// A pipeline started in the background with `&`, or stopped like by Ctrl-Z while it ran in
+// the foreground
+pub struct Job {
+    id: usize,
+    // Command line the job was started with, for reporting it later on
+    cmd: String,
+    processes: Vec<Process>,
+    stopped: bool,
+}
+
+// A command of a job, along with its exit code once it finished
+struct Process {
+    stage: Stage,
+    status: Option<i32>,
+}
+
+impl Job {
+    pub(super) fn new(cmd: String, stages: impl IntoIterator<Item = Stage>) -> Self {
+        let processes = stages
+            .into_iter()
+            .map(|stage| Process {
+                stage,
+                status: None,
+            })
+            .collect();
+
+        Self {
+            id: 0,
+            cmd,
+            processes,
+            stopped: false,
+        }
+    }
+
+    // With job control every command of the job is in the process group of the first one
+    fn process_group(&self) -> Option<Pid> {
+        self.processes.first().map(|process| process.stage.pid())
+    }
+
+    // Reap the commands which finished without blocking, returning the exit code of the job
+    // once all of them did
+    fn try_wait(&mut self) -> io::Result<Option<i32>> {
+        for process in &mut self.processes {
+            if process.status.is_none() {
+                process.status = process.stage.try_wait()?.map(<Shell>::exit_code);
+            }
+        }
+
+        Ok(self.status())
+    }
+
+    // Block until every command of the job finished or was stopped, returning the exit code
+    // of the job unless it was stopped
+    fn wait(&mut self) -> io::Result<Option<i32>> {
+        self.stopped = false;
+
+        for index in 0..self.processes.len() {
+            self.wait_process(index)?;
+        }
+
+        match self.stopped {
+            true => Ok(None),
+            false => Ok(Some(self.status().unwrap_or_default())),
+        }
+    }
+
+    // Block until the command at `index` finished or was stopped, unless it already finished
+    fn wait_process(&mut self, index: usize) -> io::Result<()> {
+        let process = &mut self.processes[index];
+
+        if process.status.is_some() {
+            return Ok(());
+        }
+
+        let status = process.stage.wait()?;
+
+        match status.stopped_signal() {
+            Some(_) => self.stopped = true,
+            None => process.status = Some(<Shell>::exit_code(status)),
+        }
+
+        Ok(())
+    }
+
+    // Continue the commands of a stopped job
+    fn resume(&mut self) -> io::Result<()> {
+        for process in self
+            .processes
+            .iter()
+            .filter(|process| process.status.is_none())
+        {
+            sys::kill(process.stage.pid(), sys::SIGCONT)?;
+        }
+
+        self.stopped = false;
+        Ok(())
+    }
+
+    fn status(&self) -> Option<i32> {
+        // Like in the foreground, the exit code of a pipeline is the one of its last command
+        let statuses: Option<Vec<_>> = self
+            .processes
+            .iter()
+            .map(|process| process.status)
+            .collect();
+
+        statuses?.last().copied()
+    }
+}
+
+impl<R: BufRead, W: Write, E: Write> Shell<R, W, E> {
+    pub(super) fn start_job(&mut self, cmd: &str) -> Result<()> {
+        // Commands which failed to start were already reported and are left out of the job
+        let stages = self.spawn_pipeline()?.into_iter().flatten();
+        let mut job = Job::new(cmd.to_owned(), stages);
+
+        let Some(last) = job.processes.last() else {
+            self.last_status = 1;
+            return Ok(());
+        };
+
+        job.id = self.next_job_id();
+        writeln!(self.stdout, "[{}] {}", job.id, last.stage.pid())?;
+
+        self.jobs.push(job);
+        Ok(())
+    }
+
+    // Ids count up from the newest job, so they start at 1 again once all jobs finished
+    fn next_job_id(&self) -> usize {
+        self.jobs.last().map_or(1, |job| job.id + 1)
+    }
+
+    // Wait for a job in the foreground, which has the terminal to itself until it finished or
+    // was stopped like by Ctrl-Z. A stopped job is added to the jobs and can be continued by
+    // `fg` or `bg`
+    pub(super) fn wait_foreground(&mut self, mut job: Job) -> Result<()> {
+        let pgid = job.process_group().filter(|_| self.job_control);
+
+        if let Some(pgid) = pgid {
+            sys::set_foreground(pgid)?;
+        }
+
+        let result = match job.stopped {
+            true => job.resume().and_then(|_| job.wait()),
+            false => job.wait(),
+        };
+
+        // The shell takes back the terminal even if waiting failed
+        if pgid.is_some() {
+            sys::set_foreground(sys::process_group())?;
+        }
+
+        match result? {
+            Some(status) => self.last_status = status,
+            None => {
+                // Like a command killed by a signal
+                self.last_status = 128 + sys::SIGTSTP;
+
+                if job.id == 0 {
+                    job.id = self.next_job_id();
+                }
+
+                // On a line of its own, as Ctrl-Z doesn't end the line the command was on
+                writeln!(self.stdout, "\n[{}]+ Stopped {}", job.id, job.cmd)?;
+                self.push_job(job);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Add a job brought back from the foreground, keeping the jobs ordered by id
+    fn push_job(&mut self, job: Job) {
+        let index = self.jobs.partition_point(|other| other.id < job.id);
+        self.jobs.insert(index, job);
+    }
+
+    // Wait for the job `spec` refers to like it was started in the foreground, continuing it if
+    // it is stopped. Without job control the job already shares the terminal with the shell
+    pub(super) fn foreground_job(&mut self, spec: Option<&str>) -> Result<()> {
+        let job = self.take_job("fg", spec)?;
+
+        // Show which job is continued, like other shells do
+        writeln!(self.stdout, "{}", job.cmd)?;
+        self.flush()?;
+
+        self.wait_foreground(job)
+    }
+
+    // Continue the stopped job `spec` refers to in the background
+    pub(super) fn background_job(&mut self, spec: Option<&str>) -> Result<()> {
+        let index = self.find_job("bg", spec)?;
+        let marker = self.job_marker(index);
+        let job = &mut self.jobs[index];
+
+        if !job.stopped {
+            return Err(ShellError::InvalidExpression(
+                "bg".to_owned(),
+                format!("job {} already in background", job.id),
+            ));
+        }
+
+        job.resume()?;
+        writeln!(self.stdout, "[{}]{} {} &", job.id, marker, job.cmd)?;
+
+        Ok(())
+    }
+
+    // Wait for the jobs `specs` refer to, with the status of the last one, or for all jobs if
+    // none are given. Like for `kill` jobs are given like `%1`, anything else is the process id
+    // of one of their commands which is waited for on its own
+    pub(super) fn wait_jobs(&mut self, specs: &[String]) -> Result<()> {
+        if specs.is_empty() {
+            for mut job in mem::take(&mut self.jobs) {
+                // Stopped jobs would never finish, so they are kept
+                if job.stopped || job.wait()?.is_none() {
+                    self.push_job(job);
+                }
+            }
+
+            return Ok(());
+        }
+
+        for spec in specs {
+            let (mut job, process) = match spec.starts_with('%') {
+                true => (self.take_job("wait", Some(spec))?, None),
+                false => {
+                    let (job, index) = self.take_process_job("wait", spec)?;
+                    (job, Some(index))
+                }
+            };
+
+            let status = match (job.stopped, process) {
+                (true, _) => None,
+                (false, None) => job.wait()?,
+                (false, Some(index)) => {
+                    job.wait_process(index)?;
+                    job.processes[index].status
+                }
+            };
+
+            match status {
+                Some(status) => {
+                    self.last_status = status;
+
+                    // The other commands of the job may still be running
+                    if job.status().is_none() {
+                        self.push_job(job);
+                    }
+                }
+                None => {
+                    self.last_status = 128 + sys::SIGTSTP;
+                    self.push_job(job);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Send `signal` to every command of the job `spec` refers to which is still running
+    pub(super) fn signal_job(&mut self, spec: &str, signal: c_int) -> Result<()> {
+        let index = self.find_job("kill", Some(spec))?;
+        let job = &mut self.jobs[index];
+
+        for process in job
+            .processes
+            .iter()
+            .filter(|process| process.status.is_none())
+        {
+            sys::kill(process.stage.pid(), signal)?;
+        }
+
+        // A stopped job only gets the signal once it continues
+        if job.stopped && !sys::is_stop_signal(signal) {
+            job.resume()?;
+        }
+
+        // Reap the commands which already terminated, the job is then reported as finished at
+        // the next prompt
+        job.try_wait()?;
+        Ok(())
+    }
+
+    fn take_job(&mut self, cmd: &str, spec: Option<&str>) -> Result<Job> {
+        let index = self.find_job(cmd, spec)?;
+
+        Ok(self.jobs.remove(index))
+    }
+
+    // Take the job with the command of process id `spec`, along with the index of the command
+    fn take_process_job(&mut self, cmd: &str, spec: &str) -> Result<(Job, usize)> {
+        let pid = spec
+            .parse::<Pid>()
+            .map_err(|_| ShellError::InvalidProcessId(cmd.to_owned(), spec.to_owned()))?;
+
+        let found = self.jobs.iter().enumerate().find_map(|(index, job)| {
+            job.processes
+                .iter()
+                .position(|process| process.stage.pid() == pid)
+                .map(|process| (index, process))
+        });
+
+        let (index, process) =
+            found.ok_or_else(|| ShellError::NotAChild(cmd.to_owned(), spec.to_owned()))?;
+
+        Ok((self.jobs.remove(index), process))
+    }
+
+    // Find the index of the job `spec` refers to. `%n` refers to job `n`, `%-` to the previous
+    // job and `%+`, `%%` or no spec at all to the current one, i.e. the newest
+    fn find_job(&self, cmd: &str, spec: Option<&str>) -> Result<usize> {
+        let index = match spec.map(|spec| spec.strip_prefix('%').unwrap_or(spec)) {
+            None | Some("+" | "%") => self.jobs.len().checked_sub(1),
+            Some("-") => self.jobs.len().checked_sub(2),
+            Some(id) => id
+                .parse()
+                .ok()
+                .and_then(|id| self.jobs.iter().position(|job| job.id == id)),
+        };
+
+        index.ok_or_else(|| {
+            ShellError::NoSuchJob(cmd.to_owned(), spec.unwrap_or("current").to_owned())
+        })
+    }
+
+    // Report the jobs which finished since the last prompt, then forget about them
+    pub(super) fn notify_jobs(&mut self) -> io::Result<()> {
+        self.report_jobs(false)
+    }
+
+    // Report finished jobs and remove them, running ones are only listed if `all` is set
+    pub(super) fn report_jobs(&mut self, all: bool) -> io::Result<()> {
+        let mut i = 0;
+
+        while i < self.jobs.len() {
+            let marker = self.job_marker(i);
+
+            match self.jobs[i].try_wait()? {
+                Some(status) => {
+                    let job = self.jobs.remove(i);
+                    let status = Self::describe_status(status);
+
+                    writeln!(self.stdout, "[{}]{} {} {}", job.id, marker, status, job.cmd)?;
+                }
+                None => {
+                    let job = &self.jobs[i];
+
+                    if all && job.stopped {
+                        writeln!(self.stdout, "[{}]{} Stopped {}", job.id, marker, job.cmd)?;
+                    } else if all {
+                        writeln!(self.stdout, "[{}]{} Running {} &", job.id, marker, job.cmd)?;
+                    }
+
+                    i += 1;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // `+` marks the most recent job and `-` the one before
+    fn job_marker(&self, index: usize) -> char {
+        match self.jobs.len() - index {
+            1 => '+',
+            2 => '-',
+            _ => ' ',
+        }
+    }
+
+    fn describe_status(status: i32) -> String {
+        match status {
+            0 => "Done".to_owned(),
+            code => format!("Exit {}", code),
+        }
+    }
+}
@@ -0,0 +1,555 @@
+use std::{
+    fmt,
+    io::{BufRead, Write},
+    mem,
+    os::fd::RawFd,
+};
+
+use super::{error::ShellError, expand::BACKTICK, Result, Shell};
+
+const SINGLE_QUOTES: char = '\'';
+const DOUBLE_QUOTES: char = '"';
+const BACKSLASH: char = '\\';
+const SPACE: char = ' ';
+const NEWLINE: char = '\n';
+const TAB: char = '\t';
+const PROMPT: char = '$';
+const HASH: char = '#';
+const PAREN_OPEN: char = '(';
+const BRACE_OPEN: char = '{';
+
+pub(super) const PIPE: &str = "|";
+pub(super) const AND: &str = "&&";
+pub(super) const OR: &str = "||";
+pub(super) const SEMICOLON: &str = ";";
+pub(super) const BACKGROUND: &str = "&";
+
+const STDIN_FD: RawFd = 0;
+const STDOUT_FD: RawFd = 1;
+
+// A word or an operator of the input
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    // A word as it was typed, including its quotes and escapes. These are only removed when the
+    // word is expanded right before its command runs, so e.g. `$?` refers to the command before
+    Word(String),
+    Pipe,
+    // `<` or `0<` for stdin, `3<` for another file descriptor
+    RedirectIn(RawFd),
+    // `>` or `1>` for stdout, `2>` for stderr and so on
+    RedirectOut(RawFd),
+    // `>>` or `1>>` for stdout, `2>>` for stderr and so on
+    RedirectAppend(RawFd),
+    // `>|` or `2>|`, overwriting the file even if `set -C` is enabled
+    RedirectForce(RawFd),
+    // `2>&1`, `>&2` or `3<&0`, pointing the first file descriptor to where the second one points
+    Duplicate(RawFd, RawFd),
+    // `2>&-` or `<&-`, closing the file descriptor
+    Close(RawFd),
+    // `<<< word`, feeding the word followed by a newline to the file descriptor
+    HereString(RawFd),
+    // `<<EOF`, feeding the following lines up to `EOF` to the file descriptor. The delimiter is
+    // the word after the operator, and the body is read once the line ends. `<<-EOF` removes
+    // leading tabs from the lines
+    HereDoc {
+        fd: RawFd,
+        strip_tabs: bool,
+        delimiter: Option<String>,
+        body: Option<String>,
+    },
+    And,
+    Or,
+    Semicolon,
+    // An unquoted line break, which ends a command like `;`
+    Newline,
+    Background,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Word(word) => write!(f, "{}", word),
+            Self::Pipe => write!(f, "{}", PIPE),
+            Self::RedirectIn(STDIN_FD) => write!(f, "<"),
+            Self::RedirectIn(fd) => write!(f, "{}<", fd),
+            Self::RedirectOut(STDOUT_FD) => write!(f, ">"),
+            Self::RedirectOut(fd) => write!(f, "{}>", fd),
+            Self::RedirectAppend(STDOUT_FD) => write!(f, ">>"),
+            Self::RedirectAppend(fd) => write!(f, "{}>>", fd),
+            Self::RedirectForce(STDOUT_FD) => write!(f, ">|"),
+            Self::RedirectForce(fd) => write!(f, "{}>|", fd),
+            Self::Duplicate(STDIN_FD, target) => write!(f, "<&{}", target),
+            Self::Duplicate(STDOUT_FD, target) => write!(f, ">&{}", target),
+            Self::Duplicate(fd, target) => write!(f, "{}>&{}", fd, target),
+            Self::Close(STDIN_FD) => write!(f, "<&-"),
+            Self::Close(STDOUT_FD) => write!(f, ">&-"),
+            Self::Close(fd) => write!(f, "{}>&-", fd),
+            Self::HereString(STDIN_FD) => write!(f, "<<<"),
+            Self::HereString(fd) => write!(f, "{}<<<", fd),
+            Self::HereDoc {
+                fd,
+                strip_tabs,
+                delimiter,
+                ..
+            } => {
+                if *fd != STDIN_FD {
+                    write!(f, "{}", fd)?;
+                }
+
+                let dash = if *strip_tabs { "-" } else { "" };
+                write!(f, "<<{}{}", dash, delimiter.as_deref().unwrap_or_default())
+            }
+            Self::And => write!(f, "{}", AND),
+            Self::Or => write!(f, "{}", OR),
+            Self::Semicolon => write!(f, "{}", SEMICOLON),
+            Self::Newline => write!(f, "newline"),
+            Self::Background => write!(f, "{}", BACKGROUND),
+        }
+    }
+}
+
+impl<R: BufRead, W: Write, E: Write> Shell<R, W, E> {
+    // Split the input into words and operators. Operators inside quotes or substitutions don't
+    // end a word, so `echo "a | b" $(ls | wc -l)` is a single command of three words
+    pub fn tokenize(input: &str) -> Result<Vec<Token>> {
+        let mut tokens = Vec::new();
+        let mut word = String::new();
+
+        let mut in_single_quotes = false;
+        let mut in_double_quotes = false;
+
+        let mut rest = input;
+
+        while let Some(c) = rest.chars().next() {
+            let is_quoted = in_single_quotes || in_double_quotes;
+
+            // Unquoted whitespace and operators end the current word
+            if !is_quoted && matches!(c, SPACE | TAB | NEWLINE) {
+                Self::push_word(&mut word, &mut tokens);
+                rest = &rest[c.len_utf8()..];
+
+                // The bodies of here-documents follow the line of their operators
+                if c == NEWLINE {
+                    tokens.push(Token::Newline);
+                    rest = Self::read_here_docs(&mut tokens, rest)?;
+                }
+                continue;
+            }
+
+            // An unquoted `#` starting a word comments out the rest of the line, while one
+            // inside a word like in `a#b` is literal
+            if !is_quoted && c == HASH && word.is_empty() {
+                rest = rest.find(NEWLINE).map_or("", |i| &rest[i..]);
+                continue;
+            }
+
+            let operator = if is_quoted {
+                None
+            } else {
+                Self::read_operator(rest)?
+            };
+
+            if let Some((mut token, len)) = operator {
+                // A single digit right before a redirection like in `2>` is the file descriptor
+                // to redirect instead of an argument
+                if let [digit @ b'0'..=b'9'] = word.as_bytes() {
+                    if Self::set_redirect_fd(&mut token, RawFd::from(digit - b'0')) {
+                        word.clear();
+                    }
+                }
+
+                Self::push_word(&mut word, &mut tokens);
+                tokens.push(token);
+                rest = &rest[len..];
+                continue;
+            }
+
+            let len = match c {
+                SINGLE_QUOTES if !in_double_quotes => {
+                    in_single_quotes = !in_single_quotes;
+                    c.len_utf8()
+                }
+                DOUBLE_QUOTES if !in_single_quotes => {
+                    in_double_quotes = !in_double_quotes;
+                    c.len_utf8()
+                }
+                // An escaped character belongs to the word, whatever it is
+                BACKSLASH if !in_single_quotes => {
+                    c.len_utf8() + rest[1..].chars().next().map_or(0, char::len_utf8)
+                }
+                // Substitutions belong to the word as a whole
+                PROMPT if !in_single_quotes && rest[1..].starts_with(PAREN_OPEN) => {
+                    let mut chars = rest[2..].chars();
+                    Self::read_paren_cmd(&mut chars);
+                    rest.len() - chars.as_str().len()
+                }
+                // `${...}` like `${A:-a b}` may contain spaces
+                PROMPT if !in_single_quotes && rest[1..].starts_with(BRACE_OPEN) => {
+                    let mut chars = rest[2..].chars();
+                    Self::read_brace_expr(&mut chars);
+                    rest.len() - chars.as_str().len()
+                }
+                BACKTICK if !in_single_quotes => {
+                    let mut chars = rest[1..].chars();
+                    Self::read_backtick_cmd(&mut chars);
+                    rest.len() - chars.as_str().len()
+                }
+                // `$'...'` may contain escaped quotes like in `$'it\'s'`
+                PROMPT if !is_quoted && rest[1..].starts_with(SINGLE_QUOTES) => {
+                    let mut chars = rest[2..].chars();
+                    Self::skip_ansi_c_quoted(&mut chars);
+                    rest.len() - chars.as_str().len()
+                }
+                _ => c.len_utf8(),
+            };
+
+            word.push_str(&rest[..len]);
+            rest = &rest[len..];
+        }
+
+        Self::push_word(&mut word, &mut tokens);
+        Self::read_here_docs(&mut tokens, rest)?;
+
+        Ok(tokens)
+    }
+
+    // Read the bodies of the here-documents whose operators are on the line which just ended,
+    // returning the input after them. A body missing its delimiter reaches up to the end of
+    // the input
+    fn read_here_docs<'a>(tokens: &mut [Token], mut rest: &'a str) -> Result<&'a str> {
+        for token in tokens {
+            let Token::HereDoc {
+                strip_tabs,
+                delimiter,
+                body: body @ None,
+                ..
+            } = token
+            else {
+                continue;
+            };
+
+            // A here-document needs a delimiter like in `cat <<EOF`
+            let delimiter = delimiter
+                .as_deref()
+                .map(Self::unquote_delimiter)
+                .ok_or_else(|| ShellError::SyntaxError("newline".to_owned()))?;
+
+            let mut text = String::new();
+
+            while !rest.is_empty() {
+                let (line, next) = rest.split_once(NEWLINE).unwrap_or((rest, ""));
+                rest = next;
+
+                let line = if *strip_tabs {
+                    line.trim_start_matches(TAB)
+                } else {
+                    line
+                };
+
+                if line == delimiter {
+                    break;
+                }
+
+                text.push_str(line);
+                text.push(NEWLINE);
+            }
+
+            *body = Some(text);
+        }
+
+        Ok(rest)
+    }
+
+    // The delimiter of a here-document with quotes removed, so `'EOF'` ends at a line `EOF`
+    pub(super) fn unquote_delimiter(delimiter: &str) -> String {
+        let mut chars = delimiter.chars();
+        let mut unquoted = String::new();
+
+        while let Some(c) = chars.next() {
+            match c {
+                SINGLE_QUOTES | DOUBLE_QUOTES => {}
+                BACKSLASH => unquoted.extend(chars.next()),
+                c => unquoted.push(c),
+            }
+        }
+
+        unquoted
+    }
+
+    // Read the operator at the start of `rest`, returning it along with its length in bytes
+    fn read_operator(rest: &str) -> Result<Option<(Token, usize)>> {
+        // `&&` and `||` have to be matched before `&` and `|`
+        let operator = match rest.as_bytes() {
+            [b'|', b'|', ..] => (Token::Or, 2),
+            [b'|', ..] => (Token::Pipe, 1),
+            [b'&', b'&', ..] => (Token::And, 2),
+            [b'&', ..] => (Token::Background, 1),
+            [b';', ..] => (Token::Semicolon, 1),
+            [b'<', b'&', b'-', ..] => (Token::Close(STDIN_FD), 3),
+            [b'<', b'&', digit @ b'0'..=b'9', ..] => {
+                (Token::Duplicate(STDIN_FD, RawFd::from(digit - b'0')), 3)
+            }
+            // Duplicating needs a file descriptor to copy, or `-` to close it
+            [b'<', b'&', ..] => return Err(ShellError::SyntaxError("<&".to_owned())),
+            [b'<', b'<', b'<', ..] => (Token::HereString(STDIN_FD), 3),
+            [b'<', b'<', b'-', ..] => (Self::here_doc(true), 3),
+            [b'<', b'<', ..] => (Self::here_doc(false), 2),
+            [b'<', ..] => (Token::RedirectIn(STDIN_FD), 1),
+            [b'>', b'>', ..] => (Token::RedirectAppend(STDOUT_FD), 2),
+            [b'>', b'|', ..] => (Token::RedirectForce(STDOUT_FD), 2),
+            [b'>', b'&', b'-', ..] => (Token::Close(STDOUT_FD), 3),
+            [b'>', b'&', digit @ b'0'..=b'9', ..] => {
+                (Token::Duplicate(STDOUT_FD, RawFd::from(digit - b'0')), 3)
+            }
+            [b'>', b'&', ..] => return Err(ShellError::SyntaxError(">&".to_owned())),
+            [b'>', ..] => (Token::RedirectOut(STDOUT_FD), 1),
+            _ => return Ok(None),
+        };
+
+        Ok(Some(operator))
+    }
+
+    fn here_doc(strip_tabs: bool) -> Token {
+        Token::HereDoc {
+            fd: STDIN_FD,
+            strip_tabs,
+            delimiter: None,
+            body: None,
+        }
+    }
+
+    // Redirect `fd` instead of the default file descriptor of `token`, returning whether `token`
+    // is a redirection
+    fn set_redirect_fd(token: &mut Token, fd: RawFd) -> bool {
+        match token {
+            Token::RedirectIn(default)
+            | Token::RedirectOut(default)
+            | Token::RedirectAppend(default)
+            | Token::RedirectForce(default)
+            | Token::Duplicate(default, _)
+            | Token::Close(default)
+            | Token::HereString(default)
+            | Token::HereDoc { fd: default, .. } => {
+                *default = fd;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn skip_ansi_c_quoted<I: Iterator<Item = char>>(chars: &mut I) {
+        while let Some(c) = chars.next() {
+            match c {
+                SINGLE_QUOTES => break,
+                BACKSLASH => {
+                    chars.next();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn push_word(word: &mut String, tokens: &mut Vec<Token>) {
+        // Each separator ends the word, so runs of them like in `echo   a  b` or whitespace
+        // around the line must not produce empty words
+        if word.is_empty() {
+            return;
+        }
+
+        // The word after `<<` is the delimiter of the here-document instead of an argument
+        if let Some(Token::HereDoc {
+            delimiter: delimiter @ None,
+            ..
+        }) = tokens.last_mut()
+        {
+            *delimiter = Some(mem::take(word));
+            return;
+        }
+
+        tokens.push(Token::Word(mem::take(word)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(input: &str) -> Vec<Token> {
+        input
+            .split_whitespace()
+            .map(|word| Token::Word(word.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn collapses_repeated_separators() {
+        assert_eq!(
+            <Shell>::tokenize("echo   a    b").unwrap(),
+            words("echo a b")
+        );
+        assert_eq!(
+            <Shell>::tokenize("echo\t\ta \t b").unwrap(),
+            words("echo a b")
+        );
+        assert_eq!(
+            <Shell>::tokenize("echo a  |   wc").unwrap(),
+            [
+                Token::Word("echo".to_owned()),
+                Token::Word("a".to_owned()),
+                Token::Pipe,
+                Token::Word("wc".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_leading_and_trailing_whitespace() {
+        assert_eq!(<Shell>::tokenize("   echo a").unwrap(), words("echo a"));
+        assert_eq!(<Shell>::tokenize("echo    a").unwrap(), words("echo a"));
+        assert_eq!(<Shell>::tokenize("echo a \t  ").unwrap(), words("echo a"));
+        assert_eq!(<Shell>::tokenize(" \techo a  ").unwrap(), words("echo a"));
+    }
+
+    #[test]
+    fn keeps_quoted_whitespace() {
+        assert_eq!(
+            <Shell>::tokenize("echo  'a  b'   \" c \"").unwrap(),
+            [
+                Token::Word("echo".to_owned()),
+                Token::Word("'a  b'".to_owned()),
+                Token::Word("\" c \"".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn gives_no_tokens_for_whitespace() {
+        for input in ["", " ", "   ", "\t \t"] {
+            assert_eq!(<Shell>::tokenize(input).unwrap(), [], "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn parses_words_into_args() {
+        let mut shell = Shell::for_test();
+
+        let commands = shell.parse_input("  echo   a    b  ").unwrap();
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].cmd, "echo");
+        assert_eq!(commands[0].args, ["a", "b"]);
+
+        assert_eq!(shell.output("echo   a    b   "), (0, "a b\n".to_owned()));
+        assert_eq!(shell.output("   \t  "), (0, String::new()));
+    }
+
+    #[test]
+    fn splits_operators_from_words() {
+        let word = |word: &str| Token::Word(word.to_owned());
+
+        assert_eq!(
+            <Shell>::tokenize("a|b&&c||d;e&").unwrap(),
+            [
+                word("a"),
+                Token::Pipe,
+                word("b"),
+                Token::And,
+                word("c"),
+                Token::Or,
+                word("d"),
+                Token::Semicolon,
+                word("e"),
+                Token::Background,
+            ]
+        );
+        assert_eq!(
+            <Shell>::tokenize("echo hi>out 2>>err <in").unwrap(),
+            [
+                word("echo"),
+                word("hi"),
+                Token::RedirectOut(1),
+                word("out"),
+                Token::RedirectAppend(2),
+                word("err"),
+                Token::RedirectIn(0),
+                word("in"),
+            ]
+        );
+        assert_eq!(
+            <Shell>::tokenize("cmd 2>&1 >&- 3>|f").unwrap(),
+            [
+                word("cmd"),
+                Token::Duplicate(2, 1),
+                Token::Close(1),
+                Token::RedirectForce(3),
+                word("f"),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_operators_in_quotes_and_substitutions() {
+        assert_eq!(
+            <Shell>::tokenize("echo 'a|b' \"c > d\" $(ls | wc -l) a\\;b").unwrap(),
+            [
+                Token::Word("echo".to_owned()),
+                Token::Word("'a|b'".to_owned()),
+                Token::Word("\"c > d\"".to_owned()),
+                Token::Word("$(ls | wc -l)".to_owned()),
+                Token::Word("a\\;b".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn runs_echo_and_redirects_from_tokens() {
+        let mut shell = Shell::for_test();
+
+        assert_eq!(
+            shell.output("echo a \"b  c\" 'd|e' f\\ g"),
+            (0, "a b  c d|e f g\n".to_owned())
+        );
+        assert_eq!(
+            shell.output("echo hidden > /dev/null; echo shown 2>/dev/null"),
+            (0, "shown\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn drops_comments() {
+        let mut shell = Shell::for_test();
+
+        assert_eq!(shell.output("echo a # b c"), (0, "a\n".to_owned()));
+        assert_eq!(shell.output("# only a comment"), (0, String::new()));
+        assert_eq!(shell.output("echo a;# b"), (0, "a\n".to_owned()));
+        assert_eq!(
+            shell.output("echo one # first\necho two"),
+            (0, "one\ntwo\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn keeps_hashes_inside_words_and_quotes() {
+        let mut shell = Shell::for_test();
+
+        assert_eq!(shell.output("echo a#b"), (0, "a#b\n".to_owned()));
+        assert_eq!(
+            shell.output("echo '#a' \"# b\" \\#c"),
+            (0, "#a # b #c\n".to_owned())
+        );
+        assert_eq!(shell.output("x=1; echo ${#x} $#"), (0, "1 0\n".to_owned()));
+    }
+
+    #[test]
+    fn separates_words_by_tabs() {
+        let mut shell = Shell::for_test();
+
+        assert_eq!(shell.output("echo\ta\t\tb"), (0, "a b\n".to_owned()));
+        assert_eq!(shell.output("\t echo a\t \t"), (0, "a\n".to_owned()));
+        assert_eq!(shell.output("x=1;\techo\t$x"), (0, "1\n".to_owned()));
+        // Tabs inside quotes stay part of the word
+        assert_eq!(
+            shell.output("echo 'a\tb' \"c\td\" $'e\\tf'"),
+            (0, "a\tb c\td e\tf\n".to_owned())
+        );
+    }
+}
@@ -0,0 +1,2329 @@
+mod arithmetic;
+mod builder;
+mod completion;
+mod compound;
+mod condition;
+mod editor;
+mod error;
+mod expand;
+mod flags;
+mod glob;
+mod history;
+mod jobs;
+mod lexer;
+mod pipeline;
+mod prompt;
+mod sys;
+mod trap;
+mod utils;
+mod variables;
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{
+    self, BufRead, BufReader, BufWriter, IsTerminal, Read, Stderr, StdinLock, Stdout, Write,
+};
+use std::os::fd::RawFd;
+use std::os::raw::c_int;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Component, Path, PathBuf};
+use std::process::ExitStatus;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use std::{env, process};
+
+use self::completion::PathIndex;
+use self::compound::{Control, Item};
+use self::editor::CLEAR_SCREEN;
+use self::flags::Flags;
+use self::history::History;
+use self::jobs::Job;
+use self::pipeline::Stage;
+use self::sys::{STDERR_FD, STDIN_FD, STDOUT_FD};
+use self::utils::Redirects;
+use self::variables::Variables;
+
+pub use self::builder::ShellBuilder;
+pub use self::error::ShellError;
+pub use self::lexer::Token;
+pub use self::utils::{ParsedCommand, Redirect};
+
+pub type Result<T> = std::result::Result<T, ShellError>;
+
+// A builtin runs inside the shell with the command's arguments already loaded into `Shell`
+type Run<R, W, E> = fn(&mut Shell<R, W, E>) -> Result<()>;
+
+struct Builtin<R: BufRead, W: Write, E: Write> {
+    run: Run<R, W, E>,
+    // Shown by `help`, `usage` like `cd [dir]` along with what the builtin does
+    usage: &'static str,
+    description: &'static str,
+}
+
+// Not derived, the streams don't need to be `Copy` for the function pointer to be
+impl<R: BufRead, W: Write, E: Write> Clone for Builtin<R, W, E> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<R: BufRead, W: Write, E: Write> Copy for Builtin<R, W, E> {}
+
+// Where the shell writes its output to. The shell of the process, forked copies of it like for
+// `$(...)` and builtins with redirected output write straight to the file descriptor `fd`
+// instead of the writer. Unlike through `io::stdout()`, writes to a closed one then fail
+struct Output<W> {
+    writer: W,
+    fd: Option<RawFd>,
+    // Set while output which couldn't be written is thrown away, see `flush_output()`
+    discard: bool,
+}
+
+impl<W: Write> Output<W> {
+    fn new(writer: W, fd: Option<RawFd>) -> Self {
+        Self {
+            writer,
+            fd,
+            discard: false,
+        }
+    }
+}
+
+impl<W: Write> Write for Output<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.fd {
+            _ if self.discard => Ok(buf.len()),
+            Some(fd) => sys::write(fd, buf),
+            None => self.writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.fd {
+            Some(_) => Ok(()),
+            None => self.writer.flush(),
+        }
+    }
+}
+
+const RC_FILE: &str = ".shellrc";
+const TIME: &str = "time";
+// Used by `fc` if neither `$FCEDIT` nor `$EDITOR` is set
+const DEFAULT_EDITOR: &str = "vi";
+// Number of entries `fc -l` lists by default
+const FC_LIST_LEN: usize = 16;
+
+pub struct Shell<R: BufRead = StdinLock<'static>, W: Write = Stdout, E: Write = Stderr> {
+    cmd: String,
+    args: Option<Vec<String>>,
+    assignments: Vec<(String, String)>,
+    redirects: Vec<Redirect>,
+    pipeline: Vec<ParsedCommand>,
+    last_status: i32,
+    // `$0`, and `$1` onwards of a script or `-c` run with arguments
+    script_name: String,
+    positional: Vec<String>,
+    // Where `getopts` continues inside a word of grouped options like `-ab`, as the value of
+    // `$OPTIND` it applies to along with the offset of the next option
+    option_group: (usize, usize),
+    // Number of loops currently running, and whether `break` or `continue` is unwinding them
+    loop_depth: usize,
+    control: Option<Control>,
+    // Functions defined like `greet() { ... }` by name, and the number of them currently running
+    functions: HashMap<String, Rc<Vec<Item>>>,
+    function_depth: usize,
+    // Whether commands are typed at the prompt, which adds them to the history before they run
+    interactive: bool,
+    // Whether commands run in process groups of their own, which take turns owning the terminal
+    job_control: bool,
+    // Actions set by `trap` by signal number, 0 for when the shell exits
+    traps: HashMap<c_int, String>,
+    // Whether stdin of the shell currently points to a redirection of a builtin
+    stdin_redirected: bool,
+    // Where commands were found in `$PATH` before, see `hash`
+    hashed: HashMap<String, PathBuf>,
+    // Directories saved by `pushd`, the last one is the top of the stack
+    dir_stack: Vec<PathBuf>,
+    aliases: HashMap<String, String>,
+    variables: Variables,
+    flags: Flags,
+    builtins: HashMap<&'static str, Builtin<R, W, E>>,
+    jobs: Vec<Job>,
+    history: History,
+    path_index: PathIndex,
+    // Where commands are read from, and whether that is a terminal which they are typed at with
+    // the line editor
+    stdin: R,
+    tty: bool,
+    stdout: BufWriter<Output<W>>,
+    stderr: BufWriter<Output<E>>,
+}
+
+impl Shell {
+    pub fn new(stdout: Stdout, stderr: Stderr) -> Self {
+        let stdin = io::stdin();
+        let tty = stdin.is_terminal();
+
+        let history_file = History::default_path();
+        let mut shell = Self::with_streams(stdin.lock(), tty, stdout, stderr, history_file);
+        shell.stdout.get_mut().fd = Some(STDOUT_FD);
+        shell.stderr.get_mut().fd = Some(STDERR_FD);
+        shell
+    }
+
+    // Configure the shell before it starts, like its streams or which builtins it has
+    pub fn builder() -> ShellBuilder {
+        ShellBuilder::new()
+    }
+}
+
+impl<R: BufRead, W: Write, E: Write> Shell<R, W, E> {
+    fn with_streams(
+        stdin: R,
+        tty: bool,
+        stdout: W,
+        stderr: E,
+        history_file: Option<PathBuf>,
+    ) -> Self {
+        let variables = Variables::from_env();
+        let history = History::load(history_file, &variables);
+
+        Self {
+            cmd: String::new(),
+            args: None,
+            assignments: Vec::new(),
+            redirects: Vec::new(),
+            pipeline: Vec::new(),
+            last_status: 0,
+            script_name: env::args().next().unwrap_or_default(),
+            positional: Vec::new(),
+            option_group: (0, 0),
+            loop_depth: 0,
+            control: None,
+            functions: HashMap::new(),
+            function_depth: 0,
+            interactive: false,
+            job_control: false,
+            traps: HashMap::new(),
+            stdin_redirected: false,
+            hashed: HashMap::new(),
+            dir_stack: Vec::new(),
+            aliases: HashMap::new(),
+            variables,
+            flags: Flags::default(),
+            builtins: Self::register_builtins(),
+            jobs: Vec::new(),
+            history,
+            path_index: PathIndex::default(),
+            stdin,
+            tty,
+            stdout: BufWriter::new(Output::new(stdout, None)),
+            stderr: BufWriter::new(Output::new(stderr, None)),
+        }
+    }
+
+    // Run the commands of `input` without exiting afterwards, returning the status of the last
+    // one. Everything written by builtins is flushed into the writers of the shell
+    pub fn eval(&mut self, input: &str) -> Result<i32> {
+        self.handle_input(input)?;
+        self.flush()?;
+
+        Ok(self.last_status)
+    }
+
+    // The writers the shell was built with, see `Shell::builder()`
+    pub fn stdout(&mut self) -> &mut W {
+        &mut self.stdout.get_mut().writer
+    }
+
+    pub fn stderr(&mut self) -> &mut E {
+        &mut self.stderr.get_mut().writer
+    }
+
+    // Read and run commands until the input ends, returning the status to exit with, which is
+    // the one of the last command
+    pub fn run(&mut self) -> Result<i32> {
+        // Piped input like `echo pwd | shell` runs like a script, without prompts, the history
+        // or the rc file
+        if !self.tty {
+            self.run_lines(|shell, input| shell.stdin.read_line(input))?;
+            self.run_exit_trap()?;
+            self.flush()?;
+
+            return Ok(self.last_status);
+        }
+
+        let mut input = String::new();
+
+        sys::ignore_interrupts()?;
+        self.interactive = true;
+
+        // Ctrl-Z then stops the command in the foreground instead of the shell
+        sys::enable_job_control()?;
+        self.job_control = true;
+        self.path_index
+            .refresh(self.variables.get("PATH").unwrap_or_default());
+        self.load_rc()?;
+
+        loop {
+            self.notify_jobs()?;
+            self.run_traps()?;
+
+            match self.read_cmd(&mut input) {
+                // Ctrl-D exits with the status of the last command, on a line of its own instead
+                // of after the prompt
+                Ok(0) => {
+                    writeln!(self.stdout)?;
+                    self.run_exit_trap()?;
+                    self.save_history();
+                    self.flush()?;
+
+                    return Ok(self.last_status);
+                }
+                Ok(_) => {}
+                // Ctrl-C cancels the command typed so far and starts over with a fresh prompt
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {
+                    input.clear();
+                    continue;
+                }
+                Err(error) => return Err(error.into()),
+            }
+
+            // References to earlier commands like `!!` are shown once expanded, a missing one
+            // fails the whole line
+            match self.history.expand(&input) {
+                Ok(Some(expanded)) => {
+                    write!(self.stdout, "{}", expanded)?;
+                    input = expanded;
+                }
+                Ok(None) => {}
+                Err(error) => {
+                    self.last_status = 1;
+                    writeln!(self.stderr, "{}", error)?;
+                    self.flush()?;
+                    input.clear();
+                    continue;
+                }
+            }
+
+            self.history.push(&input, &self.variables);
+            self.handle_input(&input)?;
+            self.flush()?;
+            input.clear();
+        }
+    }
+
+    // Run the commands of `input` like `sh -c` does, returning the status of the last one
+    // instead of prompting for more. The first of `args` is `$0`, the rest are the positional
+    // parameters
+    pub fn run_command(&mut self, input: &str, args: Vec<String>) -> Result<i32> {
+        let mut args = args.into_iter();
+
+        if let Some(name) = args.next() {
+            self.script_name = name;
+        }
+        self.positional = args.collect();
+
+        self.handle_input(input)?;
+        self.run_exit_trap()?;
+        self.flush()?;
+
+        Ok(self.last_status)
+    }
+
+    // Run the script at `path` with `args` as its positional parameters, returning the status
+    // of its last command
+    pub fn run_script(&mut self, path: &str, args: Vec<String>) -> Result<i32> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(error) => {
+                let error = ShellError::ScriptError(PathBuf::from(path), error.kind());
+                writeln!(self.stderr, "{}", error)?;
+                self.flush()?;
+
+                return Ok(error.status());
+            }
+        };
+
+        self.script_name = path.to_owned();
+        self.positional = args;
+
+        self.run_file(file)?;
+        self.run_exit_trap()?;
+        self.flush()?;
+
+        Ok(self.last_status)
+    }
+
+    fn handle_input(&mut self, input: &str) -> io::Result<()> {
+        let items = Self::tokenize(input)
+            .and_then(|tokens| self.expand_aliases(tokens))
+            .and_then(Self::split_list)
+            .and_then(Self::parse_compound);
+
+        match items {
+            Ok(items) => self.run_items(&items, false),
+            Err(error) => {
+                self.last_status = 2;
+                writeln!(self.stderr, "{}", error)
+            }
+        }
+    }
+
+    // Run a pipeline of a list, `operator` is the one following it
+    fn run_pipeline(&mut self, mut tokens: Vec<Token>, operator: Option<&Token>) -> io::Result<()> {
+        // Each command is parsed right before it runs so `$?` refers to the command before
+        // `time` in front of a pipeline reports how long all of its commands took
+        let timed = matches!(tokens.first(), Some(Token::Word(word)) if word == TIME);
+
+        if timed {
+            tokens.remove(0);
+        }
+
+        let start = Instant::now();
+
+        // Jobs are listed by the command line they were started with, which is only needed for
+        // commands running in the background
+        let line = (operator == Some(&Token::Background)).then(|| {
+            tokens
+                .iter()
+                .map(Token::to_string)
+                .collect::<Vec<_>>()
+                .join(" ")
+        });
+
+        // A command followed by `&` runs in the background without waiting for it
+        let result = self.parse_pipeline(tokens).and_then(|pipeline| {
+            self.pipeline = pipeline;
+
+            if self.flags.xtrace {
+                self.trace_pipeline()?;
+            }
+
+            match &line {
+                Some(line) => self.start_job(line),
+                None => self.handle_cmd(),
+            }
+        });
+
+        // Failed expansions like `$((1/0))` fail the command without running it
+        if let Err(error) = result {
+            self.last_status = error.status();
+            writeln!(self.stderr, "{}", error)?;
+        }
+
+        if timed {
+            self.report_time(start.elapsed())?;
+        }
+
+        self.flush()
+    }
+
+    // Print each command of the pipeline like `+ echo 'a b'` as it is run, after expansion
+    fn trace_pipeline(&mut self) -> io::Result<()> {
+        // Output of earlier commands comes first
+        self.stdout.flush()?;
+
+        for segment in &self.pipeline {
+            let assignments = segment
+                .assignments
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, Self::quote(value)));
+
+            let words = [&segment.cmd]
+                .into_iter()
+                .chain(&segment.args)
+                .filter(|word| !word.is_empty())
+                .map(|word| Self::quote(word));
+
+            let line: Vec<_> = assignments.chain(words).collect();
+            writeln!(self.stderr, "+ {}", line.join(" "))?;
+        }
+
+        self.stderr.flush()
+    }
+
+    // Quote `word` with `'...'` if it wouldn't be read back as a single word otherwise
+    fn quote(word: &str) -> String {
+        let is_plain = !word.is_empty()
+            && word
+                .chars()
+                .all(|c| c.is_alphanumeric() || "-_./=:,+%@".contains(c));
+
+        if is_plain {
+            word.to_owned()
+        } else {
+            format!("'{}'", word.replace('\'', "'\\''"))
+        }
+    }
+
+    fn report_time(&mut self, elapsed: Duration) -> io::Result<()> {
+        let secs = elapsed.as_secs();
+
+        // Like `real	0m1.003s` in other shells
+        writeln!(
+            self.stderr,
+            "\nreal\t{}m{}.{:03}s",
+            secs / 60,
+            secs % 60,
+            elapsed.subsec_millis()
+        )
+    }
+
+    fn load_rc(&mut self) -> io::Result<()> {
+        let Some(home) = self.variables.get("HOME") else {
+            return Ok(());
+        };
+
+        // A missing rc file is fine, the shell then just starts unconfigured
+        let Ok(file) = File::open(PathBuf::from(home).join(RC_FILE)) else {
+            return Ok(());
+        };
+
+        // Errors in the rc file shouldn't prevent the interactive prompt from starting
+        if let Err(error) = self.run_file(file) {
+            writeln!(self.stderr, "{}: {}", RC_FILE, error)?;
+        }
+
+        self.flush()
+    }
+
+    fn run_file(&mut self, file: File) -> io::Result<()> {
+        let mut reader = BufReader::new(file);
+        self.run_lines(|_, input| reader.read_line(input))
+    }
+
+    // Run each line appended to the buffer by `read_line` as if it was typed, until it reads
+    // nothing. Errors are printed by `handle_input()` and don't stop the remaining lines
+    fn run_lines(
+        &mut self,
+        mut read_line: impl FnMut(&mut Self, &mut String) -> io::Result<usize>,
+    ) -> io::Result<()> {
+        // Lines are read into the same buffer, which is only cleared once a command is complete
+        let mut input = String::new();
+
+        while read_line(self, &mut input)? > 0 {
+            // Quotes left open continue on the next line, just like at the prompt
+            if Self::is_incomplete(&input) {
+                continue;
+            }
+
+            self.handle_input(&input)?;
+            input.clear();
+        }
+
+        // Run an incomplete command at the end of the file anyway
+        if !input.is_empty() {
+            self.handle_input(&input)?;
+        }
+
+        Ok(())
+    }
+
+    fn handle_cmd(&mut self) -> Result<()> {
+        // Builtins succeed unless they return an error, external commands and pipelines
+        // overwrite the status with their exit code
+        self.last_status = 0;
+
+        let result = if self.pipeline.len() > 1 {
+            // Commands connected by `|` run concurrently as child processes
+            self.execute_pipeline()
+        } else {
+            self.handle_single_cmd()
+        };
+
+        if let Err(error) = &result {
+            self.last_status = error.status();
+        }
+
+        result
+    }
+
+    fn handle_single_cmd(&mut self) -> Result<()> {
+        // A single command runs directly inside the shell
+        match self.pipeline.pop() {
+            Some(segment) => self.load_segment(segment),
+            None => return Ok(()),
+        }
+
+        self.dispatch()
+    }
+
+    fn load_segment(&mut self, segment: ParsedCommand) {
+        self.cmd = segment.cmd;
+        self.args = (!segment.args.is_empty()).then_some(segment.args);
+        self.assignments = segment.assignments;
+        self.redirects = segment.redirects;
+    }
+
+    fn dispatch(&mut self) -> Result<()> {
+        if self.cmd.is_empty() {
+            // A line of only assignments like `FOO=bar` sets them in the shell itself
+            for (name, value) in self.assignments.drain(..) {
+                self.variables.set(name, value);
+            }
+
+            return Ok(());
+        }
+
+        // Assignments in front of a builtin or function only apply while it runs, external
+        // commands get them passed through `Command::envs()` instead
+        let saved: Vec<_> = if self.is_builtin(&self.cmd) || self.is_function(&self.cmd) {
+            self.assignments
+                .iter()
+                .map(|(name, value)| (name.clone(), self.variables.set(name, value)))
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        // Functions come first, `command` skips them by calling `dispatch_cmd()` directly
+        let result = match self.functions.get(&self.cmd).cloned() {
+            Some(body) => self.run_in_shell(|shell| shell.call_function(&body)),
+            None => self.dispatch_cmd(),
+        };
+
+        for (name, value) in saved {
+            match value {
+                Some(value) => self.variables.set(name, value),
+                None => self.variables.remove(&name),
+            };
+        }
+
+        result
+    }
+
+    fn dispatch_cmd(&mut self) -> Result<()> {
+        match self.builtins.get(self.cmd.as_str()).copied() {
+            // `exec` applies its redirections itself, as they outlast it without a command
+            Some(builtin) if self.cmd == "exec" => (builtin.run)(self),
+            Some(builtin) => self.run_in_shell(builtin.run),
+            None => self.execute(),
+        }
+    }
+
+    // Run a builtin or function with its redirections applied to the shell's own file
+    // descriptors, so everything it reads and writes goes through them until they are pointed
+    // back. This includes its errors, like for `cd missing 2> /dev/null`
+    fn run_in_shell(&mut self, run: impl FnOnce(&mut Self) -> Result<()>) -> Result<()> {
+        if self.redirects.is_empty() {
+            run(self)?;
+            return self.flush_output();
+        }
+
+        let redirects = self.open_redirects()?;
+        let stdin_redirected = self.stdin_redirected;
+        self.stdin_redirected |= redirects.has(STDIN_FD);
+
+        // Commands run by the builtin like `command ls > file` inherit the redirections
+        self.redirects.clear();
+
+        self.flush()?;
+        let fds = self.redirect_output(&redirects);
+        let saved = redirects.apply_to_shell()?;
+
+        if let Err(error) = run(self) {
+            self.last_status = error.status();
+            let _ = writeln!(self.stderr, "{}", error);
+        }
+
+        // Point the file descriptors back even if the output can't be written
+        let flushed = self.flush_output();
+        Redirects::restore_shell(saved)?;
+        (self.stdout.get_mut().fd, self.stderr.get_mut().fd) = fds;
+        self.stdin_redirected = stdin_redirected;
+
+        flushed
+    }
+
+    fn is_builtin(&self, name: &str) -> bool {
+        self.builtins.contains_key(name)
+    }
+
+    fn is_function(&self, name: &str) -> bool {
+        self.functions.contains_key(name)
+    }
+
+    // The single list of builtins, used for running them as well as by `type`, `help` and
+    // completion
+    fn register_builtins() -> HashMap<&'static str, Builtin<R, W, E>> {
+        let builtins = [
+            (
+                ".",
+                Self::source as Run<R, W, E>,
+                ". file",
+                "Run the commands of file in the current shell",
+            ),
+            (":", Self::true_, ": [arg ...]", "Do nothing and succeed"),
+            (
+                "[",
+                Self::test,
+                "[ expr ]",
+                "Evaluate a conditional expression, like test",
+            ),
+            (
+                "alias",
+                Self::alias,
+                "alias [name[=value] ...]",
+                "Define or print aliases",
+            ),
+            (
+                "bg",
+                Self::bg,
+                "bg [job]",
+                "Continue a stopped job in the background",
+            ),
+            (
+                "break",
+                Self::break_,
+                "break [n]",
+                "Leave the innermost n loops, 1 by default",
+            ),
+            (
+                "cd",
+                Self::cd,
+                "cd [dir | -]",
+                "Change the current directory, to $HOME by default",
+            ),
+            ("clear", Self::clear, "clear", "Clear the terminal screen"),
+            (
+                "command",
+                Self::command,
+                "command [-v] name [arg ...]",
+                "Run name ignoring aliases, or print how each name resolves with -v",
+            ),
+            (
+                "continue",
+                Self::continue_,
+                "continue [n]",
+                "Start the next iteration of the nth innermost loop, 1 by default",
+            ),
+            (
+                "dirs",
+                Self::dirs,
+                "dirs",
+                "Print the directory stack, starting with the current directory",
+            ),
+            (
+                "echo",
+                Self::echo,
+                "echo [-neE] [arg ...]",
+                "Print the arguments separated by spaces",
+            ),
+            (
+                "exec",
+                Self::exec,
+                "exec [command [arg ...]]",
+                "Replace the shell with command, or redirect the shell's own streams",
+            ),
+            (
+                "exit",
+                Self::exit,
+                "exit [code]",
+                "Exit the shell with code, 0 by default",
+            ),
+            (
+                "export",
+                Self::export,
+                "export [name[=value] ...]",
+                "Set environment variables or print all of them",
+            ),
+            ("false", Self::false_, "false", "Do nothing and fail"),
+            (
+                "fc",
+                Self::fc,
+                "fc [-l] [first [last]]",
+                "List or edit and run commands of the history",
+            ),
+            (
+                "fg",
+                Self::fg,
+                "fg [job]",
+                "Continue a job in the foreground",
+            ),
+            (
+                "getopts",
+                Self::getopts,
+                "getopts optstring name [arg ...]",
+                "Parse the options of the positional parameters one at a time",
+            ),
+            (
+                "hash",
+                Self::hash,
+                "hash [-r] [name ...]",
+                "Remember where commands are found, or forget all of them with -r",
+            ),
+            (
+                "help",
+                Self::help,
+                "help [name]",
+                "Describe all builtins or the given one",
+            ),
+            (
+                "history",
+                Self::history,
+                "history",
+                "Print the command history",
+            ),
+            ("jobs", Self::jobs, "jobs", "List background jobs"),
+            (
+                "kill",
+                Self::kill,
+                "kill [-signal] pid | job ...",
+                "Send a signal to processes or jobs, TERM by default",
+            ),
+            (
+                "popd",
+                Self::popd,
+                "popd",
+                "Change to the directory on top of the stack and remove it",
+            ),
+            (
+                "pushd",
+                Self::pushd,
+                "pushd [dir]",
+                "Save the current directory on the stack and change to dir",
+            ),
+            (
+                "pwd",
+                Self::pwd,
+                "pwd [-L | -P]",
+                "Print the current directory, with symlinks resolved by -P",
+            ),
+            (
+                "read",
+                Self::read,
+                "read [-p prompt] [name ...]",
+                "Read a line from stdin into variables",
+            ),
+            (
+                "return",
+                Self::return_,
+                "return [n]",
+                "Leave the running function with status n, the last one by default",
+            ),
+            (
+                "set",
+                Self::set,
+                "set [-+Ceux] [-+o option]",
+                "Enable options with -, disable them with +, or print them",
+            ),
+            (
+                "shift",
+                Self::shift,
+                "shift [n]",
+                "Remove the first n positional parameters, 1 by default",
+            ),
+            (
+                "source",
+                Self::source,
+                "source file",
+                "Run the commands of file in the current shell",
+            ),
+            (
+                "test",
+                Self::test,
+                "test expr",
+                "Evaluate a conditional expression",
+            ),
+            (
+                "trap",
+                Self::trap,
+                "trap [action] [signal ...]",
+                "Run action when the shell receives a signal or exits",
+            ),
+            ("true", Self::true_, "true", "Do nothing and succeed"),
+            (
+                "type",
+                Self::type_,
+                "type [-a] name ...",
+                "Describe how each name would be run",
+            ),
+            (
+                "unalias",
+                Self::unalias,
+                "unalias [-a] name ...",
+                "Remove aliases",
+            ),
+            (
+                "unset",
+                Self::unset,
+                "unset name ...",
+                "Remove environment variables",
+            ),
+            (
+                "wait",
+                Self::wait,
+                "wait [pid | job ...]",
+                "Wait for background jobs to finish",
+            ),
+            (
+                "which",
+                Self::which,
+                "which [-a] name ...",
+                "Print where each name is found in $PATH",
+            ),
+        ];
+
+        builtins
+            .into_iter()
+            .map(|(name, run, usage, description)| {
+                let builtin = Builtin {
+                    run,
+                    usage,
+                    description,
+                };
+
+                (name, builtin)
+            })
+            .collect()
+    }
+}
+
+impl<R: BufRead, W: Write, E: Write> Shell<R, W, E> {
+    fn exit(&mut self) -> Result<()> {
+        let code = match self.args.as_ref().and_then(|args| args.first()) {
+            Some(code) => code
+                .parse::<i32>()
+                .map_err(ShellError::ExitCodeParseError)?,
+            None => 0,
+        };
+
+        // The action sees the status the shell exits with as `$?`
+        self.last_status = code;
+        self.run_exit_trap()?;
+
+        self.save_history();
+        self.flush()?;
+
+        process::exit(code);
+    }
+
+    fn exec(&mut self) -> Result<()> {
+        let (mut cmd_args, redirects) = self.handle_redirect()?;
+
+        // Without a command the redirections apply to the shell itself from now on
+        if cmd_args.is_empty() {
+            return self.redirect_shell(redirects);
+        }
+
+        self.cmd = cmd_args.remove(0);
+
+        // Nothing of the shell survives once the program runs
+        self.save_history();
+        self.flush()?;
+
+        // Only returns if the program couldn't be run, in which case the shell keeps going
+        self.start_command(&cmd_args, &redirects, None, None, |cmd| {
+            Err::<(), _>(cmd.exec())
+        })
+    }
+
+    fn redirect_shell(&mut self, redirects: Redirects) -> Result<()> {
+        // Output written so far still goes to the old destinations
+        self.flush()?;
+        self.redirect_output(&redirects);
+        redirects.apply_to_shell()?;
+
+        Ok(())
+    }
+
+    fn echo(&mut self) -> Result<()> {
+        let cmd_args = self.args.take().unwrap_or_default();
+
+        let mut newline = true;
+        let mut escapes = false;
+
+        // Options like `-n` or `-ne` are only recognized in front of the words, so e.g.
+        // `echo a -n` prints it. `-e` interprets escapes like `\t`, while `-E` keeps them as-is
+        let words = cmd_args.iter().skip_while(|arg| {
+            let Some(flags) = arg.strip_prefix('-') else {
+                return false;
+            };
+
+            if flags.is_empty() || !flags.chars().all(|flag| "neE".contains(flag)) {
+                return false;
+            }
+
+            for flag in flags.chars() {
+                match flag {
+                    'n' => newline = false,
+                    'e' => escapes = true,
+                    _ => escapes = false,
+                }
+            }
+            true
+        });
+
+        let mut output = words.map(String::as_str).collect::<Vec<_>>().join(" ");
+
+        if escapes {
+            output = Self::interpret_escapes(&output);
+        }
+
+        if newline {
+            output.push('\n');
+        }
+
+        write!(self.stdout, "{}", output)?;
+
+        Ok(())
+    }
+
+    // `true` and `:` do nothing and ignore their arguments, the status stays 0
+    fn true_(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn false_(&mut self) -> Result<()> {
+        self.last_status = 1;
+
+        Ok(())
+    }
+
+    fn test(&mut self) -> Result<()> {
+        let args = self.args.as_deref().unwrap_or_default();
+
+        // `[` is the same as `test`, except that it needs a closing `]`
+        let args = match (self.cmd.as_str(), args.split_last()) {
+            ("[", Some((last, args))) if last == "]" => args,
+            ("[", _) => {
+                return Err(ShellError::InvalidCondition(
+                    self.cmd.clone(),
+                    "missing `]'".to_owned(),
+                ))
+            }
+            _ => args,
+        };
+
+        if !Self::evaluate(&self.cmd, args)? {
+            self.last_status = 1;
+        }
+
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        // The sequence is written even if stdout isn't a terminal, like `clear` does
+        write!(self.stdout, "{}", CLEAR_SCREEN)?;
+        self.stdout.flush()?;
+
+        Ok(())
+    }
+
+    fn command(&mut self) -> Result<()> {
+        let mut args = self.args.take().unwrap_or_default().into_iter();
+
+        let Some(name) = args.next() else {
+            return Ok(());
+        };
+
+        if name == "-v" {
+            let names: Vec<_> = args.collect();
+
+            if names.is_empty() {
+                return Err(ShellError::NoArguments);
+            }
+
+            for name in names {
+                self.print_resolution(&name)?;
+            }
+
+            return Ok(());
+        }
+
+        // Only the first word of a command is looked up as alias, so the command given as
+        // argument runs as-is
+        let args: Vec<_> = args.collect();
+
+        self.cmd = name;
+        self.args = (!args.is_empty()).then_some(args);
+
+        self.dispatch_cmd()
+    }
+
+    fn print_resolution(&mut self, name: &str) -> Result<()> {
+        // Like `type` but only printing what runs, in a form which can be used again
+        if let Some(value) = self.aliases.get(name) {
+            writeln!(self.stdout, "alias {}='{}'", name, value)?;
+        } else if self.is_function(name)
+            || self.is_builtin(name)
+            || (name.contains('/') && Self::is_executable(Path::new(name)))
+        {
+            // Builtins and paths run by the name given
+            writeln!(self.stdout, "{}", name)?;
+        } else if let Some(path) = self.find_exe_in_path(name) {
+            writeln!(self.stdout, "{}", path.display())?;
+        } else {
+            // Unknown names print nothing, only the status tells
+            self.last_status = 1;
+        }
+
+        Ok(())
+    }
+
+    fn type_(&mut self) -> Result<()> {
+        let args = self.args.as_ref().ok_or(ShellError::NoArguments)?;
+
+        // `-a` reports every builtin and executable of a name instead of only the one which runs
+        let all = args.first().is_some_and(|arg| arg == "-a");
+        let names = &args[usize::from(all)..];
+
+        if names.is_empty() {
+            return Err(ShellError::NoArguments);
+        }
+
+        for name in names {
+            let mut found = self.is_function(name);
+
+            if found {
+                writeln!(self.stdout, "{} is a function", name)?;
+            }
+
+            // Functions hide builtins of the same name
+            if self.is_builtin(name) && (all || !found) {
+                // Check if command is shell builtin
+                writeln!(self.stdout, "{} is a shell builtin", name)?;
+                found = true;
+            }
+
+            // Check if command is in `$PATH`, unless it's a path itself like `./build.sh`
+            let paths = match (found, all) {
+                _ if name.contains('/') => Some(PathBuf::from(name))
+                    .filter(|path| Self::is_executable(path))
+                    .into_iter()
+                    .collect(),
+                (_, true) => self.find_exes_in_path(name),
+                (false, false) => self.find_exe_in_path(name).into_iter().collect(),
+                (true, false) => Vec::new(),
+            };
+
+            for path in paths {
+                writeln!(self.stdout, "{} is {}", name, path.display())?;
+                found = true;
+            }
+
+            // Report unknown names but keep going with the others
+            if !found {
+                writeln!(
+                    self.stderr,
+                    "{}",
+                    ShellError::CommandNotFound(name.to_owned())
+                )?;
+                self.last_status = 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn which(&mut self) -> Result<()> {
+        let args = self.args.as_ref().ok_or(ShellError::NoArguments)?;
+
+        // Unlike `type` only `$PATH` is searched, so builtins aren't found unless there is an
+        // executable of the same name
+        let all = args.first().is_some_and(|arg| arg == "-a");
+        let names = &args[usize::from(all)..];
+
+        if names.is_empty() {
+            return Err(ShellError::NoArguments);
+        }
+
+        for name in names {
+            let paths = if all {
+                self.find_exes_in_path(name)
+            } else {
+                self.find_exe_in_path(name).into_iter().collect()
+            };
+
+            // Unknown names print nothing, only the status tells
+            if paths.is_empty() {
+                self.last_status = 1;
+            }
+
+            for path in paths {
+                writeln!(self.stdout, "{}", path.display())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn hash(&mut self) -> Result<()> {
+        let Some(args) = self.args.clone() else {
+            // Without arguments print the remembered commands sorted by name
+            let mut hashed: Vec<_> = self.hashed.iter().collect();
+            hashed.sort();
+
+            for (name, path) in hashed {
+                writeln!(self.stdout, "{}\t{}", name, path.display())?;
+            }
+
+            return Ok(());
+        };
+
+        for name in &args {
+            // `hash -r` forgets all commands, e.g. after installing one which shadows another
+            if name == "-r" {
+                self.hashed.clear();
+                self.path_index
+                    .refresh(self.variables.get("PATH").unwrap_or_default());
+            } else if self.hash_cmd(name).is_none() {
+                return Err(ShellError::NotFound(self.cmd.clone(), name.to_owned()));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn help(&mut self) -> Result<()> {
+        let Some(args) = self.args.as_ref() else {
+            // Without arguments list all builtins sorted by name
+            let mut builtins: Vec<_> = self.builtins.iter().collect();
+            builtins.sort_by_key(|(name, _)| **name);
+
+            let width = builtins.iter().map(|(name, _)| name.len()).max();
+
+            for (name, builtin) in builtins {
+                writeln!(
+                    self.stdout,
+                    "{:width$}  {}",
+                    name,
+                    builtin.description,
+                    width = width.unwrap_or_default()
+                )?;
+            }
+
+            return Ok(());
+        };
+
+        for name in args {
+            // `help cd` shows the usage of `cd`
+            let builtin = self
+                .builtins
+                .get(name.as_str())
+                .ok_or_else(|| ShellError::NotFound(self.cmd.clone(), name.to_owned()))?;
+
+            writeln!(self.stdout, "{}: {}", name, builtin.usage)?;
+            writeln!(self.stdout, "    {}", builtin.description)?;
+        }
+
+        Ok(())
+    }
+
+    fn history(&mut self) -> Result<()> {
+        // Print history entries numbered from 1
+        for (i, entry) in self.history.entries().iter().enumerate() {
+            writeln!(self.stdout, "{:>5}  {}", i + 1, entry)?;
+        }
+
+        Ok(())
+    }
+
+    fn fc(&mut self) -> Result<()> {
+        let mut args = self.args.take().unwrap_or_default();
+
+        let list = args.first().is_some_and(|arg| arg == "-l");
+        if list {
+            args.remove(0);
+        }
+
+        // At the prompt the line running `fc` is already the newest entry, which doesn't count
+        let len = self
+            .history
+            .entries()
+            .len()
+            .saturating_sub(usize::from(self.interactive));
+
+        if len == 0 {
+            return Err(ShellError::InvalidExpression(
+                self.cmd.clone(),
+                "history specification out of range".to_owned(),
+            ));
+        }
+
+        // Without arguments list the most recent entries, or edit the newest one
+        let first = match args.first() {
+            Some(reference) => self.find_history(reference, len)?,
+            None if list => len.saturating_sub(FC_LIST_LEN),
+            None => len - 1,
+        };
+
+        let last = match args.get(1) {
+            Some(reference) => self.find_history(reference, len)?,
+            None if list => len - 1,
+            None => first,
+        };
+
+        let entries = &self.history.entries()[first.min(last)..=first.max(last)];
+
+        if list {
+            for (i, entry) in entries.iter().enumerate() {
+                writeln!(self.stdout, "{:>5}  {}", first.min(last) + i + 1, entry)?;
+            }
+
+            return Ok(());
+        }
+
+        let text = entries.join("\n") + "\n";
+        self.edit_and_run(&text)
+    }
+
+    fn find_history(&self, reference: &str, len: usize) -> Result<usize> {
+        self.history.find(reference, len).ok_or_else(|| {
+            ShellError::InvalidExpression(
+                self.cmd.clone(),
+                "history specification out of range".to_owned(),
+            )
+        })
+    }
+
+    // Write `text` into a new file only the user may read, returning its path
+    fn create_edit_file(text: &str) -> Result<PathBuf> {
+        let mut i = 0;
+
+        loop {
+            let path = env::temp_dir().join(format!("shell-fc-{}-{}", process::id(), i));
+
+            let mut file = match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&path)
+            {
+                Ok(file) => file,
+                // Left behind by another process which had the same id
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {
+                    i += 1;
+                    continue;
+                }
+                Err(error) => return Err(ShellError::RedirectionError(path, error.kind())),
+            };
+
+            file.write_all(text.as_bytes())?;
+
+            return Ok(path);
+        }
+    }
+
+    // Open `text` in the editor like any other command, then run what it was edited into
+    fn edit_and_run(&mut self, text: &str) -> Result<()> {
+        let path = Self::create_edit_file(text)?;
+
+        let editor = self
+            .variables
+            .get("FCEDIT")
+            .or_else(|| self.variables.get("EDITOR"))
+            .unwrap_or(DEFAULT_EDITOR)
+            .to_owned();
+
+        // The editor may come with arguments like `code --wait`
+        let mut words = editor.split_whitespace().map(str::to_owned);
+        self.cmd = words.next().unwrap_or_else(|| DEFAULT_EDITOR.to_owned());
+        self.args = Some(words.chain([path.display().to_string()]).collect());
+
+        let result = self.execute();
+        let edited = fs::read_to_string(&path);
+        let _ = fs::remove_file(&path);
+        result?;
+
+        // Nothing runs if the editor failed, like when it was quit with `:cq`
+        if self.last_status != 0 {
+            return Ok(());
+        }
+
+        let edited = edited?;
+
+        // The commands take the place of `fc` in the history, and are shown before they run
+        if self.interactive {
+            self.history.remove_last();
+            self.history.push(&edited, &self.variables);
+        }
+
+        write!(self.stdout, "{}", edited)?;
+        self.handle_input(&edited)?;
+
+        Ok(())
+    }
+
+    fn jobs(&mut self) -> Result<()> {
+        // List all background jobs, finished ones are reported one last time
+        self.report_jobs(true)?;
+
+        Ok(())
+    }
+
+    fn fg(&mut self) -> Result<()> {
+        // Without a job spec the most recent job is brought to the foreground
+        let spec = self.args.as_ref().and_then(|args| args.first()).cloned();
+
+        self.foreground_job(spec.as_deref())
+    }
+
+    fn bg(&mut self) -> Result<()> {
+        // Like `fg` the most recent job by default
+        let spec = self.args.as_ref().and_then(|args| args.first()).cloned();
+
+        self.background_job(spec.as_deref())
+    }
+
+    fn wait(&mut self) -> Result<()> {
+        let specs = self.args.clone().unwrap_or_default();
+
+        self.wait_jobs(&specs)
+    }
+
+    fn kill(&mut self) -> Result<()> {
+        let args = self.args.clone().ok_or(ShellError::NoArguments)?;
+
+        // An optional signal like `-9` or `-KILL` comes first, `SIGTERM` is sent by default
+        let (signal, targets) = match args.split_first() {
+            Some((first, rest)) if first.starts_with('-') => {
+                let name = &first[1..];
+                let signal = sys::parse_signal(name)
+                    .ok_or_else(|| ShellError::InvalidSignal(name.to_owned()))?;
+
+                (signal, rest)
+            }
+            _ => (sys::SIGTERM, args.as_slice()),
+        };
+
+        if targets.is_empty() {
+            return Err(ShellError::NoArguments);
+        }
+
+        // Targets are either jobs like `%1` or process ids
+        for target in targets {
+            if target.starts_with('%') {
+                self.signal_job(target, signal)?;
+            } else {
+                let pid = target
+                    .parse()
+                    .map_err(|_| ShellError::InvalidProcessId("kill".to_owned(), target.clone()))?;
+
+                sys::kill(pid, signal)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn export(&mut self) -> Result<()> {
+        let Some(args) = self.args.as_ref() else {
+            // Without arguments print all environment variables sorted by name
+            let mut vars: Vec<_> = self.variables.iter().collect();
+            vars.sort();
+
+            for (name, value) in vars {
+                writeln!(self.stdout, "{}={}", name, value)?;
+            }
+
+            return Ok(());
+        };
+
+        for arg in args {
+            // Quotes were already removed while parsing, so `X="a b"` arrives as `X=a b`
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (arg.as_str(), None),
+            };
+
+            if !Self::is_name(name) {
+                return Err(ShellError::InvalidIdentifier(
+                    self.cmd.clone(),
+                    arg.to_owned(),
+                ));
+            }
+
+            match value {
+                Some(value) => self.variables.set(name, value),
+                // `export NAME` keeps an existing value and creates an empty variable otherwise
+                None if self.variables.get(name).is_none() => self.variables.set(name, ""),
+                None => None,
+            };
+        }
+
+        Ok(())
+    }
+
+    fn read(&mut self) -> Result<()> {
+        let mut cmd_args = self.args.take().unwrap_or_default().into_iter().peekable();
+
+        // `-p prompt` shows the prompt before reading
+        let prompt = match cmd_args.next_if_eq("-p") {
+            Some(_) => Some(cmd_args.next().ok_or(ShellError::NoArguments)?),
+            None => None,
+        };
+
+        // Without names the line is stored in `$REPLY`
+        let mut names: Vec<_> = cmd_args.collect();
+
+        if names.is_empty() {
+            names.push("REPLY".to_owned());
+        }
+
+        if let Some(name) = names.iter().find(|name| !Self::is_name(name)) {
+            return Err(ShellError::InvalidIdentifier(
+                self.cmd.clone(),
+                name.to_owned(),
+            ));
+        }
+
+        if let Some(prompt) = prompt {
+            write!(self.stderr, "{}", prompt)?;
+            self.stderr.flush()?;
+        }
+
+        // The line is read as-is, backslashes don't escape anything like with `read -r`
+        let mut line = String::new();
+
+        // A redirected stdin is read a byte at a time, so nothing after the line is left in the
+        // buffer of the shell's own stdin once it's pointed back
+        if self.stdin_redirected {
+            Self::read_line_unbuffered(&mut line)?;
+        } else {
+            self.stdin.read_line(&mut line)?;
+        }
+
+        // The input ended before a complete line was read, whatever was read is still stored
+        if !line.ends_with('\n') {
+            self.last_status = 1;
+        }
+
+        // Each name gets one word, the last one gets the rest of the line
+        let mut rest = line.trim();
+
+        if let Some((last, names)) = names.split_last() {
+            for name in names {
+                let (word, remainder) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+
+                self.variables.set(name, word);
+                rest = remainder.trim_start();
+            }
+
+            self.variables.set(last, rest);
+        }
+
+        Ok(())
+    }
+
+    fn read_line_unbuffered(line: &mut String) -> io::Result<()> {
+        let mut stdin = File::from(sys::dup(STDIN_FD)?);
+        let mut bytes = Vec::new();
+        let mut byte = [0];
+
+        while stdin.read(&mut byte)? > 0 {
+            bytes.push(byte[0]);
+
+            if byte[0] == b'\n' {
+                break;
+            }
+        }
+
+        line.push_str(&String::from_utf8_lossy(&bytes));
+        Ok(())
+    }
+
+    fn set(&mut self) -> Result<()> {
+        let Some(args) = self.args.as_ref() else {
+            return self.print_flags();
+        };
+
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            // `-e` enables an option and `+e` disables it
+            let (enabled, letters) = match (arg.strip_prefix('-'), arg.strip_prefix('+')) {
+                (Some(letters), _) => (true, letters),
+                (_, Some(letters)) => (false, letters),
+                _ => return Err(ShellError::InvalidOption(self.cmd.clone(), arg.to_owned())),
+            };
+
+            // `-o name` refers to the option by name, without a name all options are printed
+            if letters == "o" {
+                match args.next() {
+                    Some(name) if self.flags.set(name, enabled) => continue,
+                    Some(name) => {
+                        return Err(ShellError::InvalidOption(self.cmd.clone(), name.to_owned()))
+                    }
+                    None => return self.print_flags(),
+                }
+            }
+
+            for letter in letters.chars() {
+                let name = Flags::name_of(letter).ok_or_else(|| {
+                    ShellError::InvalidOption(self.cmd.clone(), format!("-{}", letter))
+                })?;
+
+                self.flags.set(name, enabled);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn print_flags(&mut self) -> Result<()> {
+        // Printed so they can be run again to restore the options
+        for (name, enabled) in self.flags.options() {
+            let sign = if enabled { '-' } else { '+' };
+            writeln!(self.stdout, "set {}o {}", sign, name)?;
+        }
+
+        Ok(())
+    }
+
+    fn shift(&mut self) -> Result<()> {
+        let count = match self.args.as_ref().and_then(|args| args.first()) {
+            Some(count) => count.parse::<usize>().map_err(|_| {
+                ShellError::InvalidExpression(
+                    self.cmd.clone(),
+                    format!("{}: numeric argument required", count),
+                )
+            })?,
+            None => 1,
+        };
+
+        // Shifting out more parameters than there are fails and keeps all of them
+        if count > self.positional.len() {
+            self.last_status = 1;
+            return Ok(());
+        }
+
+        self.positional.drain(..count);
+
+        Ok(())
+    }
+
+    // Parse the next option of the positional parameters, or of the arguments after `name`.
+    // The letter is stored in `name`, its argument in `$OPTARG` and the index of the next
+    // argument to parse in `$OPTIND`. Fails once there are no options left
+    fn getopts(&mut self) -> Result<()> {
+        let args = self.args.take().unwrap_or_default();
+
+        let [optstring, name, rest @ ..] = &args[..] else {
+            return Err(ShellError::InvalidExpression(
+                self.cmd.clone(),
+                "usage: getopts optstring name [arg ...]".to_owned(),
+            ));
+        };
+
+        if !Self::is_name(name) {
+            return Err(ShellError::InvalidIdentifier(
+                self.cmd.clone(),
+                name.clone(),
+            ));
+        }
+
+        let params = if rest.is_empty() {
+            self.positional.clone()
+        } else {
+            rest.to_vec()
+        };
+
+        // A leading `:` reports errors through `name` and `$OPTARG` instead of printing them
+        let (silent, optstring) = match optstring.strip_prefix(':') {
+            Some(optstring) => (true, optstring),
+            None => (false, optstring.as_str()),
+        };
+
+        let index = self
+            .variables
+            .get("OPTIND")
+            .and_then(|index| index.parse().ok())
+            .unwrap_or(1usize)
+            .max(1);
+
+        // A changed `$OPTIND` starts at the beginning of a word again
+        let offset = match self.option_group {
+            (group, offset) if group == index => offset,
+            _ => 1,
+        };
+
+        self.variables.remove("OPTARG");
+
+        let word = params.get(index - 1).map_or("", String::as_str);
+        let letter = word.get(offset..).and_then(|rest| rest.chars().next());
+
+        // Options end at the first argument which isn't one, after `--` which is skipped
+        let letter = match letter {
+            Some(letter) if word.starts_with('-') && word != "-" && word != "--" => letter,
+            _ => {
+                let index = if word == "--" { index + 1 } else { index };
+
+                self.variables.set("OPTIND", index.to_string());
+                self.variables.set(name, "?");
+                self.last_status = 1;
+                return Ok(());
+            }
+        };
+
+        // The next option follows in the same word, or starts the next one
+        let next = offset + letter.len_utf8();
+        let (mut index, mut offset) = match next < word.len() {
+            true => (index, next),
+            false => (index + 1, 1),
+        };
+
+        let spec = optstring.find(letter).filter(|_| letter != ':');
+        let takes_arg = spec.is_some_and(|i| optstring[i + letter.len_utf8()..].starts_with(':'));
+
+        // Unknown options and missing arguments set `name` to `?`, or report the option in
+        // `$OPTARG` with a leading `:` in the option string
+        let value = match spec {
+            None => {
+                if silent {
+                    self.variables.set("OPTARG", letter.to_string());
+                } else {
+                    writeln!(self.stderr, "{}: illegal option -- {}", self.cmd, letter)?;
+                }
+
+                '?'
+            }
+            Some(_) if takes_arg => {
+                // The argument is the rest of the word like in `-ofile`, or the next word
+                let arg = if offset > 1 {
+                    let arg = word[offset..].to_owned();
+                    (index, offset) = (index + 1, 1);
+                    Some(arg)
+                } else {
+                    let arg = params.get(index - 1).cloned();
+                    index += usize::from(arg.is_some());
+                    arg
+                };
+
+                match arg {
+                    Some(arg) => {
+                        self.variables.set("OPTARG", arg);
+                        letter
+                    }
+                    None if silent => {
+                        self.variables.set("OPTARG", letter.to_string());
+                        ':'
+                    }
+                    None => {
+                        writeln!(
+                            self.stderr,
+                            "{}: option requires an argument -- {}",
+                            self.cmd, letter
+                        )?;
+                        '?'
+                    }
+                }
+            }
+            Some(_) => letter,
+        };
+
+        self.variables.set(name, value.to_string());
+        self.variables.set("OPTIND", index.to_string());
+        self.option_group = (index, offset);
+
+        Ok(())
+    }
+
+    fn unset(&mut self) -> Result<()> {
+        let args = self.args.as_ref().ok_or(ShellError::NoArguments)?;
+
+        for name in args {
+            // Invalid names can't refer to a variable
+            if !Self::is_name(name) {
+                return Err(ShellError::InvalidIdentifier(
+                    self.cmd.clone(),
+                    name.to_owned(),
+                ));
+            }
+
+            // Removing a variable which isn't set does nothing
+            self.variables.remove(name);
+        }
+
+        Ok(())
+    }
+
+    fn source(&mut self) -> Result<()> {
+        let args = self.args.as_ref().ok_or(ShellError::NoArguments)?;
+        let path = PathBuf::from(args.first().ok_or(ShellError::NoArguments)?);
+
+        let file =
+            File::open(&path).map_err(|_| ShellError::FileOrDirNotFound(self.cmd.clone(), path))?;
+
+        self.run_file(file)?;
+
+        Ok(())
+    }
+
+    fn alias(&mut self) -> Result<()> {
+        let Some(args) = self.args.as_ref() else {
+            // Without arguments print all aliases sorted by name
+            let mut aliases: Vec<_> = self.aliases.iter().collect();
+            aliases.sort();
+
+            for (name, value) in aliases {
+                writeln!(self.stdout, "alias {}='{}'", name, value)?;
+            }
+
+            return Ok(());
+        };
+
+        for arg in args {
+            match arg.split_once('=') {
+                // `alias name=value` defines an alias
+                Some((name, value)) => {
+                    if name.is_empty() || name.contains(char::is_whitespace) {
+                        return Err(ShellError::InvalidIdentifier(
+                            self.cmd.clone(),
+                            arg.to_owned(),
+                        ));
+                    }
+
+                    self.aliases.insert(name.to_owned(), value.to_owned());
+                }
+                // `alias name` prints the alias
+                None => match self.aliases.get(arg) {
+                    Some(value) => writeln!(self.stdout, "alias {}='{}'", arg, value)?,
+                    None => return Err(ShellError::NotFound(self.cmd.clone(), arg.to_owned())),
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    fn unalias(&mut self) -> Result<()> {
+        let args = self.args.as_ref().ok_or(ShellError::NoArguments)?;
+
+        for name in args {
+            // `unalias -a` removes all aliases
+            if name == "-a" {
+                self.aliases.clear();
+            } else if self.aliases.remove(name).is_none() {
+                return Err(ShellError::NotFound(self.cmd.clone(), name.to_owned()));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn pwd(&mut self) -> Result<()> {
+        // `-L` prints the directory including the symlinks it was reached through, which is the
+        // default, while `-P` resolves them. The last option given wins
+        let mut physical = false;
+
+        for arg in self.args.iter().flatten() {
+            match arg.as_str() {
+                "-L" => physical = false,
+                "-P" => physical = true,
+                _ => return Err(ShellError::InvalidOption(self.cmd.clone(), arg.to_owned())),
+            }
+        }
+
+        let dir = if physical {
+            env::current_dir()?.canonicalize()?
+        } else {
+            self.logical_dir()?
+        };
+
+        writeln!(self.stdout, "{}", dir.display())?;
+
+        Ok(())
+    }
+
+    fn cd(&mut self) -> Result<()> {
+        let arg = self.args.as_ref().and_then(|args| args.first());
+        let to_previous = arg.is_some_and(|arg| arg == "-");
+
+        // `cd -` goes back to the previous directory, otherwise get first argument and try to
+        // create PathBuf from it, otherwise PathBuf from home path
+        let mut print_pwd = to_previous;
+
+        let path = if to_previous {
+            self.variables
+                .get("OLDPWD")
+                .map(PathBuf::from)
+                .ok_or_else(|| ShellError::EnvVarNotFound("OLDPWD".to_owned()))?
+        } else {
+            match arg {
+                // Directories found through `$CDPATH` are printed, as it isn't obvious where
+                // `cd` went otherwise
+                Some(arg) => match self.search_cdpath(arg) {
+                    Some(path) => {
+                        print_pwd = true;
+                        path
+                    }
+                    None => PathBuf::from(arg),
+                },
+                // Get `$HOME` path
+                None => self
+                    .variables
+                    .get("HOME")
+                    .map(PathBuf::from)
+                    .ok_or_else(|| ShellError::EnvVarNotFound("HOME".to_owned()))?,
+            }
+        };
+
+        let pwd = self.change_dir(path)?;
+
+        // Like other shells print the directory `cd -` switched to
+        if print_pwd {
+            writeln!(self.stdout, "{}", pwd.display())?;
+        }
+
+        Ok(())
+    }
+
+    // Look for `dir` in the directories of `$CDPATH`. Paths like `/usr`, `./src` or `..` are
+    // never searched, and an empty entry in `$CDPATH` stands for the current directory which
+    // `cd` falls back to anyway
+    fn search_cdpath(&self, dir: &str) -> Option<PathBuf> {
+        let is_explicit = Path::new(dir).is_absolute()
+            || [".", ".."].contains(&dir)
+            || dir.starts_with("./")
+            || dir.starts_with("../");
+
+        if is_explicit {
+            return None;
+        }
+
+        let cdpath = self.variables.get("CDPATH")?;
+
+        for entry in env::split_paths(&cdpath) {
+            if entry.as_os_str().is_empty() {
+                if Path::new(dir).is_dir() {
+                    return None;
+                }
+            } else if entry.join(dir).is_dir() {
+                return Some(entry.join(dir));
+            }
+        }
+
+        None
+    }
+
+    fn pushd(&mut self) -> Result<()> {
+        let path = match self.args.as_ref().and_then(|args| args.first()) {
+            Some(arg) => PathBuf::from(arg),
+            // Without a directory swap the current one with the one on top of the stack
+            None => self
+                .dir_stack
+                .pop()
+                .ok_or_else(|| ShellError::DirStackEmpty(self.cmd.clone()))?,
+        };
+
+        let previous = self.logical_dir()?;
+
+        if let Err(error) = self.change_dir(path.clone()) {
+            // Keep the stack as it was if the directory can't be entered
+            if self.args.is_none() {
+                self.dir_stack.push(path);
+            }
+
+            return Err(error);
+        }
+
+        self.dir_stack.push(previous);
+        self.print_dir_stack()
+    }
+
+    fn popd(&mut self) -> Result<()> {
+        let path = self
+            .dir_stack
+            .last()
+            .cloned()
+            .ok_or_else(|| ShellError::DirStackEmpty(self.cmd.clone()))?;
+
+        // Only pop the directory once it was entered
+        self.change_dir(path)?;
+        self.dir_stack.pop();
+
+        self.print_dir_stack()
+    }
+
+    fn dirs(&mut self) -> Result<()> {
+        self.print_dir_stack()
+    }
+
+    fn print_dir_stack(&mut self) -> Result<()> {
+        // The current directory comes first, followed by the stack from its top
+        let dirs: Vec<_> = [self.logical_dir()?]
+            .iter()
+            .chain(self.dir_stack.iter().rev())
+            .map(|dir| self.tilde_path(dir))
+            .collect();
+
+        writeln!(self.stdout, "{}", dirs.join(" "))?;
+
+        Ok(())
+    }
+
+    // Change the current directory to `path`, returning where it ended up
+    fn change_dir(&mut self, path: PathBuf) -> Result<PathBuf> {
+        let previous = self.logical_dir()?;
+
+        // Relative paths are resolved against the directory as reached through symlinks, so
+        // `..` goes back to where `cd` came from like in other shells. Joining an absolute path
+        // replaces the directory instead
+        let logical = Self::normalize_path(&previous.join(&path));
+
+        // Leave resolving `..` to the filesystem if the lexically resolved path can't be entered
+        let pwd = if env::set_current_dir(&logical).is_ok() {
+            logical
+        } else {
+            let path = previous.join(path);
+
+            env::set_current_dir(&path)
+                .map_err(|_| ShellError::FileOrDirNotFound(self.cmd.clone(), path.clone()))?;
+
+            env::current_dir()?
+        };
+
+        // Keep `$PWD` and `$OLDPWD` in sync for child processes
+        self.variables.set("PWD", pwd.to_string_lossy());
+        self.variables.set("OLDPWD", previous.to_string_lossy());
+
+        Ok(pwd)
+    }
+
+    // The current directory including the symlinks it was reached through, as tracked by
+    // `$PWD`. An inherited `$PWD` is only trusted if it still refers to the current directory
+    fn logical_dir(&self) -> io::Result<PathBuf> {
+        let physical = env::current_dir()?;
+
+        let logical = self
+            .variables
+            .get("PWD")
+            .map(PathBuf::from)
+            .filter(|pwd| pwd.is_absolute())
+            .filter(|pwd| pwd.canonicalize().is_ok_and(|pwd| pwd == physical));
+
+        Ok(logical.unwrap_or(physical))
+    }
+
+    // Remove `.` and resolve `..` in `path` without looking at the filesystem
+    fn normalize_path(path: &Path) -> PathBuf {
+        let mut normalized = PathBuf::new();
+
+        for component in path.components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    normalized.pop();
+                }
+                component => normalized.push(component),
+            }
+        }
+
+        normalized
+    }
+
+    fn execute(&mut self) -> Result<()> {
+        // If redirect with either `<`, `>`, `1>` or `2>` then get arguments until symbol,
+        // handle to file of either stdin, stdout or stderr
+        let (cmd_args, redirects) = self.handle_redirect()?;
+        let line = [&self.cmd].into_iter().chain(&cmd_args).cloned();
+        let line = line.collect::<Vec<_>>().join(" ");
+
+        let group = self.job_control.then_some(0);
+        let child = self.start_command(&cmd_args, &redirects, None, None, |cmd| {
+            Self::spawn_in_group(cmd, group)
+        })?;
+
+        self.wait_foreground(Job::new(line, [Stage::External(child)]))
+    }
+
+    fn save_history(&mut self) {
+        // Losing the history is not worth preventing the shell from exiting
+        if let Err(error) = self.history.save(&self.variables) {
+            let _ = writeln!(self.stderr, "history: could not save history: {}", error);
+        }
+    }
+
+    fn exit_code(status: ExitStatus) -> i32 {
+        // A command killed by a signal exits with 128 plus the signal number, e.g. 130 for
+        // Ctrl-C
+        status
+            .code()
+            .or_else(|| status.signal().map(|signal| 128 + signal))
+            .unwrap_or(1)
+    }
+}
+
+// A shell reading nothing and writing into buffers, for running commands in the tests of the
+// modules
+#[cfg(test)]
+impl Shell<io::Empty, Vec<u8>, Vec<u8>> {
+    fn for_test() -> Self {
+        Shell::builder()
+            .stdin(io::empty())
+            .stdout(Vec::new())
+            .stderr(Vec::new())
+            .disable_history()
+            .build()
+    }
+
+    // Run `input`, returning its status along with what it wrote to stdout
+    fn output(&mut self, input: &str) -> (i32, String) {
+        let status = self.eval(input).unwrap();
+        let stdout = std::mem::take(self.stdout());
+
+        (status, String::from_utf8(stdout).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The builtins `handle_cmd` matched on before they were looked up in the registry
+    const MATCHED_BUILTINS: [&str; 16] = [
+        "exit", "echo", "type", "pwd", "cd", "history", "jobs", "fg", "wait", "kill", "export",
+        "unset", "source", ".", "alias", "unalias",
+    ];
+
+    #[test]
+    fn registers_the_matched_builtins() {
+        let mut shell = Shell::for_test();
+
+        for name in MATCHED_BUILTINS {
+            assert!(shell.is_builtin(name), "{}", name);
+            assert_eq!(
+                shell.output(&format!("type {}", name)),
+                (0, format!("{} is a shell builtin\n", name))
+            );
+        }
+    }
+
+    #[test]
+    fn runs_builtins_like_the_match() {
+        let mut shell = Shell::for_test();
+
+        assert_eq!(shell.output("echo a  b"), (0, "a b\n".to_owned()));
+        assert_eq!(
+            shell.output("export REGISTRY_TEST=1; echo $REGISTRY_TEST"),
+            (0, "1\n".to_owned())
+        );
+        assert_eq!(
+            shell.output("unset REGISTRY_TEST; echo \"[$REGISTRY_TEST]\""),
+            (0, "[]\n".to_owned())
+        );
+        assert_eq!(
+            shell.output("alias ll='ls -l'; alias"),
+            (0, "alias ll='ls -l'\n".to_owned())
+        );
+        assert_eq!(shell.output("unalias ll; alias"), (0, String::new()));
+    }
+
+    #[test]
+    fn falls_back_to_commands_in_path() {
+        let mut shell = Shell::builder()
+            .stdin(io::empty())
+            .stdout(Vec::new())
+            .stderr(Vec::new())
+            .disable_builtin("echo")
+            .disable_history()
+            .build();
+
+        assert!(!shell.is_builtin("echo"));
+        assert!(!shell.output("type echo").1.contains("builtin"));
+        assert_eq!(shell.output("no-such-builtin-here").0, 127);
+    }
+
+    #[test]
+    fn echoes_without_a_newline() {
+        let mut shell = Shell::for_test();
+
+        assert_eq!(shell.output("echo -n hi"), (0, "hi".to_owned()));
+        assert_eq!(shell.output("echo -nn a b"), (0, "a b".to_owned()));
+        assert_eq!(shell.output("echo -n"), (0, String::new()));
+        // Only leading options count
+        assert_eq!(shell.output("echo a -n"), (0, "a -n\n".to_owned()));
+        assert_eq!(shell.output("echo -x -n"), (0, "-x -n\n".to_owned()));
+
+        let path = env::temp_dir().join(format!("shell-echo-test-{}", process::id()));
+        let redirected = format!("echo -n one > {0}; echo -n two >> {0}", path.display());
+        assert_eq!(shell.output(&redirected), (0, String::new()));
+        let contents = fs::read_to_string(&path).unwrap();
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(contents, "onetwo");
+    }
+
+    #[test]
+    fn interprets_escapes_with_echo_e() {
+        let mut shell = Shell::for_test();
+
+        assert_eq!(
+            shell.output(r"echo -e 'a\tb\nc'"),
+            (0, "a\tb\nc\n".to_owned())
+        );
+        assert_eq!(
+            shell.output(r"echo -E 'a\tb\nc'"),
+            (0, "a\\tb\\nc\n".to_owned())
+        );
+        assert_eq!(shell.output(r"echo 'a\tb'"), (0, "a\\tb\n".to_owned()));
+        assert_eq!(
+            shell.output(r"echo -e '\r\\\0\a'"),
+            (0, "\r\\\0\x07\n".to_owned())
+        );
+        // The last of `-e` and `-E` wins, and both combine with `-n`
+        assert_eq!(shell.output(r"echo -eE 'a\t'"), (0, "a\\t\n".to_owned()));
+        assert_eq!(shell.output(r"echo -ne 'x\ty'"), (0, "x\ty".to_owned()));
+        assert_eq!(shell.output(r"echo -e -n 'x\n'"), (0, "x\n".to_owned()));
+        // Escapes are interpreted in the joined words
+        assert_eq!(shell.output(r"echo -e 'a\' 'b'"), (0, "a\\ b\n".to_owned()));
+    }
+
+    #[test]
+    fn shifts_positional_parameters() {
+        let mut shell = Shell::for_test();
+        shell.positional = ["a", "b", "c", "d", "e"].map(str::to_owned).to_vec();
+
+        assert_eq!(shell.output("echo $1 $#"), (0, "a 5\n".to_owned()));
+        assert_eq!(shell.output("shift; echo $1 $#"), (0, "b 4\n".to_owned()));
+        assert_eq!(shell.output("shift 2; echo $@"), (0, "d e\n".to_owned()));
+        // Shifting more than there are fails and keeps them
+        assert_eq!(
+            shell.output("shift 3; echo $? $@"),
+            (0, "1 d e\n".to_owned())
+        );
+        assert_eq!(shell.output("shift 0; echo $? $1"), (0, "0 d\n".to_owned()));
+    }
+
+    #[test]
+    fn shifts_arguments_of_functions() {
+        let mut shell = Shell::for_test();
+
+        assert_eq!(
+            shell.output("f() { while [ $# -gt 0 ]; do echo $1; shift; done; }; f x 'y z'"),
+            (0, "x\ny z\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn waits_for_jobs_and_process_ids() {
+        let mut shell = Shell::for_test();
+
+        // Starting a job prints its id along with the process id of its last command
+        let (_, started) = shell.output("sh -c 'exit 3' &");
+        let pid = started.trim().strip_prefix("[1] ").unwrap().to_owned();
+
+        assert_eq!(
+            shell.output(&format!("wait {pid}; echo $?")),
+            (0, "3\n".to_owned())
+        );
+
+        shell.output("sh -c 'exit 4' &");
+        assert_eq!(shell.output("wait %1; echo $?"), (0, "4\n".to_owned()));
+
+        // A bare number is a process id, not a job
+        shell.output("sh -c 'exit 5' &");
+        assert_eq!(shell.output("wait 1; echo $?"), (0, "127\n".to_owned()));
+        assert_eq!(shell.output("wait %1; echo $?"), (0, "5\n".to_owned()));
+    }
+
+    #[test]
+    fn saves_history_only_from_the_shell() {
+        let path = env::temp_dir().join(format!("shell-fork-history-test-{}", process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut shell = Shell::builder()
+            .stdin(io::empty())
+            .stdout(Vec::new())
+            .stderr(Vec::new())
+            .history_file(&path)
+            .build();
+        shell.history.push("echo one", &shell.variables);
+
+        // Exiting forked copies of the shell doesn't save the entries of the shell
+        assert_eq!(
+            shell.output("echo $(exit 2); true | exit"),
+            (0, "\n".to_owned())
+        );
+        shell.save_history();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(contents, "echo one\n");
+    }
+
+    fn with_history(entries: &[&str]) -> Shell<io::Empty, Vec<u8>, Vec<u8>> {
+        let mut shell = Shell::for_test();
+
+        for entry in entries {
+            shell.history.push(entry, &shell.variables);
+        }
+
+        shell
+    }
+
+    #[test]
+    fn lists_history_with_fc() {
+        let mut shell = with_history(&["echo one", "echo two", "echo three"]);
+
+        assert_eq!(
+            shell.output("fc -l"),
+            (
+                0,
+                "    1  echo one\n    2  echo two\n    3  echo three\n".to_owned()
+            )
+        );
+        assert_eq!(
+            shell.output("fc -l 2"),
+            (0, "    2  echo two\n    3  echo three\n".to_owned())
+        );
+        assert_eq!(
+            shell.output("fc -l -1 1"),
+            (
+                0,
+                "    1  echo one\n    2  echo two\n    3  echo three\n".to_owned()
+            )
+        );
+        assert_eq!(
+            shell.output("fc -l ec 2").1,
+            "    2  echo two\n    3  echo three\n"
+        );
+        assert_eq!(shell.output("fc -l 9").0, 1);
+    }
+
+    #[test]
+    fn runs_what_fc_edited() {
+        let mut shell = with_history(&["echo one", "echo two"]);
+        shell.variables.set("FCEDIT", "sed -i s/one/1/");
+
+        // The edited commands are shown before they run, without arguments the newest one
+        assert_eq!(shell.output("fc 1"), (0, "echo 1\n1\n".to_owned()));
+        assert_eq!(shell.output("fc"), (0, "echo two\ntwo\n".to_owned()));
+    }
+
+    #[test]
+    fn parses_options_with_getopts() {
+        let mut shell = Shell::for_test();
+        shell.positional = ["-a", "-b", "value", "-cx", "file", "-a"]
+            .map(str::to_owned)
+            .to_vec();
+
+        let parsed = shell.output(
+            "while getopts ab:c opt; do
+                if [ $opt = b ]; then echo \"b $OPTARG\"; else echo $opt; fi
+            done
+            echo $? $OPTIND
+            shift $((OPTIND - 1)); echo $@",
+        );
+
+        assert_eq!(parsed, (0, "a\nb value\nc\n?\n0 5\nfile -a\n".to_owned()));
+
+        let stderr = String::from_utf8(std::mem::take(shell.stderr())).unwrap();
+        assert_eq!(stderr, "getopts: illegal option -- x\n");
+    }
+
+    #[test]
+    fn requires_arguments_of_options_with_getopts() {
+        let mut shell = Shell::for_test();
+        shell.positional = vec!["-b".to_owned()];
+
+        assert_eq!(
+            shell.output("getopts b: opt; echo $? $opt \"[$OPTARG]\"; getopts b: opt; echo $?"),
+            (0, "0 ? []\n1\n".to_owned())
+        );
+    }
+}
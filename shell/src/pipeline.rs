@@ -0,0 +1,297 @@
+use std::{
+    io::{self, BufRead, PipeReader, PipeWriter, Read, Write},
+    mem,
+    os::unix::process::CommandExt,
+    process::{self, Child, Command, ExitStatus},
+};
+
+use super::{
+    compound::Compound,
+    jobs::Job,
+    sys::{self, Fork, Pid, STDIN_FD, STDOUT_FD},
+    Result, Shell,
+};
+
+// A running command of a pipeline
+pub enum Stage {
+    External(Child),
+    Builtin(Pid),
+}
+
+impl Stage {
+    pub(super) fn pid(&self) -> Pid {
+        match self {
+            Self::External(child) => child.id() as Pid,
+            Self::Builtin(pid) => *pid,
+        }
+    }
+
+    // Also returns once the command is stopped, unlike `Child::wait()`
+    pub(super) fn wait(&mut self) -> io::Result<ExitStatus> {
+        sys::wait_or_stop(self.pid())
+    }
+
+    // Returns `None` if the command is still running
+    pub(super) fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        match self {
+            Self::External(child) => child.try_wait(),
+            Self::Builtin(pid) => sys::try_waitpid(*pid),
+        }
+    }
+}
+
+impl<R: BufRead, W: Write, E: Write> Shell<R, W, E> {
+    pub(super) fn execute_pipeline(&mut self) -> Result<()> {
+        let line = self.pipeline_line();
+        let stages = self.spawn_pipeline()?;
+
+        // The exit status of the pipeline is the one of its last command, even if that one
+        // failed to start
+        let failed = stages
+            .last()
+            .and_then(|stage| stage.as_ref().err().copied());
+
+        // Wait for every command so no zombie processes are left behind
+        self.wait_foreground(Job::new(line, stages.into_iter().flatten()))?;
+
+        if let Some(status) = failed {
+            self.last_status = status;
+        }
+
+        Ok(())
+    }
+
+    // The expanded commands of the pipeline, which a job stopped in the foreground is listed by
+    fn pipeline_line(&self) -> String {
+        let segments: Vec<_> = self
+            .pipeline
+            .iter()
+            .map(|segment| {
+                [&segment.cmd]
+                    .into_iter()
+                    .chain(&segment.args)
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect();
+
+        segments.join(" | ")
+    }
+
+    // Start every command of the pipeline without waiting for them, commands which failed to
+    // start are replaced by their exit status
+    pub(super) fn spawn_pipeline(&mut self) -> Result<Vec<std::result::Result<Stage, i32>>> {
+        let segments = mem::take(&mut self.pipeline);
+        let last = segments.len() - 1;
+
+        let mut stages = Vec::with_capacity(segments.len());
+        // Read end of the pipe connected to the previous command's stdout
+        let mut stdin = None;
+        // With job control the commands form a process group, led by the first one
+        let mut leader = None;
+
+        for (i, segment) in segments.into_iter().enumerate() {
+            self.load_segment(segment);
+
+            // Every command except the last one writes into a new pipe, the last one inherits
+            // the shell's stdout
+            let (reader, writer) = if i < last {
+                let (reader, writer) = io::pipe()?;
+                (Some(reader), Some(writer))
+            } else {
+                (None, None)
+            };
+
+            let group = self.job_control.then_some(leader.unwrap_or(0));
+
+            // Builtins and functions run in a forked copy of the shell
+            let stage = if self.is_builtin(&self.cmd) || self.is_function(&self.cmd) {
+                self.fork_builtin(stdin.take(), writer, group, Self::dispatch)
+                    .map(Stage::Builtin)
+            } else {
+                self.spawn(stdin.take(), writer, group).map(Stage::External)
+            };
+
+            // A failing command doesn't stop the pipeline, the next command just reads EOF
+            match stage {
+                Ok(stage) => {
+                    leader = leader.or(Some(stage.pid()));
+                    stages.push(Ok(stage));
+                }
+                Err(error) => {
+                    stages.push(Err(error.status()));
+                    writeln!(self.stderr, "{}", error)?;
+                }
+            }
+
+            stdin = reader;
+        }
+
+        Ok(stages)
+    }
+
+    // Start the command in the process group `group`, 0 for a new one led by the command
+    fn spawn(
+        &mut self,
+        stdin: Option<PipeReader>,
+        stdout: Option<PipeWriter>,
+        group: Option<Pid>,
+    ) -> Result<Child> {
+        let (cmd_args, redirects) = self.handle_redirect()?;
+
+        // The pipes are dropped on return which closes the shell's copies of their ends
+        self.start_command(
+            &cmd_args,
+            &redirects,
+            stdin.as_ref(),
+            stdout.as_ref(),
+            |cmd| Self::spawn_in_group(cmd, group),
+        )
+    }
+
+    pub(super) fn spawn_in_group(cmd: &mut Command, group: Option<Pid>) -> io::Result<Child> {
+        if let Some(group) = group {
+            cmd.process_group(group);
+        }
+
+        cmd.spawn()
+    }
+
+    // Run a pipeline with compound commands in it like `ls | while read name; do ...; done`.
+    // Every command runs in a forked copy of the shell, so variables set by them are lost
+    pub(super) fn run_piped(&mut self, stages: &[Compound], is_condition: bool) -> Result<()> {
+        let last = stages.len() - 1;
+
+        let mut running = Vec::with_capacity(stages.len());
+        let mut stdin = None;
+        let mut leader = None;
+
+        for (i, stage) in stages.iter().enumerate() {
+            let (reader, writer) = if i < last {
+                let (reader, writer) = io::pipe()?;
+                (Some(reader), Some(writer))
+            } else {
+                (None, None)
+            };
+
+            let group = self.job_control.then_some(leader.unwrap_or(0));
+            let pid = self.fork_builtin(stdin.take(), writer, group, |shell| {
+                Ok(shell.run_compound(stage, None, is_condition)?)
+            })?;
+
+            leader = leader.or(Some(pid));
+            running.push(Stage::Builtin(pid));
+            stdin = reader;
+        }
+
+        let line = stages.iter().map(Self::describe).collect::<Vec<_>>();
+        self.wait_foreground(Job::new(line.join(" | "), running))
+    }
+
+    // Run `run` like a builtin in a forked copy of the shell, connected to the pipes
+    fn fork_builtin(
+        &mut self,
+        stdin: Option<PipeReader>,
+        stdout: Option<PipeWriter>,
+        group: Option<Pid>,
+        run: impl FnOnce(&mut Self) -> Result<()>,
+    ) -> Result<Pid> {
+        // Flush pending output first, otherwise it would be written by both processes
+        self.flush()?;
+
+        match sys::fork()? {
+            Fork::Parent(pid) => {
+                // Set by both processes, so it is in place whichever of them runs first
+                if let Some(group) = group {
+                    let _ = sys::setpgid(pid, if group == 0 { pid } else { group });
+                }
+
+                Ok(pid)
+            }
+            // The child runs the builtin with its stdin/stdout connected to the pipes and
+            // must never return into the shell's loop
+            Fork::Child => {
+                // Traps are reset before the default signals are restored, as resetting a trap
+                // on `SIGINT` ignores it again in an interactive shell
+                self.job_control = false;
+                self.reset_traps();
+                self.history.disable_saving();
+
+                if let Some(group) = group {
+                    let _ = sys::setpgid(0, group);
+                    let _ = sys::restore_default_signals();
+                }
+
+                let _ = self.use_process_streams();
+
+                // Builtins like `false` report a failure through the status instead of an error
+                self.last_status = 0;
+
+                let code = match Self::connect_pipes(stdin, stdout).and_then(|_| run(self)) {
+                    Ok(()) => self.last_status,
+                    Err(error) => {
+                        let _ = writeln!(self.stderr, "{}", error);
+                        error.status()
+                    }
+                };
+
+                let _ = self.flush();
+                process::exit(code);
+            }
+        }
+    }
+
+    // Run `input` in a forked copy of the shell with its stdout connected to a pipe, returning
+    // everything written into it. The child sees the same variables and aliases as the shell
+    pub(super) fn capture_output(&mut self, input: &str) -> Result<String> {
+        let (mut reader, writer) = io::pipe()?;
+
+        // Flush pending output first, otherwise it would be written by both processes
+        self.flush()?;
+
+        match sys::fork()? {
+            Fork::Parent(pid) => {
+                // Close the shell's copy of the write end, so reading stops once the child exits
+                drop(writer);
+
+                let mut output = Vec::new();
+                let result = reader.read_to_end(&mut output);
+
+                sys::waitpid(pid)?;
+                result?;
+
+                Ok(String::from_utf8_lossy(&output).into_owned())
+            }
+            Fork::Child => {
+                drop(reader);
+                self.job_control = false;
+                self.reset_traps();
+                self.history.disable_saving();
+                let _ = self.use_process_streams();
+
+                if let Err(error) = Self::connect_pipes(None, Some(writer)) {
+                    let _ = writeln!(self.stderr, "{}", error);
+                    let _ = self.flush();
+                    process::exit(1);
+                }
+
+                let _ = self.handle_input(input);
+                let _ = self.flush();
+                process::exit(self.last_status);
+            }
+        }
+    }
+
+    fn connect_pipes(stdin: Option<PipeReader>, stdout: Option<PipeWriter>) -> Result<()> {
+        if let Some(reader) = stdin {
+            sys::dup2(&reader, STDIN_FD)?;
+        }
+
+        if let Some(writer) = stdout {
+            sys::dup2(&writer, STDOUT_FD)?;
+        }
+
+        Ok(())
+    }
+}
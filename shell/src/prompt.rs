@@ -0,0 +1,146 @@
+use std::{
+    io::{BufRead, Write},
+    path::Path,
+};
+
+use super::{sys, Shell};
+
+const BACKSLASH: char = '\\';
+const DOT: char = '.';
+const ESCAPE: char = '\x1b';
+
+// Shown when `$PS1` isn't set
+const DEFAULT_PROMPT: &str = "$ ";
+
+impl<R: BufRead, W: Write, E: Write> Shell<R, W, E> {
+    // Render `$PS1`, which is read again for each prompt so changes apply right away. It may
+    // contain the escapes `\w` for the current directory, `\u` for the user name, `\h` for the
+    // host name up to the first `.`, `\H` for all of it, `\?` for the status of the last
+    // command, `\$` for `$` and `\\` for `\`. `\e` starts an escape sequence, so e.g.
+    // `\e[31m` switches to red text
+    pub(super) fn prompt(&self) -> String {
+        let Some(template) = self.variables.get("PS1") else {
+            return DEFAULT_PROMPT.to_owned();
+        };
+
+        let mut prompt = String::new();
+        let mut chars = template.chars();
+
+        while let Some(c) = chars.next() {
+            if c != BACKSLASH {
+                prompt.push(c);
+                continue;
+            }
+
+            match chars.next() {
+                Some('w') => prompt.push_str(&self.prompt_dir()),
+                Some('u') => prompt.push_str(&self.prompt_user()),
+                Some('h') => {
+                    let hostname = sys::hostname().unwrap_or_default();
+                    prompt.push_str(hostname.split(DOT).next().unwrap_or_default());
+                }
+                Some('H') => prompt.push_str(&sys::hostname().unwrap_or_default()),
+                Some('?') => prompt.push_str(&self.last_status.to_string()),
+                Some('e') => prompt.push(ESCAPE),
+                // `\[` and `\]` only mark invisible parts like colors for other shells
+                Some('[' | ']') => {}
+                Some(c @ ('$' | BACKSLASH)) => prompt.push(c),
+                // Unknown escapes are shown as they are
+                Some(c) => {
+                    prompt.push(BACKSLASH);
+                    prompt.push(c);
+                }
+                None => prompt.push(BACKSLASH),
+            }
+        }
+
+        prompt
+    }
+
+    fn prompt_dir(&self) -> String {
+        self.logical_dir()
+            .map(|dir| self.tilde_path(&dir))
+            .unwrap_or_default()
+    }
+
+    // Show `path` with the home directory shortened to `~`
+    pub(super) fn tilde_path(&self, path: &Path) -> String {
+        let dir = path.display().to_string();
+
+        match self.variables.get("HOME") {
+            Some(home) if !home.is_empty() && dir.starts_with(home) => match &dir[home.len()..] {
+                rest if rest.is_empty() || rest.starts_with('/') => format!("~{}", rest),
+                _ => dir,
+            },
+            _ => dir,
+        }
+    }
+
+    fn prompt_user(&self) -> String {
+        self.variables
+            .get("USER")
+            .or_else(|| self.variables.get("LOGNAME"))
+            .unwrap_or_default()
+            .to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, io, path::PathBuf};
+
+    use super::*;
+
+    fn prompt(shell: &mut Shell<io::Empty, Vec<u8>, Vec<u8>>, ps1: &str) -> String {
+        shell.variables.set("PS1", ps1);
+        shell.prompt()
+    }
+
+    #[test]
+    fn shows_the_default_prompt_without_ps1() {
+        let mut shell = Shell::for_test();
+        shell.variables.remove("PS1");
+
+        assert_eq!(shell.prompt(), "$ ");
+    }
+
+    #[test]
+    fn renders_the_current_directory() {
+        let mut shell = Shell::for_test();
+        let cwd = env::current_dir().unwrap();
+        shell.variables.remove("PWD");
+
+        shell.variables.set("HOME", "/nonexistent-home");
+        assert_eq!(
+            prompt(&mut shell, "\\w \\$ "),
+            format!("{} $ ", cwd.display())
+        );
+
+        // The home directory is shortened to `~`, but not a directory merely starting with it
+        let parent = cwd.parent().unwrap();
+        shell.variables.set("HOME", parent.display().to_string());
+        let name = cwd.file_name().unwrap().to_string_lossy();
+        assert_eq!(prompt(&mut shell, "[\\w]"), format!("[~/{}]", name));
+
+        shell.variables.set("HOME", cwd.display().to_string());
+        assert_eq!(prompt(&mut shell, "\\w"), "~");
+
+        let sibling = PathBuf::from(format!("{}x", cwd.display()));
+        assert_eq!(shell.tilde_path(&sibling), sibling.display().to_string());
+    }
+
+    #[test]
+    fn renders_escapes() {
+        let mut shell = Shell::for_test();
+        shell.variables.set("USER", "someone");
+
+        assert_eq!(prompt(&mut shell, "\\u@\\$ "), "someone@$ ");
+        assert_eq!(prompt(&mut shell, "a\\\\b"), "a\\b");
+        assert_eq!(prompt(&mut shell, "\\e[31m>\\e[0m"), "\x1b[31m>\x1b[0m");
+        assert_eq!(prompt(&mut shell, "\\[x\\]"), "x");
+        assert_eq!(prompt(&mut shell, "\\q \\"), "\\q \\");
+
+        shell.output("false");
+        assert_eq!(prompt(&mut shell, "\\? "), "1 ");
+    }
+}
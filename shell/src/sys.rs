@@ -0,0 +1,528 @@
+use std::{
+    ffi::CString,
+    io,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        raw::{c_char, c_int, c_void},
+        unix::{
+            ffi::OsStrExt,
+            process::{CommandExt, ExitStatusExt},
+        },
+    },
+    path::Path,
+    process::{Command, ExitStatus},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+// The layouts and constants below are only known for these platforms
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+compile_error!("the shell only supports Linux and macOS");
+
+// Bindings to the few libc functions which are not exposed by the standard library. The
+// standard library already links against libc, so no extra crate is needed for these
+extern "C" {
+    #[link_name = "fork"]
+    fn libc_fork() -> c_int;
+    #[link_name = "dup2"]
+    fn libc_dup2(old_fd: c_int, new_fd: c_int) -> c_int;
+    #[link_name = "write"]
+    fn libc_write(fd: c_int, buf: *const c_void, count: usize) -> isize;
+    #[link_name = "close"]
+    fn libc_close(fd: c_int) -> c_int;
+    #[link_name = "fcntl"]
+    fn libc_fcntl(fd: c_int, cmd: c_int, ...) -> c_int;
+    #[link_name = "waitpid"]
+    fn libc_waitpid(pid: c_int, status: *mut c_int, options: c_int) -> c_int;
+    #[link_name = "tcgetattr"]
+    fn libc_tcgetattr(fd: c_int, termios: *mut Termios) -> c_int;
+    #[link_name = "tcsetattr"]
+    fn libc_tcsetattr(fd: c_int, optional_actions: c_int, termios: *const Termios) -> c_int;
+    #[link_name = "kill"]
+    fn libc_kill(pid: c_int, signum: c_int) -> c_int;
+    #[link_name = "signal"]
+    fn libc_signal(signum: c_int, handler: SigHandler) -> SigHandler;
+    #[link_name = "gethostname"]
+    fn libc_gethostname(name: *mut c_char, len: usize) -> c_int;
+    #[link_name = "access"]
+    fn libc_access(path: *const c_char, mode: c_int) -> c_int;
+    #[link_name = "setpgid"]
+    fn libc_setpgid(pid: c_int, pgid: c_int) -> c_int;
+    #[link_name = "getpgrp"]
+    fn libc_getpgrp() -> c_int;
+    #[link_name = "tcsetpgrp"]
+    fn libc_tcsetpgrp(fd: c_int, pgid: c_int) -> c_int;
+}
+
+pub type Pid = i32;
+
+// `sighandler_t`, either a function pointer or one of the special values below
+type SigHandler = usize;
+
+const SIG_DFL: SigHandler = 0;
+const SIG_IGN: SigHandler = 1;
+const SIG_ERR: SigHandler = !0;
+
+const SIGINT: c_int = 2;
+pub const SIGTERM: c_int = 15;
+
+// Modes for `access()`
+pub const R_OK: c_int = 4;
+pub const W_OK: c_int = 2;
+pub const X_OK: c_int = 1;
+
+// Signals which can be given by name, like `kill -KILL`. Most numbers are the same everywhere
+const SIGNALS: [(&str, c_int); 9] = [
+    ("HUP", 1),
+    ("INT", SIGINT),
+    ("QUIT", 3),
+    ("KILL", 9),
+    ("SEGV", 11),
+    ("PIPE", 13),
+    ("ALRM", 14),
+    ("TERM", SIGTERM),
+    ("CHLD", CHLD),
+];
+
+#[cfg(target_os = "linux")]
+const CHLD: c_int = 17;
+#[cfg(target_os = "linux")]
+pub const SIGCONT: c_int = 18;
+#[cfg(target_os = "linux")]
+const SIGSTOP: c_int = 19;
+#[cfg(target_os = "linux")]
+pub const SIGTSTP: c_int = 20;
+#[cfg(target_os = "linux")]
+const JOB_CONTROL_SIGNALS: [(&str, c_int); 7] = [
+    ("USR1", 10),
+    ("USR2", 12),
+    ("CONT", SIGCONT),
+    ("STOP", SIGSTOP),
+    ("TSTP", SIGTSTP),
+    ("TTIN", SIGTTIN),
+    ("TTOU", SIGTTOU),
+];
+
+#[cfg(target_os = "macos")]
+const CHLD: c_int = 20;
+#[cfg(target_os = "macos")]
+pub const SIGCONT: c_int = 19;
+#[cfg(target_os = "macos")]
+const SIGSTOP: c_int = 17;
+#[cfg(target_os = "macos")]
+pub const SIGTSTP: c_int = 18;
+#[cfg(target_os = "macos")]
+const JOB_CONTROL_SIGNALS: [(&str, c_int); 7] = [
+    ("USR1", 30),
+    ("USR2", 31),
+    ("CONT", SIGCONT),
+    ("STOP", SIGSTOP),
+    ("TSTP", SIGTSTP),
+    ("TTIN", SIGTTIN),
+    ("TTOU", SIGTTOU),
+];
+
+// Sent to a process group outside of the foreground reading from or writing to the terminal
+const SIGTTIN: c_int = 21;
+const SIGTTOU: c_int = 22;
+
+// Signals the shell ignores while it controls jobs, so only the commands it runs stop
+const STOP_SIGNALS: [c_int; 3] = [SIGTSTP, SIGTTIN, SIGTTOU];
+
+const WNOHANG: c_int = 1;
+const WUNTRACED: c_int = 2;
+
+// Signals caught by `trap` since they were last taken, as bits by signal number. Updated from
+// the signal handler, which may do little more than that
+static PENDING_SIGNALS: AtomicU64 = AtomicU64::new(0);
+
+// Whether the shell ignores Ctrl-C, which is what resetting a trap on `SIGINT` goes back to
+static INTERRUPTS_IGNORED: AtomicBool = AtomicBool::new(false);
+
+// Whether the shell controls jobs, so resetting a trap on one of `STOP_SIGNALS` ignores it again
+static JOB_CONTROL: AtomicBool = AtomicBool::new(false);
+
+// `errno` of `exec()` for a file which isn't in a format the kernel can run
+pub const ENOEXEC: c_int = 8;
+
+const F_GETFD: c_int = 1;
+#[cfg(target_os = "linux")]
+const F_DUPFD_CLOEXEC: c_int = 1030;
+#[cfg(target_os = "macos")]
+const F_DUPFD_CLOEXEC: c_int = 67;
+
+// Copies made by `dup()` start above the file descriptors which can be redirected, so they
+// never end up in the way of applying a redirection
+const DUP_MIN_FD: c_int = 10;
+
+// Layout of `struct termios` and the flags needed to switch the terminal into raw mode, which
+// differ between platforms
+#[cfg(target_os = "linux")]
+mod termios {
+    pub type TcFlag = u32;
+    pub type Speed = u32;
+
+    pub const NCCS: usize = 32;
+    pub const VTIME: usize = 5;
+    pub const VMIN: usize = 6;
+
+    pub const ISIG: TcFlag = 0o000001;
+    pub const ICANON: TcFlag = 0o000002;
+    pub const ECHO: TcFlag = 0o000010;
+    pub const IEXTEN: TcFlag = 0o100000;
+    pub const ICRNL: TcFlag = 0o000400;
+    pub const IXON: TcFlag = 0o002000;
+}
+
+#[cfg(target_os = "macos")]
+mod termios {
+    pub type TcFlag = u64;
+    pub type Speed = u64;
+
+    pub const NCCS: usize = 20;
+    pub const VMIN: usize = 16;
+    pub const VTIME: usize = 17;
+
+    pub const ECHO: TcFlag = 0x00000008;
+    pub const ISIG: TcFlag = 0x00000080;
+    pub const ICANON: TcFlag = 0x00000100;
+    pub const IEXTEN: TcFlag = 0x00000400;
+    pub const IXON: TcFlag = 0x00000200;
+    pub const ICRNL: TcFlag = 0x00000100;
+}
+
+use termios::{Speed, TcFlag, ECHO, ICANON, ICRNL, IEXTEN, ISIG, IXON, NCCS, VMIN, VTIME};
+
+const TCSADRAIN: c_int = 1;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Termios {
+    c_iflag: TcFlag,
+    c_oflag: TcFlag,
+    c_cflag: TcFlag,
+    c_lflag: TcFlag,
+    #[cfg(target_os = "linux")]
+    c_line: u8,
+    c_cc: [u8; NCCS],
+    c_ispeed: Speed,
+    c_ospeed: Speed,
+}
+
+pub const STDIN_FD: RawFd = 0;
+pub const STDOUT_FD: RawFd = 1;
+pub const STDERR_FD: RawFd = 2;
+
+// Result of `fork()`, as seen from either side of the call
+pub enum Fork {
+    Parent(Pid),
+    Child,
+}
+
+pub fn fork() -> io::Result<Fork> {
+    // SAFETY: the child only runs shell builtins before exiting. The only other thread scans
+    // `$PATH`, and the child never waits for the lock of the index it may be holding
+    match unsafe { libc_fork() } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => Ok(Fork::Child),
+        pid => Ok(Fork::Parent(pid)),
+    }
+}
+
+pub fn dup2<F: AsRawFd>(fd: &F, target: RawFd) -> io::Result<()> {
+    // SAFETY: `dup2()` only operates on file descriptor numbers
+    match unsafe { libc_dup2(fd.as_raw_fd(), target) } {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+// Copy `fd` to a new file descriptor, which is closed once a program is run
+pub fn dup(fd: RawFd) -> io::Result<OwnedFd> {
+    // SAFETY: `fcntl()` only operates on file descriptor numbers
+    match unsafe { libc_fcntl(fd, F_DUPFD_CLOEXEC, DUP_MIN_FD) } {
+        -1 => Err(io::Error::last_os_error()),
+        // SAFETY: the new file descriptor is open and not owned by anything else
+        new_fd => Ok(unsafe { OwnedFd::from_raw_fd(new_fd) }),
+    }
+}
+
+pub fn is_open(fd: RawFd) -> bool {
+    // SAFETY: `fcntl()` only operates on file descriptor numbers
+    unsafe { libc_fcntl(fd, F_GETFD) != -1 }
+}
+
+// Write to `fd` without taking ownership of it. Unlike `io::stdout()` this fails with `EBADF`
+// if the file descriptor is closed, instead of pretending the bytes were written
+pub fn write(fd: RawFd, buf: &[u8]) -> io::Result<usize> {
+    // SAFETY: `buf` is valid for reads of its length
+    match unsafe { libc_write(fd, buf.as_ptr().cast(), buf.len()) } {
+        -1 => Err(io::Error::last_os_error()),
+        len => Ok(len as usize),
+    }
+}
+
+pub fn close(fd: RawFd) -> io::Result<()> {
+    // SAFETY: `close()` only operates on file descriptor numbers. Reading or writing a closed
+    // standard stream then fails with `EBADF`
+    match unsafe { libc_close(fd) } {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+// Switch the terminal on stdin into raw mode, returning its previous settings. Keystrokes are
+// then read one at a time without echo, and Ctrl-C or Ctrl-Z no longer generate signals.
+// Output processing stays enabled so `\n` still moves to the start of the next line
+pub fn enable_raw_mode() -> io::Result<Termios> {
+    let mut termios = Termios {
+        c_iflag: 0,
+        c_oflag: 0,
+        c_cflag: 0,
+        c_lflag: 0,
+        #[cfg(target_os = "linux")]
+        c_line: 0,
+        c_cc: [0; NCCS],
+        c_ispeed: 0,
+        c_ospeed: 0,
+    };
+
+    // SAFETY: `termios` is a valid pointer to a struct with the platform's layout
+    if unsafe { libc_tcgetattr(STDIN_FD, &mut termios) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let original = termios;
+
+    termios.c_lflag &= !(ECHO | ICANON | ISIG | IEXTEN);
+    termios.c_iflag &= !(IXON | ICRNL);
+    // Return from `read()` as soon as a single byte is available
+    termios.c_cc[VMIN] = 1;
+    termios.c_cc[VTIME] = 0;
+
+    set_terminal_mode(&termios)?;
+    Ok(original)
+}
+
+pub fn set_terminal_mode(termios: &Termios) -> io::Result<()> {
+    // SAFETY: `termios` is a valid pointer to a struct with the platform's layout
+    match unsafe { libc_tcsetattr(STDIN_FD, TCSADRAIN, termios) } {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+// Ignore Ctrl-C in the shell itself, so it only interrupts the command running in the
+// foreground. At the prompt the line editor reads Ctrl-C as a key instead
+pub fn ignore_interrupts() -> io::Result<()> {
+    INTERRUPTS_IGNORED.store(true, Ordering::Relaxed);
+    set_signal_handler(SIGINT, SIG_IGN)
+}
+
+// Let Ctrl-C interrupt and Ctrl-Z stop `cmd` again, as ignored signals stay ignored across
+// `exec()`
+pub fn restore_interrupts(cmd: &mut Command) {
+    // SAFETY: `signal()` is async-signal-safe, so it may be called between `fork()` and
+    // `exec()`
+    unsafe {
+        cmd.pre_exec(restore_default_signals);
+    }
+}
+
+// Like `restore_interrupts()` for a forked copy of the shell which keeps running
+pub fn restore_default_signals() -> io::Result<()> {
+    set_signal_handler(SIGINT, SIG_DFL)?;
+
+    STOP_SIGNALS
+        .iter()
+        .try_for_each(|&signum| set_signal_handler(signum, SIG_DFL))
+}
+
+// Whether `signum` stops the process it is sent to
+pub fn is_stop_signal(signum: c_int) -> bool {
+    signum == SIGSTOP || STOP_SIGNALS.contains(&signum)
+}
+
+// Put the shell into a process group of its own which owns the terminal, so the process
+// groups of the jobs it starts can take turns in the foreground
+pub fn enable_job_control() -> io::Result<()> {
+    JOB_CONTROL.store(true, Ordering::Relaxed);
+
+    STOP_SIGNALS
+        .iter()
+        .try_for_each(|&signum| set_signal_handler(signum, SIG_IGN))?;
+
+    // Fails if the shell already leads its session, which is fine as its group is its own then
+    let _ = setpgid(0, 0);
+
+    set_foreground(process_group())
+}
+
+// Move process `pid` into the group `pgid`, 0 for either means the calling process
+pub fn setpgid(pid: Pid, pgid: Pid) -> io::Result<()> {
+    // SAFETY: `setpgid()` only operates on process ids
+    match unsafe { libc_setpgid(pid, pgid) } {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+// The process group of the shell
+pub fn process_group() -> Pid {
+    // SAFETY: `getpgrp()` has no arguments and always succeeds
+    unsafe { libc_getpgrp() }
+}
+
+// Let the process group `pgid` read from and write to the terminal on stdin
+pub fn set_foreground(pgid: Pid) -> io::Result<()> {
+    // SAFETY: `tcsetpgrp()` only operates on a file descriptor and process group id
+    match unsafe { libc_tcsetpgrp(STDIN_FD, pgid) } {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+// Parse a signal given by number like `9` or by name like `KILL` or `SIGKILL`
+pub fn parse_signal(signal: &str) -> Option<c_int> {
+    if let Ok(signum) = signal.parse() {
+        return Some(signum);
+    }
+
+    let signal = signal.to_uppercase();
+    let name = signal.strip_prefix("SIG").unwrap_or(&signal);
+
+    SIGNALS
+        .iter()
+        .chain(JOB_CONTROL_SIGNALS.iter())
+        .find_map(|&(known, signum)| (known == name).then_some(signum))
+}
+
+// The name of `signum` like `INT`, if it has one
+pub fn signal_name(signum: c_int) -> Option<&'static str> {
+    SIGNALS
+        .iter()
+        .chain(JOB_CONTROL_SIGNALS.iter())
+        .find_map(|&(name, known)| (known == signum).then_some(name))
+}
+
+// Catch `signum` instead of its default action, so it shows up in `take_pending_signals()`
+pub fn catch_signal(signum: c_int) -> io::Result<()> {
+    // Only bits for the numbers which fit can be recorded
+    if !(1..64).contains(&signum) {
+        return Err(io::ErrorKind::InvalidInput.into());
+    }
+
+    set_signal_handler(signum, record_signal as extern "C" fn(c_int) as SigHandler)
+}
+
+pub fn ignore_signal(signum: c_int) -> io::Result<()> {
+    set_signal_handler(signum, SIG_IGN)
+}
+
+// Go back to what `signum` did before it was caught or ignored
+pub fn reset_signal(signum: c_int) -> io::Result<()> {
+    let ignored = if signum == SIGINT {
+        INTERRUPTS_IGNORED.load(Ordering::Relaxed)
+    } else {
+        STOP_SIGNALS.contains(&signum) && JOB_CONTROL.load(Ordering::Relaxed)
+    };
+
+    let handler = if ignored { SIG_IGN } else { SIG_DFL };
+
+    set_signal_handler(signum, handler)
+}
+
+// The signals caught since the last call, by increasing number
+pub fn take_pending_signals() -> Vec<c_int> {
+    let pending = PENDING_SIGNALS.swap(0, Ordering::SeqCst);
+
+    (1..64)
+        .filter(|signum| pending & (1 << signum) != 0)
+        .collect()
+}
+
+extern "C" fn record_signal(signum: c_int) {
+    // Atomics are safe to use from a signal handler, unlike locks or allocations
+    PENDING_SIGNALS.fetch_or(1 << signum, Ordering::SeqCst);
+}
+
+pub fn kill(pid: Pid, signum: c_int) -> io::Result<()> {
+    // SAFETY: `kill()` only operates on process ids and signal numbers
+    match unsafe { libc_kill(pid, signum) } {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+// Whether the shell may access `path` with `mode`, which is any of `R_OK`, `W_OK` and `X_OK`.
+// Unlike checking the permission bits this accounts for the user and its groups
+pub fn access(path: &Path, mode: c_int) -> bool {
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+
+    // SAFETY: `path` is a valid nul-terminated string for the duration of the call
+    unsafe { libc_access(path.as_ptr(), mode) == 0 }
+}
+
+pub fn hostname() -> io::Result<String> {
+    // Host names are limited to 255 bytes, the rest of the buffer keeps the name terminated
+    let mut name = [0u8; 256];
+
+    // SAFETY: the length passed leaves the last byte of the buffer as terminator
+    match unsafe { libc_gethostname(name.as_mut_ptr().cast(), name.len() - 1) } {
+        -1 => Err(io::Error::last_os_error()),
+        _ => {
+            let len = name
+                .iter()
+                .position(|&byte| byte == 0)
+                .unwrap_or(name.len());
+            Ok(String::from_utf8_lossy(&name[..len]).into_owned())
+        }
+    }
+}
+
+fn set_signal_handler(signum: c_int, handler: SigHandler) -> io::Result<()> {
+    // SAFETY: the handler is either one of the special values `SIG_DFL` and `SIG_IGN`, or
+    // `record_signal()` which only touches an atomic
+    match unsafe { libc_signal(signum, handler) } {
+        SIG_ERR => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+pub fn waitpid(pid: Pid) -> io::Result<ExitStatus> {
+    // Without `WNOHANG` the call only returns once the child changed state
+    wait_for(pid, 0).map(Option::unwrap_or_default)
+}
+
+// Like `waitpid()`, but also returns once the child is stopped like by Ctrl-Z, which the
+// status tells by `ExitStatusExt::stopped_signal()`
+pub fn wait_or_stop(pid: Pid) -> io::Result<ExitStatus> {
+    wait_for(pid, WUNTRACED).map(Option::unwrap_or_default)
+}
+
+// Like `waitpid()`, but returns `None` instead of blocking if the child is still running
+pub fn try_waitpid(pid: Pid) -> io::Result<Option<ExitStatus>> {
+    wait_for(pid, WNOHANG)
+}
+
+fn wait_for(pid: Pid, options: c_int) -> io::Result<Option<ExitStatus>> {
+    let mut status = 0;
+
+    loop {
+        // SAFETY: `status` is a valid pointer for the duration of the call
+        match unsafe { libc_waitpid(pid, &mut status, options) } {
+            -1 => {
+                let error = io::Error::last_os_error();
+
+                // Retry if interrupted by a signal before the child changed state
+                if error.kind() != io::ErrorKind::Interrupted {
+                    return Err(error);
+                }
+            }
+            // Only returned with `WNOHANG` while the child hasn't changed state yet
+            0 => return Ok(None),
+            _ => return Ok(Some(ExitStatus::from_raw(status))),
+        }
+    }
+}
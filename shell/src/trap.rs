@@ -0,0 +1,135 @@
+use std::{
+    io::{self, BufRead, Write},
+    os::raw::c_int,
+};
+
+use super::{error::ShellError, sys, Result, Shell};
+
+// Pseudo signal whose action runs when the shell exits, like in `trap 'rm -f $TMP' EXIT`
+const EXIT: c_int = 0;
+const EXIT_NAME: &str = "EXIT";
+
+// Given instead of an action, `trap - INT` resets the signal to what it did before
+const RESET: &str = "-";
+
+impl<R: BufRead, W: Write, E: Write> Shell<R, W, E> {
+    pub(super) fn trap(&mut self) -> Result<()> {
+        let args = self.args.take().unwrap_or_default();
+
+        let Some((action, signals)) = args.split_first() else {
+            return self.print_traps();
+        };
+
+        // A single argument like `trap INT` resets the signal as well
+        let (action, signals) = match signals {
+            [] => (RESET, &args[..]),
+            _ => (action.as_str(), signals),
+        };
+
+        for signal in signals {
+            let signum = self.parse_trap_signal(signal)?;
+
+            match action {
+                RESET => {
+                    self.traps.remove(&signum);
+                }
+                _ => {
+                    self.traps.insert(signum, action.to_owned());
+                }
+            }
+
+            // Nothing needs to be installed for exiting, the action is looked up on exit
+            if signum == EXIT {
+                continue;
+            }
+
+            // An empty action ignores the signal, e.g. `trap '' INT`
+            match action {
+                RESET => sys::reset_signal(signum)?,
+                "" => sys::ignore_signal(signum)?,
+                _ => sys::catch_signal(signum).map_err(|_| {
+                    ShellError::InvalidExpression(
+                        self.cmd.clone(),
+                        format!("{}: cannot trap signal", signal),
+                    )
+                })?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_trap_signal(&self, signal: &str) -> Result<c_int> {
+        if signal.eq_ignore_ascii_case(EXIT_NAME) {
+            return Ok(EXIT);
+        }
+
+        sys::parse_signal(signal)
+            .filter(|&signum| signum == EXIT || sys::signal_name(signum).is_some())
+            .ok_or_else(|| {
+                ShellError::InvalidExpression(
+                    self.cmd.clone(),
+                    format!("{}: invalid signal specification", signal),
+                )
+            })
+    }
+
+    // List the traps in a form which can be used again, like `trap -- 'echo bye' EXIT`
+    fn print_traps(&mut self) -> Result<()> {
+        let mut traps: Vec<_> = self.traps.iter().collect();
+        traps.sort();
+
+        for (&signum, action) in traps {
+            let name = match signum {
+                EXIT => EXIT_NAME,
+                _ => sys::signal_name(signum).unwrap_or_default(),
+            };
+
+            writeln!(
+                self.stdout,
+                "trap -- '{}' {}",
+                action.replace('\'', "'\\''"),
+                name
+            )?;
+        }
+
+        Ok(())
+    }
+
+    // Run the actions of the signals caught since the last time, in between two commands
+    pub(super) fn run_traps(&mut self) -> io::Result<()> {
+        for signum in sys::take_pending_signals() {
+            let Some(action) = self.traps.get(&signum).cloned() else {
+                continue;
+            };
+
+            // The action doesn't change `$?` of the commands after it
+            let status = self.last_status;
+            self.handle_input(&action)?;
+            self.last_status = status;
+        }
+
+        Ok(())
+    }
+
+    // Run the action of `trap ... EXIT` right before the shell exits
+    pub(super) fn run_exit_trap(&mut self) -> io::Result<()> {
+        // It is removed first, so `exit` inside of it doesn't run it again
+        match self.traps.remove(&EXIT) {
+            Some(action) => self.handle_input(&action),
+            None => Ok(()),
+        }
+    }
+
+    // Forked copies of the shell like for `$(...)` don't run the traps of the shell, only
+    // ignored signals stay ignored
+    pub(super) fn reset_traps(&mut self) {
+        for (&signum, action) in &self.traps {
+            if signum != EXIT && !action.is_empty() {
+                let _ = sys::reset_signal(signum);
+            }
+        }
+
+        self.traps.retain(|_, action| action.is_empty());
+    }
+}
@@ -1,14 +1,29 @@
 use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
     env,
-    fs::{File, OpenOptions},
-    io::{self, Write},
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufWriter, PipeReader, PipeWriter, Read, Seek, Write},
+    iter::Peekable,
     mem,
-    path::PathBuf,
+    os::{
+        fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd},
+        unix::{
+            fs::{OpenOptionsExt, PermissionsExt},
+            process::CommandExt,
+        },
+    },
+    path::{Path, PathBuf},
+    process::{self, Command},
 };
 
-use super::Shell;
-
-pub const BUILTINS: [&str; 5] = ["cd", "echo", "exit", "pwd", "type"];
+use super::{
+    error::ShellError,
+    expand::BACKTICK,
+    glob::Word,
+    lexer::Token,
+    sys::{self, STDERR_FD, STDIN_FD, STDOUT_FD},
+    Output, Result, Shell, TIME,
+};
 
 const SINGLE_QUOTES: char = '\'';
 const DOUBLE_QUOTES: char = '"';
@@ -16,48 +31,606 @@ const NEWLINE: char = '\n';
 const BACKSLASH: char = '\\';
 const SPACE: char = ' ';
 const PROMPT: char = '$';
+const TILDE: char = '~';
+const STAR: char = '*';
+const QUESTION_MARK: char = '?';
+const BRACKET_OPEN: char = '[';
+const BRACKET_CLOSE: char = ']';
+const PAREN_OPEN: char = '(';
+const PAREN_CLOSE: char = ')';
+const HASH: char = '#';
+const LESS: char = '<';
+const DASH: char = '-';
+const TAB: char = '\t';
+const AT: char = '@';
+
+// Characters of operators, which end a word like whitespace does
+const OPERATOR_CHARS: &str = ";&|<>";
+
+// Runs executable files which have no `#!` line
+const SCRIPT_SHELL: &str = "/bin/sh";
+
+// A single command of a pipeline, with all expansions applied
+pub struct ParsedCommand {
+    pub cmd: String,
+    pub args: Vec<String>,
+    // Variables only set for this command, like `FOO=bar` in `FOO=bar cmd`
+    pub assignments: Vec<(String, String)>,
+    // Applied in the given order once the command runs
+    pub redirects: Vec<Redirect>,
+}
+
+// A redirection of a file descriptor of a command
+pub enum Redirect {
+    // `< file` or `0< file` for stdin, `3< file` for another file descriptor
+    Input {
+        fd: RawFd,
+        path: String,
+    },
+    // `> file` or `1> file` for stdout, `2> file` for stderr and so on, appending with `>>` or
+    // `2>>`. `>|` forces overwriting an existing file even with `set -C`
+    Output {
+        fd: RawFd,
+        path: String,
+        append: bool,
+        force: bool,
+    },
+    // `2>&1`, `1>&2` or `3<&0`, pointing `fd` to wherever `target` points at that moment
+    Duplicate {
+        fd: RawFd,
+        target: RawFd,
+    },
+    // `2>&-` or `<&-`, closing `fd` so using it fails
+    Close(RawFd),
+    // The expanded body of a here-document like `<<EOF`, or the word of `<<< word` followed by
+    // a newline, which `fd` reads from
+    HereDoc {
+        fd: RawFd,
+        text: String,
+    },
+}
+
+// Where the redirected file descriptors of a command point. Those which aren't redirected are
+// inherited from the shell, or connected to the pipes of a pipeline
+#[derive(Default)]
+pub(super) struct Redirects(BTreeMap<RawFd, Descriptor>);
+
+// What a file descriptor of a command points to
+pub(super) enum Descriptor {
+    File(File),
+    // Wherever the file descriptor points when not redirected, i.e. the terminal or a pipe
+    Default(RawFd),
+    Closed,
+}
+
+// Copies of file descriptors of the shell, to point them back once a builtin is done
+pub(super) type SavedFds = Vec<(RawFd, Option<OwnedFd>)>;
+
+// The pipes a command of a pipeline is connected to unless redirected
+#[derive(Default)]
+struct Pipes<'a> {
+    stdin: Option<BorrowedFd<'a>>,
+    stdout: Option<BorrowedFd<'a>>,
+}
+
+impl Redirects {
+    pub(super) fn has(&self, fd: RawFd) -> bool {
+        self.0.contains_key(&fd)
+    }
+
+    // Point to the current destination of `target`, like `2>&1`
+    fn duplicate(&self, target: RawFd) -> Result<Descriptor> {
+        match self.0.get(&target) {
+            Some(descriptor) => Ok(descriptor.try_clone()?),
+            None if sys::is_open(target) => Ok(Descriptor::Default(target)),
+            None => Err(ShellError::BadFileDescriptor(target)),
+        }
+    }
+
+    // Point the file descriptors of the shell itself to the redirections, like `exec > file`.
+    // Returns copies of what they pointed to before, `None` for those which weren't open
+    pub(super) fn apply_to_shell(self) -> io::Result<SavedFds> {
+        // Copy all sources before changing anything, as e.g. `exec 2>&1 > file` points stderr
+        // to stdout from before
+        let sources = self
+            .0
+            .into_iter()
+            .map(|(fd, descriptor)| Ok((fd, descriptor.resolve(&Pipes::default())?)))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let mut saved = Vec::new();
+
+        for (fd, source) in sources {
+            saved.push((fd, sys::dup(fd).ok()));
+
+            match source {
+                Some(source) => sys::dup2(&source, fd)?,
+                // Closing a file descriptor which isn't open changes nothing
+                None => drop(sys::close(fd)),
+            }
+        }
+
+        Ok(saved)
+    }
+
+    // Point the file descriptors of the shell back to where they did before `apply_to_shell()`
+    pub(super) fn restore_shell(saved: SavedFds) -> io::Result<()> {
+        for (fd, source) in saved {
+            match source {
+                Some(source) => sys::dup2(&source, fd)?,
+                None => drop(sys::close(fd)),
+            }
+        }
+
+        Ok(())
+    }
+
+    // Connect the file descriptors of `cmd`. `stdin` and `stdout` are the pipes of a command of
+    // a pipeline, which redirections take precedence over
+    pub(super) fn apply(
+        &self,
+        cmd: &mut Command,
+        stdin: Option<&PipeReader>,
+        stdout: Option<&PipeWriter>,
+    ) -> io::Result<()> {
+        let pipes = Pipes {
+            stdin: stdin.map(AsFd::as_fd),
+            stdout: stdout.map(AsFd::as_fd),
+        };
+
+        if !self.0.contains_key(&STDIN_FD) {
+            if let Some(pipe) = pipes.stdin {
+                cmd.stdin(pipe.try_clone_to_owned()?);
+            }
+        }
+
+        if !self.0.contains_key(&STDOUT_FD) {
+            if let Some(pipe) = pipes.stdout {
+                cmd.stdout(pipe.try_clone_to_owned()?);
+            }
+        }
+
+        // Only stdin, stdout and stderr can be passed to `Command`, the child sets up other
+        // file descriptors and closes the ones closed by redirections itself
+        let mut child_fds = Vec::new();
+
+        for (&fd, descriptor) in &self.0 {
+            match (fd, descriptor.resolve(&pipes)?) {
+                (STDIN_FD, Some(source)) => cmd.stdin(source),
+                (STDOUT_FD, Some(source)) => cmd.stdout(source),
+                (STDERR_FD, Some(source)) => cmd.stderr(source),
+                (fd, source) => {
+                    child_fds.push((fd, source));
+                    continue;
+                }
+            };
+        }
+
+        if !child_fds.is_empty() {
+            // SAFETY: `dup2()` and `close()` are async-signal-safe, so they may be called
+            // between `fork()` and `exec()`. The copies are numbered above any file descriptor
+            // which can be redirected, so none is overwritten before it is used
+            unsafe {
+                cmd.pre_exec(move || {
+                    for (fd, source) in &child_fds {
+                        match source {
+                            Some(source) => sys::dup2(source, *fd)?,
+                            // Closing a file descriptor which isn't open changes nothing
+                            None => drop(sys::close(*fd)),
+                        }
+                    }
+
+                    Ok(())
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Descriptor {
+    // A copy of where the descriptor points, or `None` if it's closed
+    fn resolve(&self, pipes: &Pipes) -> io::Result<Option<OwnedFd>> {
+        let source = match (self, &pipes.stdin, &pipes.stdout) {
+            (Self::File(file), ..) => file.as_raw_fd(),
+            (Self::Default(STDIN_FD), Some(pipe), _) => pipe.as_raw_fd(),
+            (Self::Default(STDOUT_FD), _, Some(pipe)) => pipe.as_raw_fd(),
+            (Self::Default(fd), ..) => *fd,
+            (Self::Closed, ..) => return Ok(None),
+        };
+
+        sys::dup(source).map(Some)
+    }
+
+    fn try_clone(&self) -> io::Result<Self> {
+        let descriptor = match self {
+            Self::File(file) => Self::File(file.try_clone()?),
+            Self::Default(fd) => Self::Default(*fd),
+            Self::Closed => Self::Closed,
+        };
+
+        Ok(descriptor)
+    }
+}
+
+impl<R: BufRead, W: Write, E: Write> Shell<R, W, E> {
+    pub(super) fn split_list(tokens: Vec<Token>) -> Result<Vec<(Vec<Token>, Option<Token>)>> {
+        // Split into pipelines separated by `;`, a newline or `&` or connected by `&&` or `||`,
+        // keeping the operator which follows each pipeline
+        let mut list = Vec::new();
+        let mut pipeline = Vec::new();
+
+        for token in tokens {
+            match token {
+                Token::Semicolon | Token::Newline | Token::Background | Token::And | Token::Or => {
+                    list.push((mem::take(&mut pipeline), Some(token)));
+                }
+                _ => pipeline.push(token),
+            }
+        }
+        // Push last pipeline which isn't followed by an operator
+        list.push((pipeline, None));
+
+        // Empty commands like in `echo a ;; echo b`, after `sleep 1 &` or blank lines are
+        // allowed around `;`, newlines and `&`
+        Self::check_operands(&list, |token| {
+            matches!(token, Token::Semicolon | Token::Newline | Token::Background)
+        })?;
 
-impl Shell {
-    pub(super) fn parse_input(&mut self, input: &str) {
-        // Iterator over characters of input string
-        let mut chars = input.trim().chars();
+        Ok(list)
+    }
+
+    // Parse a pipeline like `ls -l | grep rs > out` into its commands. Aliases, variables,
+    // command substitutions and globs are expanded, which is why this needs the shell
+    pub fn parse_input(&mut self, input: &str) -> Result<Vec<ParsedCommand>> {
+        let tokens = self.expand_aliases(Self::tokenize(input)?)?;
+
+        self.parse_pipeline(tokens)
+    }
+
+    pub(super) fn parse_pipeline(&mut self, tokens: Vec<Token>) -> Result<Vec<ParsedCommand>> {
+        let mut segments = Vec::new();
+        let mut segment = Vec::new();
+
+        for token in tokens {
+            match token {
+                Token::Pipe => segments.push((mem::take(&mut segment), Some(token))),
+                _ => segment.push(token),
+            }
+        }
+        segments.push((segment, None));
+
+        // Every command of a pipeline needs a name, e.g. `ls |` or `| grep rs` are invalid
+        Self::check_operands(&segments, |_| false)?;
+
+        segments
+            .into_iter()
+            .map(|(segment, _)| self.parse_segment(segment))
+            .collect()
+    }
+
+    // Expand aliases before the input is split into its commands, so the value of an alias
+    // may hold a whole list like `alias up='cd ..; ls'`
+    pub(super) fn expand_aliases(&self, tokens: Vec<Token>) -> Result<Vec<Token>> {
+        let mut expanded_tokens = Vec::with_capacity(tokens.len());
+        let mut pending = VecDeque::from(tokens);
+
+        // Aliases which were already expanded for the current command, so `alias ls='ls -la'`
+        // doesn't expand forever
+        let mut expanded = HashSet::new();
+        // The number of tokens at the front of `pending` which come from the value of an alias.
+        // Commands in there don't reset `expanded`, so `alias x='x; x'` ends as well
+        let mut alias_tokens = 0_usize;
+        // Only the first word of each command is looked up, which includes the one after a
+        // keyword like `then` or `time`
+        let mut is_cmd_start = true;
+
+        while let Some(token) = pending.pop_front() {
+            let is_from_alias = alias_tokens > 0;
+            alias_tokens = alias_tokens.saturating_sub(1);
+
+            if let Token::Word(word) = &token {
+                let value = self.aliases.get(word).filter(|_| is_cmd_start);
+
+                if let Some(value) = value.filter(|_| expanded.insert(word.clone())) {
+                    // The value may itself start with an alias, so it is looked at again
+                    let tokens = Self::tokenize(value)?;
+                    alias_tokens += tokens.len();
+
+                    for token in tokens.into_iter().rev() {
+                        pending.push_front(token);
+                    }
+                    continue;
+                }
+            }
+
+            is_cmd_start = match &token {
+                Token::Word(word) => is_cmd_start && (word == TIME || Self::is_opening_word(word)),
+                token => matches!(
+                    token,
+                    Token::Pipe
+                        | Token::Semicolon
+                        | Token::Newline
+                        | Token::Background
+                        | Token::And
+                        | Token::Or
+                ),
+            };
+
+            if is_cmd_start && !is_from_alias {
+                expanded.clear();
+            }
+
+            expanded_tokens.push(token);
+        }
+
+        Ok(expanded_tokens)
+    }
+
+    fn parse_segment(&mut self, tokens: Vec<Token>) -> Result<ParsedCommand> {
+        let mut words = Vec::new();
+        let mut assignments = Vec::new();
+        let mut redirects = Vec::new();
+
+        let mut tokens = tokens.into_iter();
+
+        while let Some(token) = tokens.next() {
+            let redirect = match token {
+                Token::Word(word) => {
+                    // Assignments like `FOO=bar` are only recognized in front of the command
+                    match Self::split_assignment(&word).filter(|_| words.is_empty()) {
+                        Some((name, value)) => {
+                            // Values are never split into several words
+                            let value = self.expand_word(value)?.join(" ");
+                            assignments.push((name.to_owned(), value));
+                        }
+                        None => words.extend(self.expand_word(&word)?),
+                    }
+
+                    continue;
+                }
+                Token::RedirectIn(fd) => Redirect::Input {
+                    fd,
+                    path: self.redirect_target(tokens.next())?,
+                },
+                Token::RedirectOut(fd) | Token::RedirectAppend(fd) | Token::RedirectForce(fd) => {
+                    Redirect::Output {
+                        fd,
+                        path: self.redirect_target(tokens.next())?,
+                        append: matches!(token, Token::RedirectAppend(_)),
+                        force: matches!(token, Token::RedirectForce(_)),
+                    }
+                }
+                Token::Duplicate(fd, target) => Redirect::Duplicate { fd, target },
+                Token::Close(fd) => Redirect::Close(fd),
+                Token::HereString(fd) => Redirect::HereDoc {
+                    fd,
+                    text: format!("{}\n", self.redirect_target(tokens.next())?),
+                },
+                Token::HereDoc {
+                    fd,
+                    delimiter,
+                    body,
+                    ..
+                } => {
+                    let body = body.unwrap_or_default();
+
+                    // Quoting any part of the delimiter like in `<<'EOF'` keeps the body as-is
+                    let is_quoted = delimiter
+                        .is_some_and(|delimiter| Self::unquote_delimiter(&delimiter) != delimiter);
+
+                    let text = if is_quoted {
+                        body
+                    } else {
+                        self.expand_here_doc(&body)?
+                    };
 
-        self.cmd = Self::parse_cmd(&mut chars); // Parse command as string
-        self.args = Self::parse_args(&mut chars); // Parse arguments as vector of strings
+                    Redirect::HereDoc { fd, text }
+                }
+                // Operators between commands were already split off
+                token => return Err(ShellError::SyntaxError(token.to_string())),
+            };
+
+            redirects.push(redirect);
+        }
+
+        let mut words = words.into_iter();
+        let cmd = words.next().unwrap_or_default();
+
+        Ok(ParsedCommand {
+            cmd,
+            args: words.collect(),
+            assignments,
+            redirects,
+        })
+    }
+
+    fn redirect_target(&mut self, token: Option<Token>) -> Result<String> {
+        match token {
+            Some(Token::Word(word)) => Ok(self.expand_word(&word)?.join(" ")),
+            // A redirection at the end of the input like `echo >` is missing its file
+            Some(token) => Err(ShellError::SyntaxError(token.to_string())),
+            None => Err(ShellError::SyntaxError("newline".to_owned())),
+        }
+    }
+
+    fn split_assignment(word: &str) -> Option<(&str, &str)> {
+        word.split_once('=').filter(|(name, _)| Self::is_name(name))
+    }
+
+    // Remove quotes and expand a single word of the input. Unquoted command substitutions and
+    // globs may expand into several words, while an empty word like `""` or `$UNSET` vanishes
+    pub(super) fn expand_word(&mut self, word: &str) -> Result<Vec<String>> {
+        let (mut args, mut last) = self.parse_args(&mut word.chars().peekable(), true)?;
+        // Push last argument to the vector of arguments
+        Self::save_arg(&mut last, &mut args);
+
+        Ok(args)
+    }
+
+    // Remove quotes and expand `word` without splitting it into several words or matching it
+    // against paths, like the parts of `${NAME/pattern/string}`
+    pub(super) fn expand_unsplit(&mut self, word: &str) -> Result<Word> {
+        let (_, word) = self.parse_args(&mut word.chars().peekable(), false)?;
+        Ok(word)
     }
 
-    fn parse_cmd<I: Iterator<Item = char>>(chars: &mut I) -> String {
-        let mut cmd = String::new();
+    // Whether `input` ends inside quotes, with a `\` or inside a compound command like `if`,
+    // in which case the command continues on the next line
+    pub(super) fn is_incomplete(input: &str) -> bool {
+        let mut chars = input.chars().peekable();
 
         let mut in_single_quotes = false;
         let mut in_double_quotes = false;
+        let mut word_start = true;
+        // Delimiters of the here-documents whose bodies start on the next line, along with
+        // whether leading tabs are removed from their lines
+        let mut here_docs = Vec::new();
+
+        while let Some(c) = chars.next() {
+            let at_word_start = mem::replace(&mut word_start, false);
+            let is_quoted = in_single_quotes || in_double_quotes;
 
-        for c in chars {
             match c {
+                // `<<<` is followed by a word and not by a here-document
+                LESS if !is_quoted && chars.next_if_eq(&LESS).is_some() => {
+                    if chars.next_if_eq(&LESS).is_some() {
+                        continue;
+                    }
+
+                    let strip_tabs = chars.next_if_eq(&DASH).is_some();
+                    while chars.next_if(|&c| c == SPACE || c == TAB).is_some() {}
+
+                    let mut delimiter = String::new();
+
+                    while let Some(c) =
+                        chars.next_if(|&c| !c.is_whitespace() && !OPERATOR_CHARS.contains(c))
+                    {
+                        delimiter.push(c);
+                    }
+
+                    here_docs.push((Self::unquote_delimiter(&delimiter), strip_tabs));
+                }
+                // Quotes inside the bodies of here-documents don't open anything either
+                NEWLINE if !is_quoted => {
+                    for (delimiter, strip_tabs) in here_docs.drain(..) {
+                        loop {
+                            let line: String =
+                                chars.by_ref().take_while(|&c| c != NEWLINE).collect();
+                            let line = if strip_tabs {
+                                line.trim_start_matches(TAB)
+                            } else {
+                                &line
+                            };
+
+                            if line == delimiter {
+                                break;
+                            }
+
+                            if chars.peek().is_none() {
+                                return true;
+                            }
+                        }
+                    }
+
+                    word_start = true;
+                }
+                // Quotes inside a comment like `# don't` don't open anything
+                HASH if at_word_start && !is_quoted => {
+                    while chars.next_if(|&c| c != NEWLINE).is_some() {}
+                }
                 SINGLE_QUOTES if !in_double_quotes => Self::toggle_bool(&mut in_single_quotes),
                 DOUBLE_QUOTES if !in_single_quotes => Self::toggle_bool(&mut in_double_quotes),
-                // If not inside single quotes or double quotes then we reached the end of
-                // command and can start parsing the arguments
-                SPACE if !in_single_quotes && !in_double_quotes => break,
-                // Store any other character inside `cmd`
-                _ => cmd.push(c),
+                BACKSLASH if !in_single_quotes => match chars.next() {
+                    None => return true,
+                    Some(NEWLINE) if chars.peek().is_none() => return true,
+                    Some(_) => {}
+                },
+                // `$'...'` may contain escaped quotes, so unlike `'...'` skip over escapes
+                PROMPT
+                    if !in_single_quotes
+                        && !in_double_quotes
+                        && chars.next_if_eq(&SINGLE_QUOTES).is_some() =>
+                {
+                    loop {
+                        match chars.next() {
+                            Some(SINGLE_QUOTES) => break,
+                            Some(BACKSLASH) => {
+                                chars.next();
+                            }
+                            Some(_) => {}
+                            None => return true,
+                        }
+                    }
+                }
+                c if !is_quoted && (c.is_whitespace() || OPERATOR_CHARS.contains(c)) => {
+                    word_start = true;
+                }
+                _ => {}
             }
         }
-        cmd
+
+        in_single_quotes
+            || in_double_quotes
+            || Self::tokenize(input).is_ok_and(|tokens| Self::has_open_compound(&tokens))
     }
 
-    fn parse_args<I: Iterator<Item = char>>(chars: &mut I) -> Option<Vec<String>> {
+    fn check_operands<F>(list: &[(Vec<Token>, Option<Token>)], is_separator: F) -> Result<()>
+    where
+        F: Fn(&Token) -> bool,
+    {
+        for (i, (tokens, operator)) in list.iter().enumerate() {
+            if !tokens.is_empty() {
+                continue;
+            }
+
+            let previous = i.checked_sub(1).and_then(|i| list[i].1.as_ref());
+
+            // Only separators like `;` may surround an empty command, any other operator needs a
+            // command on both sides
+            let token = [operator.as_ref(), previous]
+                .into_iter()
+                .flatten()
+                .find(|operator| !is_separator(operator));
+
+            if let Some(token) = token {
+                return Err(ShellError::SyntaxError(token.to_string()));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Returns the finished arguments along with the last one, which `split` decides whether
+    // unquoted whitespace and command output end an argument
+    fn parse_args<I: Iterator<Item = char>>(
+        &mut self,
+        chars: &mut Peekable<I>,
+        split: bool,
+    ) -> Result<(Vec<String>, Word)> {
         // Characters which should be escaped by `\`
-        const ESCAPABLE: [char; 4] = [BACKSLASH, PROMPT, DOUBLE_QUOTES, NEWLINE];
+        const ESCAPABLE: [char; 5] = [BACKSLASH, PROMPT, DOUBLE_QUOTES, NEWLINE, BACKTICK];
 
         let mut args = Vec::new();
-        let mut curr_arg = String::new();
+        let mut curr_arg = Word::default();
 
         let mut in_single_quotes = false;
         let mut in_double_quotes = false;
         let mut escape_next = false;
+        // Whether the current character is the first one of an argument
+        let mut word_start = true;
+
+        while let Some(c) = chars.next() {
+            let at_word_start = mem::replace(&mut word_start, false);
+
+            // A `\` at the end of a line continues the command on the next one, both are removed
+            // like in other shells, so `echo a\<newline>b` prints `ab`
+            if escape_next && c == NEWLINE {
+                Self::toggle_bool(&mut escape_next);
+                continue;
+            }
 
-        for c in chars {
             // If `escape_next` is truthy then escape current character
             if escape_next {
                 // If inside double quotes or character is not an escapable character then
@@ -78,29 +651,119 @@ impl Shell {
                 DOUBLE_QUOTES if !in_single_quotes => Self::toggle_bool(&mut in_double_quotes),
                 BACKSLASH if !in_single_quotes => Self::toggle_bool(&mut escape_next),
                 BACKSLASH => curr_arg.push(c),
-                // When encountering a space and not inside quotes then we parsed a
+                // Replace `$((expr))` with the value of `expr`, and `$(cmd)` and `` `cmd` `` with
+                // the output of `cmd`
+                PROMPT if !in_single_quotes && chars.next_if_eq(&PAREN_OPEN).is_some() => {
+                    if chars.next_if_eq(&PAREN_OPEN).is_some() {
+                        // Reading up to the matching `)` leaves the second `)` of `))`
+                        let expr = Self::read_paren_cmd(chars);
+                        chars.next_if_eq(&PAREN_CLOSE);
+
+                        curr_arg.push_str(&self.expand_arithmetic(&expr)?.to_string());
+                    } else {
+                        let output = self.substitute(&Self::read_paren_cmd(chars));
+                        let is_quoted = in_double_quotes || !split;
+                        Self::push_output(&output, is_quoted, &mut curr_arg, &mut args);
+                    }
+                }
+                BACKTICK if !in_single_quotes => {
+                    let output = self.substitute(&Self::read_backtick_cmd(chars));
+                    let is_quoted = in_double_quotes || !split;
+                    Self::push_output(&output, is_quoted, &mut curr_arg, &mut args);
+                }
+                // `$'...'` is quoted like `'...'` but decodes escapes like `\t`
+                PROMPT
+                    if !in_single_quotes
+                        && !in_double_quotes
+                        && chars.next_if_eq(&SINGLE_QUOTES).is_some() =>
+                {
+                    curr_arg.push_str(&Self::read_ansi_c_quoted(chars))
+                }
+                // `"$@"` is an argument for each positional parameter, with text before and after
+                // it joined to the first and last one. Otherwise `$@` and `$*` are all of them
+                // separated by spaces, which are split like the output of commands
+                PROMPT if !in_single_quotes && matches!(chars.peek(), Some(&(AT | STAR))) => {
+                    let is_separate = chars.next() == Some(AT) && in_double_quotes && split;
+
+                    if is_separate {
+                        for (i, param) in self.positional.iter().enumerate() {
+                            if i > 0 {
+                                Self::save_arg(&mut curr_arg, &mut args);
+                            }
+
+                            curr_arg.push_str(param);
+                        }
+                    } else {
+                        let is_quoted = in_double_quotes || !split;
+                        let params = self.positional.join(" ");
+                        Self::push_output(&params, is_quoted, &mut curr_arg, &mut args);
+                    }
+                }
+                // Expand variables unless inside single quotes, keep `$` if no name follows
+                PROMPT if !in_single_quotes => match self.expand_var(chars)? {
+                    Some(value) => curr_arg.push_str(&value),
+                    None => curr_arg.push(c),
+                },
+                // When encountering a space or tab and not inside quotes then we parsed a
                 // complete argument, so push it to the vector and clear the string and
                 // proceed with next argument
-                SPACE if !in_single_quotes && !in_double_quotes => {
-                    Self::save_arg(&mut curr_arg, &mut args)
+                SPACE | TAB if split && !in_single_quotes && !in_double_quotes => {
+                    Self::save_arg(&mut curr_arg, &mut args);
+                    word_start = true;
+                }
+                // Unquoted `*`, `?` and `[...]` make the argument a pattern matched against paths
+                STAR | QUESTION_MARK | BRACKET_OPEN | BRACKET_CLOSE
+                    if !in_single_quotes && !in_double_quotes =>
+                {
+                    curr_arg.push_glob(c)
+                }
+                // Expand an unquoted `~` or `~user` at the start of an argument, keep it
+                // literally if the user doesn't exist
+                TILDE if at_word_start && !in_single_quotes && !in_double_quotes => {
+                    let mut user = String::new();
+
+                    while let Some(c) = chars.next_if(|&c| Self::is_user_char(c)) {
+                        user.push(c);
+                    }
+
+                    match self.expand_tilde(&user) {
+                        Some(home) => curr_arg.push_str(&home),
+                        None => {
+                            curr_arg.push(c);
+                            curr_arg.push_str(&user);
+                        }
+                    }
                 }
                 _ => curr_arg.push(c),
             }
         }
-        // Push last argument to the vector of arguments
-        Self::save_arg(&mut curr_arg, &mut args);
 
-        if args.is_empty() {
-            None
-        } else {
-            Some(args)
-        }
+        Ok((args, curr_arg))
     }
 
-    fn save_arg(arg: &mut String, args: &mut Vec<String>) {
-        // Using `mem::take()` here avoids allocating `String`s on the heap
+    fn save_arg(arg: &mut Word, args: &mut Vec<String>) {
+        // Patterns expand into all paths they match, sorted
         if !arg.is_empty() {
-            args.push(mem::take(arg));
+            args.extend(arg.expand());
+        }
+    }
+
+    // Unquoted output of a command substitution is split into separate arguments on whitespace,
+    // so `touch $(cat names)` creates a file per name. Inside double quotes like in
+    // `echo "$(date)"` it stays part of a single argument including its inner whitespace
+    fn push_output(output: &str, is_quoted: bool, arg: &mut Word, args: &mut Vec<String>) {
+        if is_quoted {
+            return arg.push_str(output);
+        }
+
+        for (i, field) in output.split(char::is_whitespace).enumerate() {
+            // Whitespace ends the current argument, so text right before `$(...)` is joined
+            // with the first field and text right after it with the last one
+            if i > 0 {
+                Self::save_arg(arg, args);
+            }
+
+            arg.push_str(field);
         }
     }
 
@@ -108,57 +771,315 @@ impl Shell {
         *b = !*b;
     }
 
-    pub(super) fn handle_redirect(&self) -> io::Result<(Vec<String>, Option<File>, Option<File>)> {
-        // Arguments up to redirection symbols (`>`, `1>`, `1>>`, `2>`, `2>>`)
-        let mut cmd_args = Vec::new();
+    pub(super) fn handle_redirect(&mut self) -> Result<(Vec<String>, Redirects)> {
+        // The arguments are moved out instead of cloned, as the command consumes them anyway
+        let cmd_args = self.args.take().unwrap_or_default();
 
-        let mut stdout_file = None; // File for stdout
-        let mut stderr_file = None; // File for stderr
+        Ok((cmd_args, self.open_redirects()?))
+    }
+
+    // Redirections are applied from left to right like in other shells, so duplicating a file
+    // descriptor with e.g. `2>&1` copies wherever the other one points to at that moment:
+    // `cmd > file 2>&1` sends both streams to `file`, while `cmd 2>&1 > file` sends stderr to
+    // where stdout pointed before, i.e. the terminal, and only stdout to `file`. Closing with
+    // `2>&-` only lasts until the file descriptor is redirected again
+    pub(super) fn open_redirects(&self) -> Result<Redirects> {
+        let mut redirects = Redirects::default();
+        // Devices like `/dev/null` are only opened once, e.g. `> /dev/null 2> /dev/null` shares
+        // the same file for both
+        let mut devices: Vec<(&str, File)> = Vec::new();
+
+        for redirect in &self.redirects {
+            let (fd, descriptor) = match redirect {
+                Redirect::Input { fd, path } => match Self::standard_stream(path) {
+                    Some(target) => (*fd, redirects.duplicate(target)?),
+                    None => (*fd, Descriptor::File(Self::open_input_file(path)?)),
+                },
+                Redirect::Output {
+                    fd,
+                    path,
+                    append,
+                    force,
+                } => {
+                    let device = devices.iter().find(|(device, _)| device == path);
+
+                    let descriptor = match (Self::standard_stream(path), device) {
+                        (Some(target), _) => redirects.duplicate(target)?,
+                        (None, Some((_, device))) => Descriptor::File(device.try_clone()?),
+                        (None, None) => {
+                            // With `set -C` only `>|` may overwrite existing files
+                            let noclobber = self.flags.noclobber && !append && !force;
+                            let file = Self::create_output_file(path, *append, noclobber)?;
+
+                            if !file.metadata()?.is_file() {
+                                devices.push((path, file.try_clone()?));
+                            }
+
+                            Descriptor::File(file)
+                        }
+                    };
+
+                    (*fd, descriptor)
+                }
+                Redirect::Duplicate { fd, target } => (*fd, redirects.duplicate(*target)?),
+                Redirect::Close(fd) => (*fd, Descriptor::Closed),
+                Redirect::HereDoc { fd, text } => {
+                    (*fd, Descriptor::File(Self::create_here_doc_file(text)?))
+                }
+            };
+
+            redirects.0.insert(fd, descriptor);
+        }
+
+        Ok(redirects)
+    }
+
+    // Like in other shells these name the file descriptors of the command itself, so e.g.
+    // `> /dev/stderr` also works where the file doesn't exist and for commands of a pipeline
+    fn standard_stream(path: &str) -> Option<RawFd> {
+        match path {
+            "/dev/stdin" => Some(STDIN_FD),
+            "/dev/stdout" => Some(STDOUT_FD),
+            "/dev/stderr" => Some(STDERR_FD),
+            _ => None,
+        }
+    }
+
+    fn open_input_file(path: &str) -> Result<File> {
+        // Open existing file as read-only
+        File::open(path)
+            .map_err(|error| ShellError::RedirectionError(PathBuf::from(path), error.kind()))
+    }
+
+    fn create_here_doc_file(text: &str) -> Result<File> {
+        // The text is read from a file which is removed right away, so it's gone once the
+        // command is done with it
+        let mut i = 0;
+
+        loop {
+            let path = env::temp_dir().join(format!("shell-{}-{}", process::id(), i));
+
+            let mut file = match OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&path)
+            {
+                Ok(file) => file,
+                // Left behind by another process which had the same id
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {
+                    i += 1;
+                    continue;
+                }
+                Err(error) => return Err(ShellError::RedirectionError(path, error.kind())),
+            };
+
+            fs::remove_file(&path)?;
+            file.write_all(text.as_bytes())?;
+            file.rewind()?;
+
+            return Ok(file);
+        }
+    }
 
-        let args = match self.args {
-            Some(ref args) => args,
-            None => {
-                return Ok((cmd_args, stdout_file, stderr_file));
+    fn create_output_file(path: &str, append: bool, noclobber: bool) -> Result<File> {
+        let metadata = fs::metadata(path);
+
+        // Checked up front, as not every platform reports a directory as such when opening it
+        if metadata.as_ref().is_ok_and(|metadata| metadata.is_dir()) {
+            return Err(ShellError::RedirectionError(
+                PathBuf::from(path),
+                io::ErrorKind::IsADirectory,
+            ));
+        }
+
+        // Devices like `/dev/null` and named pipes are written to as they are, there is nothing
+        // to create or truncate
+        if metadata.as_ref().is_ok_and(|metadata| !metadata.is_file()) {
+            return OpenOptions::new()
+                .write(true)
+                .open(path)
+                .map_err(|error| ShellError::RedirectionError(PathBuf::from(path), error.kind()));
+        }
+
+        // Create file which if doesn't exists will be created, then either append to it (`>>`)
+        // or truncate it (`>`). With `noclobber` opening fails if the file exists, which is
+        // reported as refusing to overwrite it
+        // New files are readable and writable by everyone, minus what the umask takes away
+        OpenOptions::new()
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .create(true)
+            .create_new(noclobber)
+            .mode(0o666)
+            .open(path)
+            .map_err(|error| ShellError::RedirectionError(PathBuf::from(path), error.kind()))
+    }
+
+    // Build the command running the program `self.cmd` refers to. Names without a `/` are
+    // looked up in `$PATH`, remembering where they were found for next time
+    pub(super) fn new_command(&mut self) -> Result<Command> {
+        let program = if self.cmd.contains('/') {
+            PathBuf::from(&self.cmd)
+        } else {
+            // Borrow the name by taking it out while the cache is updated, instead of cloning
+            let name = mem::take(&mut self.cmd);
+            let program = self.hash_cmd(&name);
+            self.cmd = name;
+
+            match program {
+                Some(program) => program,
+                // A file which isn't executable is still reported differently from none at all
+                None if self.exists_in_path(&self.cmd) => {
+                    return Err(ShellError::PermissionDenied(self.cmd.clone()));
+                }
+                None => return Err(ShellError::CommandNotFound(self.cmd.clone())),
             }
         };
 
-        // Iterator over arguments (String)
-        let mut iter = args.iter();
+        // The program still sees the name it was called by instead of its full path
+        let mut cmd = Command::new(program);
+        cmd.arg0(&self.cmd);
+
+        Ok(cmd)
+    }
+
+    // Start the program `self.cmd` refers to with `start`, e.g. `Command::spawn`, after setting
+    // up its arguments, variables and redirections. A file the kernel can't execute, like a
+    // script without `#!`, is run by `/bin/sh` instead, unless it looks like a binary. Some C
+    // libraries already do so themselves when executing the program
+    pub(super) fn start_command<T>(
+        &mut self,
+        args: &[String],
+        redirects: &Redirects,
+        stdin: Option<&PipeReader>,
+        stdout: Option<&PipeWriter>,
+        start: impl Fn(&mut Command) -> io::Result<T>,
+    ) -> Result<T> {
+        let mut cmd = self.new_command()?;
+        self.prepare_command(&mut cmd, args, redirects, stdin, stdout)?;
+
+        match start(&mut cmd) {
+            Err(error) if error.raw_os_error() == Some(sys::ENOEXEC) => {
+                let program = Path::new(cmd.get_program());
+
+                if Self::is_binary_file(program) {
+                    return Err(ShellError::CannotExecute(self.cmd.clone()));
+                }
+
+                let mut script = Command::new(SCRIPT_SHELL);
+                script.arg(program);
+                self.prepare_command(&mut script, args, redirects, stdin, stdout)?;
+
+                start(&mut script).map_err(|error| self.spawn_error(error))
+            }
+            result => result.map_err(|error| self.spawn_error(error)),
+        }
+    }
+
+    fn prepare_command(
+        &self,
+        cmd: &mut Command,
+        args: &[String],
+        redirects: &Redirects,
+        stdin: Option<&PipeReader>,
+        stdout: Option<&PipeWriter>,
+    ) -> io::Result<()> {
+        cmd.args(args);
+        // The program only sees the variables of the shell, not the environment of the process
+        cmd.env_clear();
+        cmd.envs(self.variables.iter());
+        cmd.envs(self.assignments.iter().map(|(name, value)| (name, value)));
+        sys::restore_interrupts(cmd);
+
+        // Redirecting from or to a file takes precedence over the pipes
+        redirects.apply(cmd, stdin, stdout)
+    }
+
+    // Like other shells a file counts as binary if its first line contains a NUL byte
+    fn is_binary_file(path: &Path) -> bool {
+        let mut start = [0; 80];
 
-        while let Some(arg) = iter.next() {
-            match arg.as_str() {
-                // Create file of path from next argument after redirection symbol for stdout
-                ">" | "1>" | ">>" | "1>>" => stdout_file = Self::create_output_file(iter.next())?,
-                // Create file of path from next argument after redirection symbol for stderr
-                "2>" | "2>>" => stderr_file = Self::create_output_file(iter.next())?,
-                // Any other argument we pass to `args`
-                _ => cmd_args.push(arg.to_owned()),
+        File::open(path)
+            .and_then(|mut file| file.read(&mut start))
+            .is_ok_and(|len| {
+                start[..len]
+                    .split(|&b| b == b'\n')
+                    .next()
+                    .is_some_and(|line| line.contains(&0))
+            })
+    }
+
+    // Tell why the program of `new_command` couldn't be run
+    pub(super) fn spawn_error(&self, error: io::Error) -> ShellError {
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => ShellError::PermissionDenied(self.cmd.clone()),
+            _ => ShellError::CommandNotFound(self.cmd.clone()),
+        }
+    }
+
+    // Look up `name` in the cache of `hash`, or in `$PATH` if it isn't cached yet or the cached
+    // executable is gone
+    pub(super) fn hash_cmd(&mut self, name: &str) -> Option<PathBuf> {
+        if let Some(path) = self.hashed.get(name) {
+            if Self::is_executable(path) {
+                return Some(path.clone());
             }
+
+            self.hashed.remove(name);
         }
 
-        Ok((cmd_args, stdout_file, stderr_file))
+        let path = self.find_exe_in_path(name)?;
+        self.hashed.insert(name.to_owned(), path.clone());
+
+        Some(path)
     }
 
-    fn create_output_file(arg: Option<&String>) -> io::Result<Option<File>> {
-        // Create file which if doesn't exists will be created and can be appended to
-        arg.map(|path| OpenOptions::new().append(true).create(true).open(path))
-            .transpose() // Option<Result<T,E> -> Result<Option<T>, E>
+    pub(super) fn find_exe_in_path(&self, name: &str) -> Option<PathBuf> {
+        // Prefer the index of `$PATH` once it's ready, but search `$PATH` itself if the
+        // executable was only installed or was removed since the index was built
+        let paths = self.variables.get("PATH").unwrap_or_default();
+
+        if let Some(path) = self.path_index.find(name, paths) {
+            if Self::is_executable(&path) {
+                return Some(path);
+            }
+        }
+
+        // The first match is the one which runs
+        self.find_exes_in_path(name).into_iter().next()
     }
 
-    pub(super) fn find_exe_in_path(name: &str) -> Option<PathBuf> {
+    pub(super) fn find_exes_in_path(&self, name: &str) -> Vec<PathBuf> {
         // Get `$PATH` and split on `:` to get all environment paths, then check if command is in
-        // one of these paths
-        env::var_os("PATH").and_then(|paths| {
-            env::split_paths(&paths).find_map(|path| {
-                let full_path = path.join(name);
-                full_path.exists().then_some(full_path)
-            })
-        })
+        // each of these paths
+        let paths = self.variables.get("PATH").unwrap_or_default();
+
+        env::split_paths(paths)
+            .map(|path| path.join(name))
+            .filter(|full_path| Self::is_executable(full_path))
+            .collect()
     }
 
-    pub(super) fn print_prompt(&mut self) -> io::Result<()> {
-        // Print prompt `$ ` and then flush to force direct output
-        write!(self.stdout, "$ ")?;
+    // Whether any directory of `$PATH` contains a file called `name`, executable or not
+    fn exists_in_path(&self, name: &str) -> bool {
+        let paths = self.variables.get("PATH").unwrap_or_default();
+
+        env::split_paths(paths).any(|path| path.join(name).is_file())
+    }
+
+    pub(super) fn is_executable(path: &Path) -> bool {
+        // Only regular files with any of the execute bits set can be run, this also rules out
+        // directories which have execute bits set for traversal
+        path.metadata()
+            .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+    }
+
+    pub(super) fn print_prompt(&mut self, prompt: &str) -> io::Result<()> {
+        // Print prompt like `$ ` and then flush to force direct output
+        write!(self.stdout, "{}", prompt)?;
         self.flush()?;
         Ok(())
     }
@@ -168,4 +1089,107 @@ impl Shell {
         self.stderr.flush()?;
         Ok(())
     }
+
+    // Called in forked copies of the shell, whose input and output has to go through the pipes
+    // connected to their stdin and stdout instead of the streams of the shell
+    pub(super) fn use_process_streams(&mut self) -> io::Result<()> {
+        // The stdin of the process is read like a redirected one, see `read`
+        self.stdin_redirected = true;
+
+        self.flush()?;
+        self.stdout.get_mut().fd = Some(STDOUT_FD);
+        self.stderr.get_mut().fd = Some(STDERR_FD);
+        Ok(())
+    }
+
+    // Write to stdout or stderr of the process while they are redirected, like for `pwd > file`,
+    // even if the shell was built with other writers. Returns the file descriptors written to
+    // before
+    pub(super) fn redirect_output(
+        &mut self,
+        redirects: &Redirects,
+    ) -> (Option<RawFd>, Option<RawFd>) {
+        let fds = (self.stdout.get_mut().fd, self.stderr.get_mut().fd);
+
+        if redirects.has(STDOUT_FD) {
+            self.stdout.get_mut().fd = Some(STDOUT_FD);
+        }
+
+        if redirects.has(STDERR_FD) {
+            self.stderr.get_mut().fd = Some(STDERR_FD);
+        }
+
+        fds
+    }
+
+    // Write out what a builtin printed. Output to a stream which can't be written to, like a
+    // closed one in `echo hi >&-`, is thrown away, and fails the builtin if it is stdout
+    pub(super) fn flush_output(&mut self) -> Result<()> {
+        // An error about stderr can't be reported anywhere
+        if self.stderr.flush().is_err() {
+            Self::discard(&mut self.stderr);
+        }
+
+        self.stdout.flush().map_err(|error| {
+            Self::discard(&mut self.stdout);
+            ShellError::WriteError(self.cmd.clone(), error)
+        })
+    }
+
+    fn discard<T: Write>(output: &mut BufWriter<Output<T>>) {
+        output.get_mut().discard = true;
+        let _ = output.flush();
+        output.get_mut().discard = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_aliases_holding_lists() {
+        let mut shell = Shell::for_test();
+        shell
+            .aliases
+            .insert("x".to_owned(), "echo a; echo b".to_owned());
+        shell.aliases.insert("y".to_owned(), "echo d".to_owned());
+
+        assert_eq!(shell.output("x"), (0, "a\nb\n".to_owned()));
+        assert_eq!(shell.output("x && y"), (0, "a\nb\nd\n".to_owned()));
+        assert_eq!(
+            shell.output("if true; then x; fi"),
+            (0, "a\nb\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn expands_aliases_only_once() {
+        let mut shell = Shell::for_test();
+        shell
+            .aliases
+            .insert("echo".to_owned(), "echo -n".to_owned());
+        shell.aliases.insert("x".to_owned(), "echo x; x".to_owned());
+
+        assert_eq!(shell.output("echo a"), (0, "a".to_owned()));
+        assert_eq!(shell.output("echo b x"), (0, "b x".to_owned()));
+        assert_eq!(shell.output("x 2>/dev/null").1, "x");
+    }
+
+    #[test]
+    fn decodes_ansi_c_quotes() {
+        let mut shell = Shell::for_test();
+
+        assert_eq!(shell.output(r"echo $'a\tb'"), (0, "a\tb\n".to_owned()));
+        assert_eq!(shell.output(r"echo $'\x41\x4a'"), (0, "AJ\n".to_owned()));
+        assert_eq!(
+            shell.output(r"echo $'one\ntwo\r' $'\\\0'"),
+            (0, "one\ntwo\r \\\0\n".to_owned())
+        );
+        // Unknown escapes keep their backslash, and plain single quotes decode nothing
+        assert_eq!(
+            shell.output(r"echo $'\q' '\t'"),
+            (0, "\\q \\t\n".to_owned())
+        );
+    }
 }
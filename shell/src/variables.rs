@@ -0,0 +1,44 @@
+use std::{collections::HashMap, env};
+
+// The variables of a shell, which start out as a copy of the environment of the process. Each
+// shell has its own, so one embedded into a program doesn't change the environment of that
+// program. All of them are passed on to the commands the shell runs, see `export`
+#[derive(Clone, Default)]
+pub struct Variables(HashMap<String, String>);
+
+impl Variables {
+    pub(super) fn from_env() -> Self {
+        // Values which aren't valid UTF-8 couldn't be expanded, so they are converted lossily
+        let vars = env::vars_os().map(|(name, value)| {
+            (
+                name.to_string_lossy().into_owned(),
+                value.to_string_lossy().into_owned(),
+            )
+        });
+
+        Self(vars.collect())
+    }
+
+    pub(super) fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+
+    // Returns the previous value, so it can be put back like for `FOO=bar builtin`
+    pub(super) fn set(
+        &mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Option<String> {
+        self.0.insert(name.into(), value.into())
+    }
+
+    pub(super) fn remove(&mut self, name: &str) -> Option<String> {
+        self.0.remove(name)
+    }
+
+    pub(super) fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
@@ -0,0 +1,63 @@
+mod common;
+
+#[test]
+fn runs_every_piped_line_without_prompts() {
+    let output = common::run("batch-lines", "echo one\necho two\necho three\n");
+
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "one\ntwo\nthree\n");
+    assert_eq!(output.stderr, "");
+}
+
+#[test]
+fn exits_with_the_status_of_the_last_command() {
+    let output = common::run("batch-false", "true\nfalse\n");
+
+    assert_eq!(output.status, 1);
+    assert_eq!(output.stdout, "");
+}
+
+#[test]
+fn exits_with_the_status_given_to_exit() {
+    let output = common::run("batch-exit", "echo before\nexit 3\necho after\n");
+
+    assert_eq!(output.status, 3);
+    assert_eq!(output.stdout, "before\n");
+}
+
+#[test]
+fn exits_at_the_end_of_empty_input() {
+    let output = common::run("batch-empty", "");
+
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "");
+    assert_eq!(output.stderr, "");
+}
+
+#[test]
+fn exits_at_the_end_of_input_without_a_newline() {
+    let output = common::run("batch-unterminated", "echo last");
+
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "last\n");
+    assert_eq!(output.stderr, "");
+}
+
+#[test]
+fn expands_positional_parameters_of_scripts() {
+    let shell = env!("CARGO_BIN_EXE_shell");
+    let output = common::run(
+        "positional",
+        &format!(
+            "printf '%s\\n' 'echo \"$0\" $1 $# ${{10}}' 'for a in \"$@\"; do echo \"[$a]\"; done' 'for a in $*; do echo \"<$a>\"; done' > args.sh\n{0} args.sh one 'two words' 3 4 5 6 7 8 9 ten > all.txt; head -5 all.txt\n{0} args.sh 'a b'\n{0} -c 'echo $0 $1 $#' name x y\n",
+            shell
+        ),
+    );
+
+    assert_eq!(
+        output.stdout,
+        "args.sh one 10 ten\n[one]\n[two words]\n[3]\n[4]\nargs.sh a b 1\n[a b]\n<a>\n<b>\nname x 2\n"
+    );
+    assert_eq!(output.stderr, "");
+    assert_eq!(output.status, 0);
+}
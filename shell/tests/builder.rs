@@ -0,0 +1,105 @@
+use std::{env, fs, io, path::PathBuf};
+
+use shell::Shell;
+
+fn temp_file(name: &str) -> PathBuf {
+    env::temp_dir().join(format!(
+        "shell-builder-test-{}-{}",
+        name,
+        std::process::id()
+    ))
+}
+
+fn output(stdout: &mut Vec<u8>) -> String {
+    String::from_utf8(std::mem::take(stdout)).unwrap()
+}
+
+#[test]
+fn keeps_variables_out_of_the_process_environment() {
+    let mut shell = Shell::builder()
+        .stdin(io::empty())
+        .stdout(Vec::new())
+        .env("SHELL_BUILDER_TEST", "builder")
+        .disable_history()
+        .build();
+
+    shell
+        .eval("echo $SHELL_BUILDER_TEST; export SHELL_BUILDER_EXPORTED=shell")
+        .unwrap();
+    shell.eval("echo $SHELL_BUILDER_EXPORTED").unwrap();
+
+    assert_eq!(output(shell.stdout()), "builder\nshell\n");
+    assert!(env::var_os("SHELL_BUILDER_TEST").is_none());
+    assert!(env::var_os("SHELL_BUILDER_EXPORTED").is_none());
+}
+
+#[test]
+fn passes_variables_to_external_commands() {
+    let file = temp_file("env");
+
+    let mut shell = Shell::builder()
+        .stdin(io::empty())
+        .stdout(Vec::new())
+        .env("SHELL_BUILDER_CHILD", "child")
+        .disable_history()
+        .build();
+
+    let status = shell
+        .eval(&format!(
+            "printenv SHELL_BUILDER_CHILD > {}",
+            file.display()
+        ))
+        .unwrap();
+
+    let contents = fs::read_to_string(&file).unwrap();
+    let _ = fs::remove_file(&file);
+
+    assert_eq!(status, 0);
+    assert_eq!(contents, "child\n");
+}
+
+#[test]
+fn loads_history_from_the_given_file() {
+    let file = temp_file("history");
+    fs::write(&file, "echo saved\n").unwrap();
+
+    let mut shell = Shell::builder()
+        .stdin(io::empty())
+        .stdout(Vec::new())
+        .history_file(&file)
+        .build();
+
+    shell.eval("history").unwrap();
+    let _ = fs::remove_file(&file);
+
+    assert_eq!(output(shell.stdout()), "    1  echo saved\n");
+}
+
+#[test]
+fn starts_without_history_when_disabled() {
+    let mut shell = Shell::builder()
+        .stdin(io::empty())
+        .stdout(Vec::new())
+        .disable_history()
+        .build();
+
+    shell.eval("history").unwrap();
+
+    assert_eq!(output(shell.stdout()), "");
+}
+
+#[test]
+fn captures_builtins_and_errors_of_the_shell() {
+    let mut shell = Shell::builder()
+        .stdin(io::empty())
+        .stdout(Vec::new())
+        .stderr(Vec::new())
+        .disable_history()
+        .build();
+
+    let status = shell.eval("echo out; cd /nonexistent").unwrap();
+
+    assert_eq!(status, 1);
+    assert_eq!(output(shell.stdout()), "out\n");
+    assert!(output(shell.stderr()).contains("/nonexistent"));
+}
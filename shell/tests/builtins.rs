@@ -0,0 +1,94 @@
+mod common;
+
+#[test]
+fn unsets_variables() {
+    let output = common::run(
+        "unset",
+        "export VAR=set; echo \"[$VAR]\"; unset VAR; echo \"[$VAR]\"\nprintenv VAR; echo $?\nunset NEVER_SET; echo $?\nunset; echo $?\n",
+    );
+
+    assert_eq!(output.stdout, "[set]\n[]\n1\n0\n1\n");
+    assert_eq!(output.stderr, "arguments are required\n");
+    assert_eq!(output.status, 0);
+}
+
+#[test]
+fn keeps_pwd_and_oldpwd_in_sync() {
+    let output = common::run(
+        "pwd",
+        "cd /; printenv PWD\ncd /tmp; printenv PWD OLDPWD\ncd /missing; printenv PWD\n",
+    );
+
+    assert_eq!(output.stdout, "/\n/tmp\n/\n/tmp\n");
+    assert_eq!(output.stderr, "cd: /missing: No such file or directory\n");
+    assert_eq!(output.status, 0);
+}
+
+#[test]
+fn searches_cdpath_for_relative_dirs() {
+    let output = common::run(
+        "cdpath",
+        "mkdir -p base/sub here/sub; CDPATH=$HOME/base\ncd sub; pwd\ncd ../../here; cd ./sub; pwd\ncd ..; cd sub >/dev/null; pwd\ncd /; cd missing; echo $?\nCDPATH=:$HOME/base; cd $HOME/here; cd sub; pwd\n",
+    );
+
+    let dir = std::env::temp_dir().join(format!("shell-test-cdpath-{}", std::process::id()));
+    let dir = dir.display();
+
+    // `cd` prints where it went when it was found through `$CDPATH`, an empty entry stands for
+    // the current directory
+    assert_eq!(
+        output.stdout,
+        format!(
+            "{0}/base/sub\n{0}/base/sub\n{0}/here/sub\n{0}/base/sub\n1\n{0}/here/sub\n",
+            dir
+        )
+    );
+    assert_eq!(output.stderr, "cd: /missing: No such file or directory\n");
+    assert_eq!(output.status, 0);
+}
+
+#[test]
+fn prints_logical_and_physical_dirs() {
+    let output = common::run(
+        "pwd-symlink",
+        "mkdir real; ln -s real link\ncd link; pwd; pwd -L; pwd -P\ncd ..; pwd\n",
+    );
+
+    let dir = std::env::temp_dir().join(format!("shell-test-pwd-symlink-{}", std::process::id()));
+    let physical = dir.canonicalize().unwrap_or_else(|_| dir.clone());
+
+    assert_eq!(
+        output.stdout,
+        format!(
+            "{0}/link\n{0}/link\n{1}/real\n{0}\n",
+            dir.display(),
+            physical.display()
+        )
+    );
+    assert_eq!(output.stderr, "");
+    assert_eq!(output.status, 0);
+}
+
+#[test]
+fn runs_the_exit_trap_on_exit() {
+    let output = common::run(
+        "trap-exit",
+        "trap 'echo bye $?' EXIT\necho running\nexit 4\necho never\n",
+    );
+
+    assert_eq!(output.stdout, "running\nbye 4\n");
+    assert_eq!(output.stderr, "");
+    assert_eq!(output.status, 4);
+}
+
+#[test]
+fn runs_traps_on_signals_until_reset() {
+    let output = common::run(
+        "trap-signal",
+        "trap 'echo caught' INT\nsh -c 'kill -INT $PPID'\necho after\ntrap 'echo bye' EXIT; trap - EXIT\necho end\n",
+    );
+
+    assert_eq!(output.stdout, "caught\nafter\nend\n");
+    assert_eq!(output.stderr, "");
+    assert_eq!(output.status, 0);
+}
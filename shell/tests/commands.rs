@@ -0,0 +1,84 @@
+mod common;
+
+#[test]
+fn keeps_exit_codes_of_external_commands() {
+    let output = common::run(
+        "exit-codes",
+        "sh -c 'exit 42'; echo $?\nsh -c 'exit 3' && echo no || echo $?\ntrue; echo $?\nno-such-command; echo $?\n",
+    );
+
+    assert_eq!(output.stdout, "42\n3\n0\n127\n");
+    assert_eq!(output.stderr, "no-such-command: not found\n");
+    assert_eq!(output.status, 0);
+}
+
+#[test]
+fn adds_128_to_signals_killing_commands() {
+    let output = common::run(
+        "signals",
+        "sh -c 'kill -9 $$'; echo $?\nsh -c 'kill -15 $$'; echo $?\n",
+    );
+
+    assert_eq!(output.stdout, "137\n143\n");
+    assert_eq!(output.status, 0);
+}
+
+#[test]
+fn tells_missing_commands_from_ones_without_permission() {
+    let output = common::run(
+        "not-executable",
+        "mkdir bin; echo 'echo tool' > bin/tool; PATH=$HOME/bin:$PATH\ntool; echo $?\nno-such-tool; echo $?\nchmod +x bin/tool; tool; echo $?\n",
+    );
+
+    assert_eq!(output.stdout, "126\n127\ntool\n0\n");
+    assert_eq!(
+        output.stderr,
+        "tool: Permission denied\nno-such-tool: not found\n"
+    );
+    assert_eq!(output.status, 0);
+}
+
+#[test]
+fn runs_paths_without_searching_path() {
+    let output = common::run(
+        "paths",
+        "printf '#!/bin/sh\\necho script $1\\n' > script; chmod +x script\n./script a; $HOME/script b\ntype ./script ./missing; echo $?\nmkdir -p bin/sub; cp script bin/sub/tool; PATH=$HOME/bin:$PATH\nsub/tool; echo $?\n",
+    );
+
+    assert_eq!(
+        output.stdout,
+        "script a\nscript b\n./script is ./script\n1\n127\n"
+    );
+    assert_eq!(output.stderr, "./missing: not found\nsub/tool: not found\n");
+    assert_eq!(output.status, 0);
+}
+
+#[test]
+fn runs_scripts_without_shebang_with_sh() {
+    let output = common::run(
+        "no-shebang",
+        "printf 'echo plain $1\\nexit 5\\n' > plain; chmod +x plain\n./plain arg; echo $?\nPATH=$HOME:$PATH; plain other; echo $?\n",
+    );
+
+    assert_eq!(output.stdout, "plain arg\n5\nplain other\n5\n");
+    assert_eq!(output.stderr, "");
+    assert_eq!(output.status, 0);
+}
+
+#[test]
+fn finds_only_executable_files_in_path() {
+    let output = common::run(
+        "executable",
+        "mkdir -p bin/tool bin2; echo 'echo data' > bin/data; printf '#!/bin/sh\\necho tool\\n' > bin2/tool; chmod +x bin2/tool\nPATH=$HOME/bin:$HOME/bin2\ntype data; echo $?\ntype tool; tool\n",
+    );
+
+    let dir = std::env::temp_dir().join(format!("shell-test-executable-{}", std::process::id()));
+
+    // Neither the file without permissions nor the directory of the same name count
+    assert_eq!(
+        output.stdout,
+        format!("1\ntool is {}/bin2/tool\ntool\n", dir.display())
+    );
+    assert_eq!(output.stderr, "data: not found\n");
+    assert_eq!(output.status, 0);
+}
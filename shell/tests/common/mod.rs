@@ -0,0 +1,51 @@
+use std::{
+    env, fs,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+// What a run of the shell printed, along with its exit status
+pub struct Output {
+    pub status: i32,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+// A directory of its own for each test, which is the home and working directory of the shell
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("shell-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// Run the shell binary with `input` piped to it as the commands to run
+pub fn run(name: &str, input: &str) -> Output {
+    let dir = temp_dir(name);
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_shell"))
+        .current_dir(&dir)
+        .env("HOME", &dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    Output {
+        status: output.status.code().unwrap_or(-1),
+        stdout: String::from_utf8(output.stdout).unwrap(),
+        stderr: String::from_utf8(output.stderr).unwrap(),
+    }
+}
@@ -0,0 +1,103 @@
+mod common;
+
+#[test]
+fn runs_branches_of_if_by_test() {
+    let output = common::run(
+        "if",
+        r#"x=5
+if [ $x -gt 3 ]; then echo big; else echo small; fi
+if [ $x -lt 3 ]; then echo small; elif test $x = 5; then echo five; fi
+if [ -z "" ] && [ -n "$x" ]; then echo both; fi
+if [ $x -eq 1 ]; then echo one; fi; echo $?
+if [ $x -eq 5 ]
+then
+    if [ -d / ]; then echo nested; fi
+fi
+if [ $x -eq 5; then echo missing; fi; echo $?
+"#,
+    );
+
+    assert_eq!(output.stdout, "big\nfive\nboth\n0\nnested\n0\n");
+    assert_eq!(output.stderr, "[: missing `]'\n");
+    assert_eq!(output.status, 0);
+}
+
+#[test]
+fn redirects_and_pipes_if() {
+    let output = common::run(
+        "if-redirect",
+        "if [ 1 = 1 ]; then echo a; echo b; fi > out\ncat out\nif true; then echo a; echo b; fi | wc -l\necho a | if read x; then echo \"read $x\"; fi\n",
+    );
+
+    assert_eq!(output.stdout, "a\nb\n2\nread a\n");
+    assert_eq!(output.status, 0);
+}
+
+#[test]
+fn rejects_words_after_fi() {
+    let output = common::run(
+        "if-syntax",
+        "if true; then :; fi x\nif true; then :; fi |\n",
+    );
+
+    assert_eq!(
+        output.stderr,
+        "syntax error near unexpected token `x`\nsyntax error near unexpected token `|`\n"
+    );
+    assert_eq!(output.stdout, "");
+    assert_eq!(output.status, 2);
+}
+
+#[test]
+fn iterates_for_over_expanded_words() {
+    let output = common::run(
+        "for",
+        r#"touch b.txt a.txt c.log
+for f in *.txt; do echo $f; done
+x="1 2"
+for i in $(echo $x) "$x" none*.md; do echo "<$i>"; done
+for i in 1 2 3 4; do [ $i = 2 ] && continue; [ $i = 4 ] && break; echo $i; done
+for i in a b
+do
+    for j in 1 2; do [ $j = 2 ] && continue 2; echo $i$j; done
+done
+for i in x y; do echo $i; done | wc -l
+"#,
+    );
+
+    assert_eq!(
+        output.stdout,
+        "a.txt\nb.txt\n<1>\n<2>\n<1 2>\n<none*.md>\n1\n3\na1\nb1\n2\n"
+    );
+    assert_eq!(output.status, 0);
+}
+
+#[test]
+fn loops_while_and_until_with_counters() {
+    let output = common::run(
+        "while",
+        r#"n=0
+while [ $n -lt 3 ]; do n=$((n + 1)); echo $n; done
+until [ $n -eq 0 ]; do n=$((n - 1)); done; echo $n
+while true; do n=$((n + 1)); [ $n -lt 5 ] && continue; break; done; echo $n
+printf 'a\nb\n' > lines
+while read line; do echo "<$line>"; done < lines
+printf 'x\ny\n' | while read line; do echo $line; done | sort -r
+"#,
+    );
+
+    assert_eq!(output.stdout, "1\n2\n3\n0\n5\n<a>\n<b>\ny\nx\n");
+    assert_eq!(output.status, 0);
+}
+
+#[test]
+fn defines_and_calls_functions() {
+    let output = common::run(
+        "functions",
+        "greet() { echo \"hi $1\"; }\ngreet world\nf() { echo $# \"$@\"; }; f a 'b c'; echo \"[$1]\"\nr() { return 3; echo never; }; r; echo $?\nls() { echo function; }; ls; command ls -d /\n",
+    );
+
+    assert_eq!(output.stdout, "hi world\n2 a b c\n[]\n3\nfunction\n/\n");
+    assert_eq!(output.stderr, "");
+    assert_eq!(output.status, 0);
+}
@@ -0,0 +1,91 @@
+mod common;
+
+#[test]
+fn exits_on_the_first_failure_with_errexit() {
+    let output = common::run(
+        "errexit",
+        "set -e\necho before\nsh -c 'exit 4'\necho after\n",
+    );
+
+    assert_eq!(output.stdout, "before\n");
+    assert_eq!(output.stderr, "");
+    assert_eq!(output.status, 4);
+}
+
+#[test]
+fn keeps_running_failed_conditions_with_errexit() {
+    let output = common::run(
+        "errexit-conditions",
+        "set -e\nif false; then echo no; fi\nfalse || echo or\nfalse && echo and\nwhile false; do :; done\nuntil true; do :; done\necho survived\nset +e\nfalse\necho disabled\n",
+    );
+
+    assert_eq!(output.stdout, "or\nsurvived\ndisabled\n");
+    assert_eq!(output.stderr, "");
+    assert_eq!(output.status, 0);
+}
+
+#[test]
+fn traces_expanded_commands_with_xtrace() {
+    let output = common::run(
+        "xtrace",
+        "set -x\nx=world\necho hello $x\nprintf '%s\\n' \"a b\"\nset +x\necho untraced\n",
+    );
+
+    assert_eq!(output.stdout, "hello world\na b\nuntraced\n");
+    assert_eq!(
+        output.stderr,
+        "+ x=world\n+ echo hello world\n+ printf '%s\\n' 'a b'\n+ set +x\n"
+    );
+    assert_eq!(output.status, 0);
+}
+
+#[test]
+fn fails_on_unset_variables_with_nounset() {
+    let output = common::run(
+        "nounset",
+        "set -u\necho $nope; echo $?\nset=value; echo $set\nset +u\necho \"[$nope]\"\n",
+    );
+
+    assert_eq!(output.stdout, "1\nvalue\n[]\n");
+    assert_eq!(output.stderr, "nope: unbound variable\n");
+    assert_eq!(output.status, 0);
+}
+
+#[test]
+fn allows_defaults_for_unset_variables_with_nounset() {
+    let output = common::run(
+        "nounset-defaults",
+        "set -u\necho ${nope:-default} \"[${nope-}]\" ${nope:+alt}end\nset -e\necho $nope\necho after\n",
+    );
+
+    assert_eq!(output.stdout, "default [] end\n");
+    assert_eq!(output.stderr, "nope: unbound variable\n");
+    assert_eq!(output.status, 1);
+}
+
+#[test]
+fn refuses_to_overwrite_files_with_noclobber() {
+    let output = common::run(
+        "noclobber",
+        "set -C\necho one > f; echo two > f; echo $?; cat f\necho three >> f; cat f\nset -o noclobber\necho four > f; echo $?\nset +C\necho five > f; cat f\n",
+    );
+
+    assert_eq!(output.stdout, "1\none\none\nthree\n1\nfive\n");
+    assert_eq!(
+        output.stderr,
+        "f: cannot overwrite existing file\nf: cannot overwrite existing file\n"
+    );
+    assert_eq!(output.status, 0);
+}
+
+#[test]
+fn forces_overwriting_files_with_noclobber() {
+    let output = common::run(
+        "noclobber-force",
+        "set -C\necho one > f\necho two >| f; cat f\necho err 2>| f >&2; cat f\n",
+    );
+
+    assert_eq!(output.stdout, "two\nerr\n");
+    assert_eq!(output.stderr, "");
+    assert_eq!(output.status, 0);
+}
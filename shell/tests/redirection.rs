@@ -0,0 +1,160 @@
+use std::{fs, os::unix::fs::PermissionsExt, process::Command};
+
+mod common;
+
+#[test]
+fn fails_builtins_writing_to_closed_descriptors() {
+    let output = common::run(
+        "closed",
+        "echo hi >&-; echo $? >&2\npwd 1>&-; echo $? >&2\necho ok\n",
+    );
+
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "ok\n");
+    assert_eq!(
+        output.stderr,
+        "echo: write error: Bad file descriptor\n1\npwd: write error: Bad file descriptor\n1\n"
+    );
+}
+
+#[test]
+fn ignores_errors_written_to_closed_stderr() {
+    let output = common::run("closed-stderr", "cd missing 2>&-; echo $?\n");
+
+    assert_eq!(output.stdout, "1\n");
+    assert_eq!(output.stderr, "");
+    assert_eq!(output.status, 0);
+}
+
+#[test]
+fn redirects_output_of_builtins() {
+    let output = common::run(
+        "builtins",
+        "pwd > cwd.txt; type echo > out.txt; type missing 2> err.txt\nread cwd < cwd.txt; echo ${cwd##*/}\ncat out.txt err.txt\n",
+    );
+
+    assert_eq!(
+        output.stdout,
+        format!(
+            "shell-test-builtins-{}\necho is a shell builtin\nmissing: not found\n",
+            std::process::id()
+        )
+    );
+    assert_eq!(output.stderr, "");
+    assert_eq!(output.status, 0);
+}
+
+#[test]
+fn fails_redirected_builtins_on_write_errors() {
+    let output = common::run("full", "type echo > /dev/full; echo $?\n");
+
+    assert_eq!(output.stdout, "1\n");
+    assert_eq!(
+        output.stderr,
+        "type: write error: No space left on device\n"
+    );
+    assert_eq!(output.status, 0);
+}
+
+#[test]
+fn truncates_files_redirected_to() {
+    let output = common::run(
+        "truncate",
+        "echo first > out.txt; echo second > out.txt; cat out.txt\nls missing 2> err.txt; echo third 2> err.txt; cat err.txt\necho a >> log.txt; echo b >> log.txt; cat log.txt\n",
+    );
+
+    assert_eq!(output.stdout, "second\nthird\na\nb\n");
+    assert_eq!(output.stderr, "");
+    assert_eq!(output.status, 0);
+}
+
+#[test]
+fn reads_stdin_from_files() {
+    let output = common::run(
+        "input",
+        "printf 'one\\ntwo\\n' > in.txt\ncat < in.txt; wc -l 0< in.txt\ncat < missing.txt; echo $?\n",
+    );
+
+    assert_eq!(output.stdout, "one\ntwo\n2\n1\n");
+    assert_eq!(output.stderr, "missing.txt: No such file or directory\n");
+    assert_eq!(output.status, 0);
+}
+
+#[test]
+fn duplicates_descriptors_in_order() {
+    let output = common::run(
+        "duplicate",
+        "sh -c 'echo out; echo err >&2' > both.txt 2>&1; cat both.txt\nsh -c 'echo out; echo err >&2' 2>&1 > out.txt; cat out.txt\nsh -c 'echo moved' 1>&2\n",
+    );
+
+    assert_eq!(output.stdout, "out\nerr\nerr\nout\n");
+    assert_eq!(output.stderr, "moved\n");
+    assert_eq!(output.status, 0);
+}
+
+#[test]
+fn feeds_here_strings_to_stdin() {
+    let output = common::run(
+        "here-string",
+        "cat <<< hello\nx=\"a b\"; cat <<< \"$x c\"\ngrep foo <<< \"$(printf 'foo1\\nbar\\nfoo2')\"\ncat <<< one | tr o 0\nread v <<< word; echo $v\n",
+    );
+
+    assert_eq!(output.stdout, "hello\na b c\nfoo1\nfoo2\n0ne\nword\n");
+    assert_eq!(output.stderr, "");
+    assert_eq!(output.status, 0);
+}
+
+#[test]
+fn creates_files_with_permissions_of_the_umask() {
+    let dir = common::temp_dir("umask");
+
+    // The shell has no `umask` builtin, so it is started by `sh` under a known umask
+    let run = |umask: &str, input: &str| {
+        let script = format!("umask {umask}; exec \"$0\" -c '{input}'");
+        let status = Command::new("sh")
+            .args(["-c", &script, env!("CARGO_BIN_EXE_shell")])
+            .current_dir(&dir)
+            .status()
+            .unwrap();
+
+        assert!(status.success());
+    };
+
+    run("022", "echo a > a; echo b >> b");
+    run("077", "echo c > c; echo d >> d");
+
+    let modes: Vec<_> = ["a", "b", "c", "d"]
+        .iter()
+        .map(|name| fs::metadata(dir.join(name)).unwrap().permissions().mode() & 0o777)
+        .collect();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(modes, [0o644, 0o644, 0o600, 0o600]);
+}
+
+#[test]
+fn refuses_redirecting_to_directories() {
+    let output = common::run(
+        "directory",
+        "mkdir dir\necho hi > dir; echo $?\nsh -c 'echo ran' > dir\necho hi >> dir 2>/dev/null\ncd dir 2> dir; echo $?\n",
+    );
+
+    assert_eq!(output.stdout, "1\n1\n");
+    assert_eq!(
+        output.stderr,
+        "dir: Is a directory\ndir: Is a directory\ndir: Is a directory\ndir: Is a directory\n"
+    );
+    assert_eq!(output.status, 0);
+}
+
+#[test]
+fn discards_output_redirected_to_dev_null() {
+    let output = common::run(
+        "dev-null",
+        "sh -c 'echo out; echo err >&2' > /dev/null 2> /dev/null; echo $?\nls missing > /dev/null 2>> /dev/null; echo $?\necho builtin > /dev/null 2>&1; echo done\n",
+    );
+
+    assert_eq!(output.stdout, "0\n2\ndone\n");
+    assert_eq!(output.stderr, "");
+    assert_eq!(output.status, 0);
+}
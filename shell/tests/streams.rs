@@ -0,0 +1,66 @@
+use std::io::Cursor;
+
+use shell::Shell;
+
+// Run the commands of `input` read from memory, returning the status along with what was
+// written to stdout and stderr
+fn run(input: &str) -> (i32, String, String) {
+    let mut shell = Shell::builder()
+        .stdin(Cursor::new(input.to_owned()))
+        .stdout(Vec::new())
+        .stderr(Vec::new())
+        .disable_history()
+        .build();
+
+    let status = shell.run().unwrap();
+    let stdout = String::from_utf8(std::mem::take(shell.stdout())).unwrap();
+    let stderr = String::from_utf8(std::mem::take(shell.stderr())).unwrap();
+
+    (status, stdout, stderr)
+}
+
+#[test]
+fn echoes_into_the_output() {
+    let (status, stdout, stderr) = run("echo hello world\necho -n again\n");
+
+    assert_eq!(status, 0);
+    assert_eq!(stdout, "hello world\nagain");
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn prints_the_working_directory() {
+    let (status, stdout, stderr) = run("cd /\npwd\n");
+
+    assert_eq!(status, 0);
+    assert_eq!(stdout, "/\n");
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn reports_errors_into_the_error_output() {
+    let (status, stdout, stderr) = run("echo before\nno-such-command-here\n");
+
+    assert_eq!(status, 127);
+    assert_eq!(stdout, "before\n");
+    assert!(stderr.contains("no-such-command-here: not found"));
+}
+
+#[test]
+fn continues_lines_ending_with_a_backslash() {
+    // The backslash and the newline are both removed, joining the words they split
+    let (status, stdout, stderr) = run("echo a\\\nb\necho one \\\n  two\\\n\\\nthree\n");
+
+    assert_eq!(status, 0);
+    assert_eq!(stdout, "ab\none twothree\n");
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn keeps_backslashes_in_single_quotes() {
+    let (status, stdout, stderr) = run("echo 'a\\\nb' \"c\\\nd\"\n");
+
+    assert_eq!(status, 0);
+    assert_eq!(stdout, "a\\\nb cd\n");
+    assert_eq!(stderr, "");
+}